@@ -0,0 +1,56 @@
+/*
+ * The C API of dimmer, for programs that link against libdimmer (built with
+ * `cargo rustc --lib --release --features ffi --crate-type cdylib`) to
+ * smoothly transition backlights without spawning `dimmer`.
+ *
+ * Functions returning an int return 0 on success and -1 on failure, after
+ * which dimmer_last_error() tells what went wrong.
+ */
+#ifndef DIMMER_H
+#define DIMMER_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/*
+ * Called by dimmer_list_devices() for every device, with its name, current
+ * and maximum brightness, and the data dimmer_list_devices() was given. The
+ * name is only valid during the call.
+ */
+typedef void (*dimmer_list_callback)(const char *name, uint64_t current,
+                                     uint64_t max, void *data);
+
+/* Call callback for every screen and keyboard backlight, passing data along. */
+int dimmer_list_devices(dimmer_list_callback callback, void *data);
+
+/*
+ * Transition the device called name, or the first backlight if name is NULL,
+ * to target over duration_ms milliseconds. The target is anything
+ * `dimmer set` takes, like "30%", "+10%" or "max". Blocks until the
+ * transition is done, or cancelled by dimmer_cancel() from another thread,
+ * which isn't a failure.
+ */
+int dimmer_transition(const char *name, const char *target,
+                      uint64_t duration_ms);
+
+/*
+ * Stop all transitions running in this process where they are. Returns how
+ * many there were.
+ */
+int dimmer_cancel(void);
+
+/*
+ * What went wrong in the last call on this thread that failed, or NULL if
+ * none did. The string stays valid until the next call that fails on this
+ * thread.
+ */
+const char *dimmer_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
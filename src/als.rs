@@ -0,0 +1,194 @@
+use anyhow::{bail, Context, Result};
+use glob::glob;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const IIO_GLOB: &str = "/sys/bus/iio/devices/*";
+
+/// How often we read the sensor.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// An ambient light sensor, as exposed by the kernel's IIO subsystem.
+#[derive(Debug)]
+pub struct Sensor {
+    input: PathBuf,
+    scale: f64,
+    offset: f64,
+}
+
+impl Sensor {
+    /// The first ambient light sensor found. Some report the illuminance in lux directly, others
+    /// a raw value we have to scale ourselves.
+    pub fn find() -> Result<Sensor> {
+        for dir in glob(IIO_GLOB)?.flatten() {
+            let input = dir.join("in_illuminance_input");
+            if input.exists() {
+                return Ok(Sensor {
+                    input,
+                    scale: 1.0,
+                    offset: 0.0,
+                });
+            }
+            let input = dir.join("in_illuminance_raw");
+            if input.exists() {
+                return Ok(Sensor {
+                    input,
+                    scale: read(&dir.join("in_illuminance_scale")).unwrap_or(1.0),
+                    offset: read(&dir.join("in_illuminance_offset")).unwrap_or(0.0),
+                });
+            }
+        }
+        bail!("Failed to find an ambient light sensor in {IIO_GLOB}")
+    }
+
+    /// The illuminance in lux.
+    pub fn lux(&self) -> Result<f64> {
+        let raw = read(&self.input)
+            .with_context(|| format!("Failed to read {}", self.input.display()))?;
+        Ok((raw + self.offset) * self.scale)
+    }
+}
+
+fn read(path: &Path) -> Result<f64> {
+    Ok(std::fs::read_to_string(path)?.trim().parse()?)
+}
+
+/// The percentage of the maximum brightness for an illuminance of `lux`. Our eyes perceive light
+/// logarithmically, so the brightness follows the logarithm of the illuminance, up to the maximum
+/// at `max_lux`.
+pub fn percent(lux: f64, max_lux: f64) -> f64 {
+    let percent = 100.0 * (1.0 + lux.max(0.0)).log10() / (1.0 + max_lux).log10();
+    percent.clamp(0.0, 100.0)
+}
+
+/// Breakpoints mapping an illuminance in lux to a percentage of the maximum brightness, between
+/// which we interpolate linearly.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "Vec<(f64, f64)>")]
+pub struct Curve(Vec<(f64, f64)>);
+
+impl TryFrom<Vec<(f64, f64)>> for Curve {
+    type Error = String;
+
+    fn try_from(points: Vec<(f64, f64)>) -> Result<Self, Self::Error> {
+        if points.is_empty() {
+            return Err("the curve needs at least one point".to_string());
+        }
+        if points.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+            return Err("the illuminance of the curve's points has to go up".to_string());
+        }
+        if points
+            .iter()
+            .any(|(_lux, percent)| !(0.0..=100.0).contains(percent))
+        {
+            return Err("the curve's percentages have to be between 0 and 100".to_string());
+        }
+        Ok(Curve(points))
+    }
+}
+
+impl Curve {
+    /// The percentage of the maximum brightness for an illuminance of `lux`. Below the first and
+    /// above the last point, we stick to their percentage.
+    pub fn percent(&self, lux: f64) -> f64 {
+        let points = &self.0;
+        let after = points.partition_point(|(point, _percent)| *point <= lux);
+        match (
+            after.checked_sub(1).map(|before| points[before]),
+            points.get(after).copied(),
+        ) {
+            (Some((from_lux, from)), Some((to_lux, to))) => {
+                from + (to - from) * (lux - from_lux) / (to_lux - from_lux)
+            }
+            (Some((_lux, percent)), None) | (None, Some((_lux, percent))) => percent,
+            (None, None) => 100.0,
+        }
+    }
+}
+
+/// Smooth `lux` measured a second after the `last` smoothed illuminance exponentially, so it
+/// takes about `window` to follow a change.
+pub fn smooth(last: f64, lux: f64, window: Duration) -> f64 {
+    let factor = 1.0 - (-POLL_INTERVAL.as_secs_f64() / window.as_secs_f64()).exp();
+    last + (lux - last) * factor
+}
+
+/// Whether the brightness for the ambient light changed by at least `hysteresis` percent, from
+/// the `last` percentage we followed if any to `percent`.
+pub fn is_noticeable(last: Option<f64>, percent: f64, hysteresis: f64) -> bool {
+    !last.is_some_and(|last| (percent - last).abs() < hysteresis)
+}
+
+/// Call `measured` with the illuminance every second, from a thread of its own.
+pub fn watch<F>(sensor: Sensor, mut measured: F)
+where
+    F: FnMut(f64) + Send + 'static,
+{
+    std::thread::spawn(move || loop {
+        match sensor.lux() {
+            Ok(lux) => measured(lux),
+            Err(err) => tracing::error!("{err:#}"),
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_curve() {
+        let curve = Curve::try_from(vec![(10.0, 5.0), (100.0, 50.0), (1000.0, 100.0)]).unwrap();
+        assert_eq!(curve.percent(0.0), 5.0);
+        assert_eq!(curve.percent(55.0), 27.5);
+        assert_eq!(curve.percent(100.0), 50.0);
+        assert_eq!(curve.percent(5000.0), 100.0);
+        assert_eq!(
+            Curve::try_from(vec![(50.0, 30.0)]).unwrap().percent(0.0),
+            30.0
+        );
+    }
+
+    #[test]
+    fn smooths_changes_of_the_illuminance() {
+        let window = Duration::from_secs(10);
+        assert_eq!(smooth(100.0, 100.0, window), 100.0);
+        // A change is followed gradually, by the same share every second.
+        let once = smooth(0.0, 100.0, window);
+        assert!(0.0 < once && once < 100.0, "{once}");
+        assert!(
+            (smooth(once, 100.0, window) - (once + (100.0 - once) * once / 100.0)).abs() < 1e-9
+        );
+        // About two thirds of the way after the window.
+        let lux = (0..10).fold(0.0, |lux, _| smooth(lux, 100.0, window));
+        assert!((lux - 63.2).abs() < 0.1, "{lux}");
+        // Without a window, we follow right away.
+        assert_eq!(smooth(0.0, 100.0, Duration::ZERO), 100.0);
+    }
+
+    #[test]
+    fn follows_only_noticeable_changes() {
+        assert!(is_noticeable(None, 50.0, 5.0));
+        assert!(!is_noticeable(Some(50.0), 54.0, 5.0));
+        assert!(!is_noticeable(Some(50.0), 46.0, 5.0));
+        assert!(is_noticeable(Some(50.0), 55.0, 5.0));
+        assert!(is_noticeable(Some(50.0), 40.0, 5.0));
+        assert!(is_noticeable(Some(50.0), 50.5, 0.0));
+    }
+
+    #[test]
+    fn rejects_invalid_curves() {
+        for points in [
+            vec![],
+            vec![(10.0, 5.0), (10.0, 50.0)],
+            vec![(100.0, 5.0), (10.0, 50.0)],
+            vec![(10.0, -1.0)],
+            vec![(10.0, 5.0), (100.0, 101.0)],
+            vec![(10.0, f64::NAN)],
+        ] {
+            assert!(Curve::try_from(points.clone()).is_err(), "{points:?}");
+        }
+    }
+}
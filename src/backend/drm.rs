@@ -0,0 +1,166 @@
+use anyhow::{bail, Context, Result};
+use drm::control::{connector, crtc, Device as ControlDevice};
+use glob::glob;
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::{AsFd, BorrowedFd};
+use std::path::PathBuf;
+
+use super::{gamma_brightness, gamma_ramp, Backend, GAMMA_MAX};
+use crate::{Brightness, DimmerError};
+
+const DRI_CARDS: &str = "/dev/dri/card*";
+
+/// Backend dimming a display in software by scaling the gamma LUT of its CRTC through DRM/KMS
+/// directly, for bare framebuffer and kiosk systems that don't run a compositor.
+///
+/// Setting the gamma requires being the DRM master, so this doesn't work while a compositor or
+/// X server has the display.
+pub struct Drm {
+    card: Card,
+    path: PathBuf,
+    name: String,
+    crtc: crtc::Handle,
+    gamma_length: usize,
+}
+
+struct Card(File);
+
+impl AsFd for Card {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl drm::Device for Card {}
+impl ControlDevice for Card {}
+
+impl Drm {
+    /// Open the connector called `name` (e.g. "eDP-1"), or the first connected one if no name is
+    /// given.
+    pub fn open(name: Option<&str>) -> Result<Drm> {
+        for (path, card) in cards()? {
+            for (connector_name, crtc) in connectors(&card)? {
+                if name.is_some_and(|name| name != connector_name) {
+                    continue;
+                }
+
+                // We can't set the gamma unless we're master, but leave reporting that to the
+                // first write, as reading works fine without.
+                let _ = drm::Device::acquire_master_lock(&card);
+                let gamma_length = card
+                    .get_crtc(crtc)
+                    .with_context(|| format!("Failed to get CRTC of {connector_name}"))?
+                    .gamma_length() as usize;
+                return Ok(Drm {
+                    card,
+                    path,
+                    name: connector_name,
+                    crtc,
+                    gamma_length,
+                });
+            }
+        }
+        match name {
+            Some(name) => bail!(DimmerError::DeviceNotFound(format!(
+                "connected DRM connector {name}"
+            ))),
+            None => bail!(DimmerError::DeviceNotFound(
+                "a connected DRM connector".to_string()
+            )),
+        }
+    }
+
+    /// The names of all connected connectors that are driven by a CRTC.
+    pub fn outputs() -> Result<Vec<String>> {
+        let mut outputs = Vec::new();
+        for (_path, card) in cards()? {
+            outputs.extend(connectors(&card)?.into_iter().map(|(name, _crtc)| name));
+        }
+        Ok(outputs)
+    }
+
+    pub fn output_name(&self) -> &str {
+        &self.name
+    }
+}
+
+fn cards() -> Result<Vec<(PathBuf, Card)>> {
+    let mut cards = Vec::new();
+    for path in glob(DRI_CARDS).with_context(|| format!("Failed to glob {DRI_CARDS}"))? {
+        let path = path.with_context(|| format!("Glob error trying to match {DRI_CARDS}"))?;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        cards.push((path, Card(file)));
+    }
+    Ok(cards)
+}
+
+fn connectors(card: &Card) -> Result<Vec<(String, crtc::Handle)>> {
+    let resources = card
+        .resource_handles()
+        .context("Failed to get DRM resources, is this a KMS device?")?;
+
+    let mut connectors = Vec::new();
+    for handle in resources.connectors() {
+        let info = card.get_connector(*handle, false)?;
+        if info.state() != connector::State::Connected {
+            continue;
+        }
+        let crtc = info
+            .current_encoder()
+            .and_then(|encoder| card.get_encoder(encoder).ok())
+            .and_then(|encoder| encoder.crtc());
+        if let Some(crtc) = crtc {
+            let name = format!("{}-{}", info.interface().as_str(), info.interface_id());
+            connectors.push((name, crtc));
+        }
+    }
+    Ok(connectors)
+}
+
+impl std::fmt::Debug for Drm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Drm")
+            .field("card", &self.path)
+            .field("connector", &self.name)
+            .finish()
+    }
+}
+
+impl Backend for Drm {
+    fn class(&self) -> &str {
+        "drm"
+    }
+
+    /// The gamma persists after we close the card, so we read it back from the top of the ramp.
+    fn current(&self) -> Result<Brightness> {
+        let mut red = vec![0; self.gamma_length];
+        let mut green = vec![0; self.gamma_length];
+        let mut blue = vec![0; self.gamma_length];
+        self.card
+            .get_gamma(self.crtc, &mut red, &mut green, &mut blue)
+            .context("Failed to get gamma")?;
+        let top = red.iter().chain(&green).chain(&blue).max();
+        Ok(gamma_brightness(top.copied()))
+    }
+
+    fn max(&self) -> Result<Brightness> {
+        Ok(GAMMA_MAX)
+    }
+
+    fn set(&mut self, brightness: Brightness) -> Result<()> {
+        let ramp = gamma_ramp(self.gamma_length, brightness);
+        self.card
+            .set_gamma(self.crtc, &ramp, &ramp, &ramp)
+            .with_context(|| {
+                format!(
+                    "Failed to set gamma on {}, is a compositor or X server running?",
+                    self.path.display()
+                )
+            })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+use anyhow::{Context, Result};
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+
+use super::Backend;
+use crate::{Brightness, DimmerError};
+
+/// Where backlight(9) puts its devices.
+const DEV_BACKLIGHT: &str = "/dev/backlight";
+
+/// The brightness at full backlight. Both backlight(9) and acpi_video(4) take a percentage.
+const MAX: Brightness = Brightness(100);
+
+/// The most brightness levels backlight(9) reports, from `sys/backlight.h`.
+const MAX_LEVELS: usize = 100;
+
+/// The most displays acpi_video(4) numbers that we look for.
+const MAX_LCDS: usize = 8;
+
+/// The brightness of a backlight, as `sys/backlight.h` defines `struct backlight_props`.
+#[repr(C)]
+struct Props {
+    brightness: u32,
+    levels: u32,
+    level: [u32; MAX_LEVELS],
+}
+
+/// `_IOWR('G', number, struct backlight_props)`, after `sys/ioccom.h`.
+const fn iowr(number: libc::c_ulong) -> libc::c_ulong {
+    const IOC_INOUT: libc::c_ulong = 0xc000_0000;
+    const IOCPARM_MASK: libc::c_ulong = 0x1fff;
+    let length = std::mem::size_of::<Props>() as libc::c_ulong & IOCPARM_MASK;
+    IOC_INOUT | length << 16 | (b'G' as libc::c_ulong) << 8 | number
+}
+
+const BACKLIGHTGETSTATUS: libc::c_ulong = iowr(0);
+const BACKLIGHTUPDATESTATUS: libc::c_ulong = iowr(1);
+
+#[derive(Debug)]
+enum Control {
+    /// A device node of backlight(9), as backlight(8) uses.
+    Backlight(File),
+    /// The `hw.acpi.video.lcdN.brightness` sysctl of acpi_video(4), for machines whose graphics
+    /// driver doesn't register with backlight(9).
+    Sysctl(CString),
+}
+
+/// Backend setting the backlight on FreeBSD, through backlight(9) or acpi_video(4). Selected
+/// instead of sysfs when there's no `/sys/class/backlight`, which there only is under Linux
+/// emulation.
+#[derive(Debug)]
+pub struct FreeBsd {
+    name: String,
+    control: Control,
+}
+
+impl FreeBsd {
+    /// Use the backlight called `name`, like "backlight0" in `/dev/backlight` or "lcd0" of
+    /// acpi_video, or the first one found if no name is given.
+    pub fn open(name: Option<&str>) -> Result<FreeBsd> {
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => FreeBsd::backlights()?.into_iter().next().ok_or_else(|| {
+                DimmerError::DeviceNotFound(format!("a backlight in {DEV_BACKLIGHT} or acpi_video"))
+            })?,
+        };
+        let path = Path::new(DEV_BACKLIGHT).join(&name);
+        let control = if path.exists() {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .map_err(|err| match err.kind() {
+                    std::io::ErrorKind::PermissionDenied => {
+                        anyhow::Error::new(DimmerError::PermissionDenied(path.clone()))
+                    }
+                    _ => anyhow::Error::new(err)
+                        .context(format!("Failed to open {}", path.display())),
+                })?;
+            Control::Backlight(file)
+        } else {
+            let sysctl = CString::new(format!("hw.acpi.video.{name}.brightness"))?;
+            if read_sysctl(&sysctl).is_err() {
+                return Err(DimmerError::DeviceNotFound(name).into());
+            }
+            Control::Sysctl(sysctl)
+        };
+        Ok(FreeBsd { name, control })
+    }
+
+    /// The names of the backlights found: those of backlight(9) first, sorted, and then those of
+    /// acpi_video.
+    pub fn backlights() -> Result<Vec<String>> {
+        let mut names: Vec<String> = match std::fs::read_dir(DEV_BACKLIGHT) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                // Devices are also linked to by the name of their driver, which we'd list twice.
+                .filter(|name| name.starts_with("backlight"))
+                .collect(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err).with_context(|| format!("Failed to read {DEV_BACKLIGHT}")),
+        };
+        names.sort();
+        names.extend(
+            (0..MAX_LCDS)
+                .map(|index| format!("lcd{index}"))
+                .filter(|name| {
+                    CString::new(format!("hw.acpi.video.{name}.brightness"))
+                        .is_ok_and(|sysctl| read_sysctl(&sysctl).is_ok())
+                }),
+        );
+        Ok(names)
+    }
+
+    pub fn backlight_name(&self) -> &str {
+        &self.name
+    }
+
+    fn props(&self, file: &File) -> Result<Props> {
+        let mut props = Props {
+            brightness: 0,
+            levels: 0,
+            level: [0; MAX_LEVELS],
+        };
+        // SAFETY: props is the struct BACKLIGHTGETSTATUS fills in.
+        if unsafe { libc::ioctl(file.as_raw_fd(), BACKLIGHTGETSTATUS, &mut props) } < 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to get the brightness of {}", self.name));
+        }
+        Ok(props)
+    }
+}
+
+impl Backend for FreeBsd {
+    fn class(&self) -> &str {
+        "backlight"
+    }
+
+    fn current(&self) -> Result<Brightness> {
+        let brightness = match &self.control {
+            Control::Backlight(file) => self.props(file)?.brightness.into(),
+            Control::Sysctl(sysctl) => read_sysctl(sysctl)
+                .with_context(|| format!("Failed to get the brightness of {}", self.name))?
+                .max(0) as u64,
+        };
+        Ok(Brightness(brightness))
+    }
+
+    fn max(&self) -> Result<Brightness> {
+        Ok(MAX)
+    }
+
+    fn set(&mut self, brightness: Brightness) -> Result<()> {
+        let brightness = brightness.0.min(MAX.0) as u32;
+        let result = match &self.control {
+            Control::Backlight(file) => {
+                let mut props = self.props(file)?;
+                props.brightness = brightness;
+                // SAFETY: props is the struct BACKLIGHTUPDATESTATUS takes.
+                if unsafe { libc::ioctl(file.as_raw_fd(), BACKLIGHTUPDATESTATUS, &mut props) } < 0 {
+                    Err(std::io::Error::last_os_error())
+                } else {
+                    Ok(())
+                }
+            }
+            Control::Sysctl(sysctl) => write_sysctl(sysctl, brightness as libc::c_int),
+        };
+        result.with_context(|| format!("Failed to set the brightness of {}", self.name))
+    }
+}
+
+fn read_sysctl(name: &CString) -> std::io::Result<libc::c_int> {
+    let mut value: libc::c_int = 0;
+    let mut length = std::mem::size_of::<libc::c_int>();
+    // SAFETY: value and length describe a valid place to store an int, and we write nothing.
+    let result = unsafe {
+        libc::sysctlbyname(
+            name.as_ptr(),
+            (&mut value as *mut libc::c_int).cast(),
+            &mut length,
+            std::ptr::null(),
+            0,
+        )
+    };
+    if result < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(value)
+}
+
+fn write_sysctl(name: &CString, value: libc::c_int) -> std::io::Result<()> {
+    // SAFETY: value is a valid int to write, and we read nothing back.
+    let result = unsafe {
+        libc::sysctlbyname(
+            name.as_ptr(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            (&value as *const libc::c_int).cast(),
+            std::mem::size_of::<libc::c_int>(),
+        )
+    };
+    if result < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
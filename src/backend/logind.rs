@@ -0,0 +1,87 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use zbus::blocking::Connection;
+
+use super::{Backend, Sysfs};
+use crate::Brightness;
+
+const LOGIND: &str = "org.freedesktop.login1";
+const SESSION_PATH: &str = "/org/freedesktop/login1/session/auto";
+const SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+
+/// Backend setting the brightness of a sysfs device through systemd-logind, which lets the user
+/// of the active session do so without root or udev rules. The brightness is still read from
+/// sysfs, which is readable by anyone.
+pub struct Logind {
+    sysfs: Sysfs,
+    name: String,
+    connection: Connection,
+}
+
+impl Logind {
+    /// Use the device in a `/sys/class/backlight/*` or `/sys/class/leds/*` style directory.
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Result<Logind> {
+        let dir = dir.as_ref();
+        let name = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let connection = Connection::system().context("Failed to connect to the system bus")?;
+        Ok(Logind {
+            sysfs: Sysfs::from_dir(dir),
+            name,
+            connection,
+        })
+    }
+}
+
+impl std::fmt::Debug for Logind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Logind")
+            .field("sysfs", &self.sysfs)
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl Backend for Logind {
+    /// The kernel subsystem of the device, which is what logind wants to know as well.
+    fn class(&self) -> &str {
+        self.sysfs.class()
+    }
+
+    fn current(&self) -> Result<Brightness> {
+        self.sysfs.current()
+    }
+
+    fn max(&self) -> Result<Brightness> {
+        self.sysfs.max()
+    }
+
+    fn set(&mut self, brightness: Brightness) -> Result<()> {
+        let brightness = u32::try_from(brightness.0).unwrap_or(u32::MAX);
+        self.connection
+            .call_method(
+                Some(LOGIND),
+                SESSION_PATH,
+                Some(SESSION_INTERFACE),
+                "SetBrightness",
+                &(self.class(), &self.name, brightness),
+            )
+            .with_context(|| {
+                format!(
+                    "Failed to set the brightness of {} through logind",
+                    self.name
+                )
+            })?;
+        Ok(())
+    }
+
+    fn is_powered(&self) -> Result<bool> {
+        self.sysfs.is_powered()
+    }
+
+    fn watched_paths(&self) -> Vec<PathBuf> {
+        self.sysfs.watched_paths()
+    }
+}
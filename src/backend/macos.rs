@@ -0,0 +1,186 @@
+use anyhow::{bail, Result};
+use std::ffi::{c_void, CStr};
+
+use super::Backend;
+use crate::{Brightness, DimmerError};
+
+/// The brightness at full backlight. macOS takes the brightness as a fraction, so we pick a unit
+/// with enough resolution for smooth transitions.
+const MAX: Brightness = Brightness(10000);
+
+/// The most displays we ask CoreGraphics about.
+const MAX_DISPLAYS: u32 = 32;
+
+/// Where the private framework setting the brightness of Apple's own displays lives.
+const DISPLAY_SERVICES: &CStr =
+    c"/System/Library/PrivateFrameworks/DisplayServices.framework/DisplayServices";
+
+type DisplayId = u32;
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGGetOnlineDisplayList(max: u32, displays: *mut DisplayId, count: *mut u32) -> i32;
+    fn CGDisplayIsBuiltin(display: DisplayId) -> u32;
+}
+
+type GetBrightness = unsafe extern "C" fn(DisplayId, *mut f32) -> i32;
+type SetBrightness = unsafe extern "C" fn(DisplayId, f32) -> i32;
+type CanChangeBrightness = unsafe extern "C" fn(DisplayId) -> bool;
+
+/// The functions of DisplayServices we use. It's a private framework, so we look them up at
+/// runtime rather than link against it.
+#[derive(Clone, Copy)]
+struct DisplayServices {
+    get: GetBrightness,
+    set: SetBrightness,
+    can_change: CanChangeBrightness,
+}
+
+impl DisplayServices {
+    fn load() -> Result<DisplayServices> {
+        // SAFETY: the path is a valid, nul-terminated string. The framework stays loaded, as we
+        // never close it.
+        let handle = unsafe { libc::dlopen(DISPLAY_SERVICES.as_ptr(), libc::RTLD_LAZY) };
+        if handle.is_null() {
+            bail!("Failed to load {}", DISPLAY_SERVICES.to_string_lossy());
+        }
+        let symbol = |name: &CStr| -> Result<*mut c_void> {
+            // SAFETY: handle is a library we loaded, and name a valid, nul-terminated string.
+            let symbol = unsafe { libc::dlsym(handle, name.as_ptr()) };
+            if symbol.is_null() {
+                bail!(
+                    "Failed to find {} in DisplayServices",
+                    name.to_string_lossy()
+                );
+            }
+            Ok(symbol)
+        };
+        // SAFETY: these are the signatures DisplayServices has had since it was introduced.
+        unsafe {
+            Ok(DisplayServices {
+                get: std::mem::transmute::<*mut c_void, GetBrightness>(symbol(
+                    c"DisplayServicesGetBrightness",
+                )?),
+                set: std::mem::transmute::<*mut c_void, SetBrightness>(symbol(
+                    c"DisplayServicesSetBrightness",
+                )?),
+                can_change: std::mem::transmute::<*mut c_void, CanChangeBrightness>(symbol(
+                    c"DisplayServicesCanChangeBrightness",
+                )?),
+            })
+        }
+    }
+}
+
+/// Backend setting the backlight of a display on macOS through DisplayServices, as the brightness
+/// keys do. This works for built-in displays and Apple's external ones, others don't take their
+/// brightness from macOS.
+pub struct MacOs {
+    display: DisplayId,
+    name: String,
+    services: DisplayServices,
+}
+
+impl MacOs {
+    /// Use the display called `name`, or the first display with an adjustable brightness if no
+    /// name is given, built-in ones first.
+    pub fn open(name: Option<&str>) -> Result<MacOs> {
+        let services = DisplayServices::load()?;
+        let mut displays = displays()?;
+        // SAFETY: CGDisplayIsBuiltin takes any display ID.
+        displays.sort_by_key(|display| unsafe { CGDisplayIsBuiltin(*display) } == 0);
+        let display = match name {
+            Some(name) => displays
+                .into_iter()
+                .find(|display| display_name(*display) == name)
+                .ok_or_else(|| DimmerError::DeviceNotFound(format!("display {name}")))?,
+            None => displays
+                .into_iter()
+                // SAFETY: can_change takes any display ID.
+                .find(|display| unsafe { (services.can_change)(*display) })
+                .ok_or_else(|| {
+                    DimmerError::DeviceNotFound(
+                        "a display with an adjustable brightness".to_string(),
+                    )
+                })?,
+        };
+        Ok(MacOs {
+            display,
+            name: display_name(display),
+            services,
+        })
+    }
+
+    /// The names of the displays that are online.
+    pub fn displays() -> Result<Vec<String>> {
+        Ok(displays()?.into_iter().map(display_name).collect())
+    }
+
+    pub fn display_name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl std::fmt::Debug for MacOs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MacOs")
+            .field("display", &self.display)
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl Backend for MacOs {
+    fn class(&self) -> &str {
+        "macos"
+    }
+
+    fn current(&self) -> Result<Brightness> {
+        let mut brightness = 0.0;
+        // SAFETY: brightness is a valid place to store the brightness.
+        let error = unsafe { (self.services.get)(self.display, &mut brightness) };
+        if error != 0 {
+            bail!("Failed to get the brightness of {} ({error})", self.name);
+        }
+        Ok(Brightness(
+            (brightness.clamp(0.0, 1.0) as f64 * MAX.0 as f64).round() as u64,
+        ))
+    }
+
+    fn max(&self) -> Result<Brightness> {
+        Ok(MAX)
+    }
+
+    fn set(&mut self, brightness: Brightness) -> Result<()> {
+        let fraction = brightness.0.min(MAX.0) as f64 / MAX.0 as f64;
+        // SAFETY: set takes any display ID and brightness.
+        let error = unsafe { (self.services.set)(self.display, fraction as f32) };
+        if error != 0 {
+            bail!("Failed to set the brightness of {} ({error})", self.name);
+        }
+        Ok(())
+    }
+}
+
+/// The IDs of the displays that are online.
+fn displays() -> Result<Vec<DisplayId>> {
+    let mut displays = vec![0; MAX_DISPLAYS as usize];
+    let mut count = 0;
+    // SAFETY: displays has room for the number of displays we ask for.
+    let error = unsafe { CGGetOnlineDisplayList(MAX_DISPLAYS, displays.as_mut_ptr(), &mut count) };
+    if error != 0 {
+        bail!("Failed to list the displays (CoreGraphics error {error})");
+    }
+    displays.truncate(count as usize);
+    Ok(displays)
+}
+
+/// The name we give `display`: "built-in" for the display of a laptop or iMac, and its ID for
+/// others, which stays the same for as long as it's connected.
+fn display_name(display: DisplayId) -> String {
+    // SAFETY: CGDisplayIsBuiltin takes any display ID.
+    if unsafe { CGDisplayIsBuiltin(display) } != 0 {
+        return "built-in".to_string();
+    }
+    format!("display{display}")
+}
@@ -0,0 +1,150 @@
+//! Backends do the actual reading and writing of brightness values, so the transition logic
+//! doesn't need to care whether it's talking to sysfs or something else entirely.
+use anyhow::Result;
+use std::path::PathBuf;
+
+use crate::{Brightness, DimmerError};
+
+#[cfg(feature = "drm")]
+mod drm;
+#[cfg(target_os = "freebsd")]
+mod freebsd;
+#[cfg(feature = "logind")]
+mod logind;
+#[cfg(all(feature = "macos", target_os = "macos"))]
+mod macos;
+#[cfg(target_os = "openbsd")]
+mod openbsd;
+mod sysfs;
+#[cfg(feature = "wayland")]
+mod wayland;
+#[cfg(all(feature = "windows", target_os = "windows"))]
+mod windows;
+#[cfg(feature = "x11")]
+mod x11;
+
+#[cfg(feature = "drm")]
+pub use self::drm::Drm;
+#[cfg(target_os = "freebsd")]
+pub use freebsd::FreeBsd;
+#[cfg(feature = "logind")]
+pub use logind::Logind;
+#[cfg(all(feature = "macos", target_os = "macos"))]
+pub use macos::MacOs;
+#[cfg(target_os = "openbsd")]
+pub use openbsd::OpenBsd;
+pub use sysfs::{Class, Sysfs, SYS_BACKLIGHT_PREFIX, SYS_LEDS_PREFIX};
+#[cfg(feature = "wayland")]
+pub use wayland::Wayland;
+#[cfg(all(feature = "windows", target_os = "windows"))]
+pub use windows::Windows;
+#[cfg(feature = "x11")]
+pub use x11::X11;
+
+/// The brightness of an output at full (unaltered) gamma, for the backends that dim in software.
+/// Gamma has no natural unit, so we pick one with enough resolution for smooth transitions.
+pub const GAMMA_MAX: Brightness = Brightness(10000);
+
+/// A gamma ramp of `size` entries, scaled down to `brightness`.
+#[cfg(any(feature = "wayland", feature = "x11", feature = "drm"))]
+fn gamma_ramp(size: usize, brightness: Brightness) -> Vec<u16> {
+    let factor = brightness.0.min(GAMMA_MAX.0) as f64 / GAMMA_MAX.0 as f64;
+    let last = size.max(2) - 1;
+    (0..size)
+        .map(|i| (i as f64 / last as f64 * u16::MAX as f64 * factor) as u16)
+        .collect()
+}
+
+/// The brightness of a ramp with `top` as its highest entry, the inverse of `gamma_ramp`.
+#[cfg(any(feature = "x11", feature = "drm"))]
+fn gamma_brightness(top: Option<u16>) -> Brightness {
+    let factor = top.unwrap_or(u16::MAX) as f64 / u16::MAX as f64;
+    Brightness((factor * GAMMA_MAX.0 as f64).round() as u64)
+}
+
+/// The backends dimmer was built with, to select one by name.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Kind {
+    Sysfs,
+    #[cfg(feature = "wayland")]
+    Wayland,
+    #[cfg(feature = "x11")]
+    X11,
+    #[cfg(feature = "drm")]
+    Drm,
+    #[cfg(feature = "logind")]
+    Logind,
+    #[cfg(all(feature = "macos", target_os = "macos"))]
+    MacOs,
+    #[cfg(all(feature = "windows", target_os = "windows"))]
+    Windows,
+    #[cfg(target_os = "freebsd")]
+    FreeBsd,
+    #[cfg(target_os = "openbsd")]
+    OpenBsd,
+}
+
+impl std::str::FromStr for Kind {
+    type Err = DimmerError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "sysfs" => Ok(Kind::Sysfs),
+            #[cfg(feature = "wayland")]
+            "wayland" => Ok(Kind::Wayland),
+            #[cfg(feature = "x11")]
+            "x11" => Ok(Kind::X11),
+            #[cfg(feature = "drm")]
+            "drm" => Ok(Kind::Drm),
+            #[cfg(feature = "logind")]
+            "logind" => Ok(Kind::Logind),
+            #[cfg(all(feature = "macos", target_os = "macos"))]
+            "macos" => Ok(Kind::MacOs),
+            #[cfg(all(feature = "windows", target_os = "windows"))]
+            "windows" => Ok(Kind::Windows),
+            #[cfg(target_os = "freebsd")]
+            "freebsd" => Ok(Kind::FreeBsd),
+            #[cfg(target_os = "openbsd")]
+            "openbsd" => Ok(Kind::OpenBsd),
+            _ => Err(DimmerError::UnknownBackend(input.to_string())),
+        }
+    }
+}
+
+pub trait Backend: std::fmt::Debug + Send {
+    /// The kind of device this backend controls, e.g. "backlight" or "x11".
+    fn class(&self) -> &str;
+
+    /// The current brightness.
+    fn current(&self) -> Result<Brightness>;
+
+    /// The maximum brightness supported.
+    fn max(&self) -> Result<Brightness>;
+
+    /// Set the brightness. This is called many times per second during a transition, so
+    /// implementations should keep any expensive setup between calls.
+    fn set(&mut self, brightness: Brightness) -> Result<()>;
+
+    /// Whether the brightness we set is undone as soon as the backend is dropped, like the gamma
+    /// of a Wayland output is reset when our connection closes.
+    fn is_volatile(&self) -> bool {
+        false
+    }
+
+    /// Whether the device is powered on. Devices that can't be powered off are always on.
+    fn is_powered(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    /// Power the device on or off, independent of its brightness. Devices that can't be powered
+    /// off ignore this.
+    fn set_powered(&mut self, _powered: bool) -> Result<()> {
+        Ok(())
+    }
+
+    /// The files that change along with the brightness, to watch for changes by other programs.
+    /// Backends without such files have their brightness polled instead.
+    fn watched_paths(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,141 @@
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+
+use super::Backend;
+use crate::{Brightness, DimmerError};
+
+/// The displays wscons(4) has, as wsconsctl(8) knows them. The second screen of a machine with
+/// two graphics cards is ttyD0.
+const DISPLAYS: [&str; 2] = ["ttyC0", "ttyD0"];
+
+/// The display parameter of the backlight, from `dev/wscons/wsconsio.h`.
+const WSDISPLAYIO_PARAM_BACKLIGHT: libc::c_int = 1;
+
+/// A parameter of a display, as `dev/wscons/wsconsio.h` defines `struct wsdisplay_param`.
+#[repr(C)]
+#[derive(Debug, Default)]
+struct Param {
+    param: libc::c_int,
+    min: libc::c_int,
+    max: libc::c_int,
+    current: libc::c_int,
+    reserved: [libc::c_int; 4],
+}
+
+/// `_IOWR('W', number, struct wsdisplay_param)`, after `sys/ioccom.h`.
+const fn iowr(number: libc::c_ulong) -> libc::c_ulong {
+    const IOC_INOUT: libc::c_ulong = 0xc000_0000;
+    const IOCPARM_MASK: libc::c_ulong = 0x1fff;
+    let length = std::mem::size_of::<Param>() as libc::c_ulong & IOCPARM_MASK;
+    IOC_INOUT | length << 16 | (b'W' as libc::c_ulong) << 8 | number
+}
+
+const WSDISPLAYIO_GETPARAM: libc::c_ulong = iowr(81);
+const WSDISPLAYIO_SETPARAM: libc::c_ulong = iowr(82);
+
+/// Backend setting the backlight on OpenBSD through wscons(4), as `wsconsctl display.backlight`
+/// does. Selected instead of sysfs, which OpenBSD doesn't have.
+#[derive(Debug)]
+pub struct OpenBsd {
+    name: String,
+    display: File,
+    min: libc::c_int,
+    max: libc::c_int,
+}
+
+impl OpenBsd {
+    /// Use the display called `name`, like "ttyC0", or the first display with a backlight if no
+    /// name is given.
+    pub fn open(name: Option<&str>) -> Result<OpenBsd> {
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => OpenBsd::displays()?.into_iter().next().ok_or_else(|| {
+                DimmerError::DeviceNotFound("a display with a backlight".to_string())
+            })?,
+        };
+        let path = Path::new("/dev").join(&name);
+        let display = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .map_err(|err| match err.kind() {
+                std::io::ErrorKind::PermissionDenied => {
+                    anyhow::Error::new(DimmerError::PermissionDenied(path.clone()))
+                }
+                std::io::ErrorKind::NotFound => {
+                    anyhow::Error::new(DimmerError::DeviceNotFound(name.clone()))
+                }
+                _ => anyhow::Error::new(err).context(format!("Failed to open {}", path.display())),
+            })?;
+        let backlight = backlight(&display)
+            .with_context(|| format!("Failed to get the backlight of {name}"))?;
+        Ok(OpenBsd {
+            name,
+            display,
+            min: backlight.min,
+            max: backlight.max,
+        })
+    }
+
+    /// The names of the displays that have a backlight we can read.
+    pub fn displays() -> Result<Vec<String>> {
+        Ok(DISPLAYS
+            .iter()
+            .filter(|name| {
+                File::open(Path::new("/dev").join(name))
+                    .is_ok_and(|display| backlight(&display).is_ok())
+            })
+            .map(|name| name.to_string())
+            .collect())
+    }
+
+    pub fn display_name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Backend for OpenBsd {
+    fn class(&self) -> &str {
+        "backlight"
+    }
+
+    fn current(&self) -> Result<Brightness> {
+        let backlight = backlight(&self.display)
+            .with_context(|| format!("Failed to get the brightness of {}", self.name))?;
+        Ok(Brightness((backlight.current - self.min).max(0) as u64))
+    }
+
+    fn max(&self) -> Result<Brightness> {
+        Ok(Brightness((self.max - self.min).max(0) as u64))
+    }
+
+    fn set(&mut self, brightness: Brightness) -> Result<()> {
+        let range = (self.max - self.min).max(0) as u64;
+        let mut param = Param {
+            param: WSDISPLAYIO_PARAM_BACKLIGHT,
+            current: self.min + brightness.0.min(range) as libc::c_int,
+            ..Param::default()
+        };
+        // SAFETY: param is the struct WSDISPLAYIO_SETPARAM takes.
+        if unsafe { libc::ioctl(self.display.as_raw_fd(), WSDISPLAYIO_SETPARAM, &mut param) } < 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to set the brightness of {}", self.name));
+        }
+        Ok(())
+    }
+}
+
+/// The backlight parameter of `display`, with its range.
+fn backlight(display: &File) -> std::io::Result<Param> {
+    let mut param = Param {
+        param: WSDISPLAYIO_PARAM_BACKLIGHT,
+        ..Param::default()
+    };
+    // SAFETY: param is the struct WSDISPLAYIO_GETPARAM fills in.
+    if unsafe { libc::ioctl(display.as_raw_fd(), WSDISPLAYIO_GETPARAM, &mut param) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(param)
+}
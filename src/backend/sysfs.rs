@@ -0,0 +1,178 @@
+use anyhow::{Context, Result};
+use glob::glob;
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use super::Backend;
+use crate::{Brightness, DimmerError};
+
+pub const SYS_BACKLIGHT_PREFIX: &str = "/sys/class/backlight";
+pub const SYS_LEDS_PREFIX: &str = "/sys/class/leds";
+
+/// The values of `bl_power`, after the framebuffer blanking levels in `linux/fb.h`.
+const FB_BLANK_UNBLANK: u64 = 0;
+const FB_BLANK_POWERDOWN: u64 = 4;
+
+/// The kinds of lights we look for in sysfs.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Class {
+    /// Screen backlights in `/sys/class/backlight`.
+    Backlight,
+    /// Keyboard backlights, which live among the other LEDs in `/sys/class/leds`.
+    Keyboard,
+}
+
+impl Class {
+    /// The name of the class, after the kernel's name for it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Class::Backlight => "backlight",
+            Class::Keyboard => "leds",
+        }
+    }
+
+    /// The pattern matching the device directories of this class.
+    pub fn glob(&self) -> String {
+        match self {
+            Class::Backlight => format!("{SYS_BACKLIGHT_PREFIX}/*"),
+            Class::Keyboard => format!("{SYS_LEDS_PREFIX}/*::kbd_backlight"),
+        }
+    }
+}
+
+/// Backend for the files the kernel exposes in `/sys/class/backlight` and `/sys/class/leds`.
+#[derive(Debug)]
+pub struct Sysfs {
+    class: Class,
+    brightness_path: PathBuf,
+    actual_brightness_path: PathBuf,
+    max_brightness_path: PathBuf,
+    bl_power_path: Option<PathBuf>,
+    output: Option<File>,
+}
+
+impl Sysfs {
+    pub fn new(
+        brightness_path: PathBuf,
+        actual_brightness_path: PathBuf,
+        max_brightness_path: PathBuf,
+    ) -> Sysfs {
+        Sysfs {
+            class: Class::Backlight,
+            brightness_path,
+            actual_brightness_path,
+            max_brightness_path,
+            bl_power_path: None,
+            output: None,
+        }
+    }
+
+    /// Use the files in a `/sys/class/backlight/*` or `/sys/class/leds/*` style directory. LEDs
+    /// have no `actual_brightness`, so we read their current brightness from `brightness`, nor
+    /// `bl_power`, so they can't be powered off.
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Sysfs {
+        let dir = dir.as_ref();
+        let actual_brightness_path = match dir.join("actual_brightness") {
+            path if path.exists() => path,
+            _ => dir.join("brightness"),
+        };
+        let mut sysfs = Sysfs::new(
+            dir.join("brightness"),
+            actual_brightness_path,
+            dir.join("max_brightness"),
+        );
+        if dir.parent().and_then(Path::file_name) == Some(Class::Keyboard.name().as_ref()) {
+            sysfs.class = Class::Keyboard;
+        }
+        sysfs.bl_power_path = Some(dir.join("bl_power")).filter(|path| path.exists());
+        sysfs
+    }
+
+    /// All device directories of `class` found in sysfs.
+    pub fn discover(class: Class) -> Result<Vec<PathBuf>> {
+        let glob_path = class.glob();
+        let dirs = glob(&glob_path)
+            .with_context(|| format!("Failed to glob {glob_path}"))?
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Glob error trying to match {glob_path}"))?;
+        Ok(dirs)
+    }
+
+    pub fn set_brightness_path(&mut self, path: PathBuf) {
+        self.brightness_path = path;
+        self.output = None;
+    }
+
+    pub fn set_actual_brightness_path(&mut self, path: PathBuf) {
+        self.actual_brightness_path = path;
+    }
+
+    pub fn set_max_brightness_path(&mut self, path: PathBuf) {
+        self.max_brightness_path = path;
+    }
+}
+
+impl Backend for Sysfs {
+    fn class(&self) -> &str {
+        self.class.name()
+    }
+
+    fn current(&self) -> Result<Brightness> {
+        Brightness::from_file(&self.actual_brightness_path)
+    }
+
+    fn max(&self) -> Result<Brightness> {
+        Brightness::from_file(&self.max_brightness_path)
+    }
+
+    /// The brightness file is kept open between calls, as we write to it many times per second
+    /// during a transition.
+    fn set(&mut self, brightness: Brightness) -> Result<()> {
+        let output = match &mut self.output {
+            Some(output) => output,
+            None => self.output.insert(create(&self.brightness_path)?),
+        };
+        write!(output, "{}", brightness.0)?;
+        Ok(())
+    }
+
+    fn is_powered(&self) -> Result<bool> {
+        match &self.bl_power_path {
+            Some(path) => Ok(Brightness::from_file(path)?.0 == FB_BLANK_UNBLANK),
+            None => Ok(true),
+        }
+    }
+
+    /// Backlights are powered off through `bl_power`, which takes them further down than a
+    /// brightness of 0 on panels that keep the backlight lit at 0.
+    fn set_powered(&mut self, powered: bool) -> Result<()> {
+        let Some(path) = &self.bl_power_path else {
+            return Ok(());
+        };
+        let value = if powered {
+            FB_BLANK_UNBLANK
+        } else {
+            FB_BLANK_POWERDOWN
+        };
+        write!(create(path)?, "{value}")
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    fn watched_paths(&self) -> Vec<PathBuf> {
+        let mut paths = vec![self.brightness_path.clone()];
+        if self.actual_brightness_path != self.brightness_path {
+            paths.push(self.actual_brightness_path.clone());
+        }
+        paths
+    }
+}
+
+/// Open the file at `path` for writing, telling a lack of permission apart from other failures, as
+/// that's the one users can fix.
+fn create(path: &Path) -> Result<File> {
+    File::create(path).map_err(|err| match err.kind() {
+        ErrorKind::PermissionDenied => DimmerError::PermissionDenied(path.to_path_buf()).into(),
+        _ => anyhow::Error::new(err).context(format!("Failed to open {}", path.display())),
+    })
+}
@@ -0,0 +1,259 @@
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::{Seek, Write};
+use std::os::unix::io::{AsFd, FromRawFd};
+use wayland_client::protocol::{wl_output, wl_registry};
+use wayland_client::{Connection, Dispatch, EventQueue, QueueHandle};
+use wayland_protocols_wlr::gamma_control::v1::client::{
+    zwlr_gamma_control_manager_v1::{self, ZwlrGammaControlManagerV1},
+    zwlr_gamma_control_v1::{self, ZwlrGammaControlV1},
+};
+
+use super::{gamma_ramp, Backend, GAMMA_MAX};
+use crate::{Brightness, DimmerError};
+
+/// Backend dimming a Wayland output in software, by scaling its gamma ramps through the
+/// `wlr-gamma-control-unstable-v1` protocol.
+///
+/// The compositor resets the gamma as soon as our connection closes, so the dimming only lasts
+/// as long as the backend is alive.
+pub struct Wayland {
+    queue: EventQueue<State>,
+    state: State,
+    output: usize,
+    current: Brightness,
+}
+
+#[derive(Default)]
+struct State {
+    manager: Option<ZwlrGammaControlManagerV1>,
+    outputs: Vec<Output>,
+}
+
+struct Output {
+    output: wl_output::WlOutput,
+    name: Option<String>,
+    gamma: Option<ZwlrGammaControlV1>,
+    gamma_size: Option<u32>,
+    failed: bool,
+}
+
+impl Wayland {
+    /// Connect to the compositor and take control of the gamma of the output called `name`, or
+    /// of the first output if no name is given.
+    pub fn connect(name: Option<&str>) -> Result<Wayland> {
+        let (mut queue, mut state) = connect()?;
+        let output = match name {
+            Some(name) => state
+                .outputs
+                .iter()
+                .position(|output| output.name.as_deref() == Some(name))
+                .ok_or_else(|| DimmerError::DeviceNotFound(format!("Wayland output {name}")))?,
+            None if state.outputs.is_empty() => {
+                bail!(DimmerError::DeviceNotFound("a Wayland output".to_string()))
+            }
+            None => 0,
+        };
+
+        let manager = state
+            .manager
+            .as_ref()
+            .context("The compositor doesn't support wlr-gamma-control-unstable-v1")?;
+        let gamma =
+            manager.get_gamma_control(&state.outputs[output].output, &queue.handle(), output);
+        state.outputs[output].gamma = Some(gamma);
+        queue
+            .roundtrip(&mut state)
+            .context("Failed to get gamma control")?;
+
+        let mut wayland = Wayland {
+            queue,
+            state,
+            output,
+            current: GAMMA_MAX,
+        };
+        wayland.check_failed()?;
+        Ok(wayland)
+    }
+
+    /// The names of all outputs the compositor advertises.
+    pub fn outputs() -> Result<Vec<String>> {
+        let (_queue, state) = connect()?;
+        Ok(state
+            .outputs
+            .into_iter()
+            .filter_map(|output| output.name)
+            .collect())
+    }
+
+    pub fn output_name(&self) -> Option<&str> {
+        self.state.outputs[self.output].name.as_deref()
+    }
+
+    fn check_failed(&mut self) -> Result<()> {
+        self.queue
+            .dispatch_pending(&mut self.state)
+            .context("Failed to dispatch Wayland events")?;
+        if self.state.outputs[self.output].failed {
+            bail!("Failed to control gamma, is another program (e.g. wlsunset) using it?");
+        }
+        Ok(())
+    }
+}
+
+fn connect() -> Result<(EventQueue<State>, State)> {
+    let conn = Connection::connect_to_env().context("Failed to connect to Wayland compositor")?;
+    let mut queue = conn.new_event_queue();
+    conn.display().get_registry(&queue.handle(), ());
+
+    let mut state = State::default();
+    // The first roundtrip gets us the globals, the second the names of the outputs we bound.
+    for _ in 0..2 {
+        queue
+            .roundtrip(&mut state)
+            .context("Failed to communicate with Wayland compositor")?;
+    }
+    Ok((queue, state))
+}
+
+impl std::fmt::Debug for Wayland {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Wayland")
+            .field("output", &self.output_name())
+            .field("current", &self.current)
+            .finish()
+    }
+}
+
+impl Backend for Wayland {
+    fn class(&self) -> &str {
+        "wayland"
+    }
+
+    fn current(&self) -> Result<Brightness> {
+        Ok(self.current)
+    }
+
+    fn max(&self) -> Result<Brightness> {
+        Ok(GAMMA_MAX)
+    }
+
+    fn set(&mut self, brightness: Brightness) -> Result<()> {
+        self.check_failed()?;
+        let output = &self.state.outputs[self.output];
+        let (Some(gamma), Some(size)) = (&output.gamma, output.gamma_size) else {
+            bail!("The compositor didn't tell us the gamma size");
+        };
+
+        let ramp: Vec<u8> = gamma_ramp(size as usize, brightness)
+            .into_iter()
+            .flat_map(u16::to_ne_bytes)
+            .collect();
+
+        // The compositor reads the red, green and blue ramps from a file descriptor.
+        let mut table = memfd()?;
+        for _channel in 0..3 {
+            table.write_all(&ramp)?;
+        }
+        table.rewind()?;
+        gamma.set_gamma(table.as_fd());
+        self.queue.flush().context("Failed to set gamma")?;
+
+        self.current = brightness;
+        Ok(())
+    }
+
+    fn is_volatile(&self) -> bool {
+        true
+    }
+}
+
+fn memfd() -> Result<File> {
+    let fd = unsafe { libc::memfd_create(c"dimmer-gamma".as_ptr(), libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to create gamma table");
+    }
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for State {
+    fn event(
+        state: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global {
+            name,
+            interface,
+            version,
+        } = event
+        {
+            match &interface[..] {
+                "wl_output" => {
+                    let index = state.outputs.len();
+                    let output = registry.bind(name, version.min(4), qh, index);
+                    state.outputs.push(Output {
+                        output,
+                        name: None,
+                        gamma: None,
+                        gamma_size: None,
+                        failed: false,
+                    });
+                }
+                "zwlr_gamma_control_manager_v1" => {
+                    state.manager = Some(registry.bind(name, 1, qh, ()));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, usize> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_output::WlOutput,
+        event: wl_output::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_output::Event::Name { name } = event {
+            state.outputs[*index].name = Some(name);
+        }
+    }
+}
+
+impl Dispatch<ZwlrGammaControlManagerV1, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &ZwlrGammaControlManagerV1,
+        _: zwlr_gamma_control_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrGammaControlV1, usize> for State {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrGammaControlV1,
+        event: zwlr_gamma_control_v1::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_gamma_control_v1::Event::GammaSize { size } => {
+                state.outputs[*index].gamma_size = Some(size)
+            }
+            zwlr_gamma_control_v1::Event::Failed => state.outputs[*index].failed = true,
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,430 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::ffi::c_void;
+use wmi::WMIConnection;
+
+use super::Backend;
+use crate::{Brightness, DimmerError};
+
+/// The brightness at full backlight for displays set through WMI, which takes a percentage.
+const WMI_MAX: Brightness = Brightness(100);
+
+/// The WMI namespace the brightness classes live in.
+const WMI_NAMESPACE: &str = "ROOT\\WMI";
+
+/// The name we give the display set through WMI, which is the display of a laptop.
+const BUILT_IN: &str = "built-in";
+
+/// The type of continuous VCP codes, which can be set to any value up to their maximum, as
+/// `MC_VCP_CODE_TYPE` defines it.
+const MC_SET_PARAMETER: u32 = 1;
+
+type Handle = *mut c_void;
+
+type MonitorEnumProc = unsafe extern "system" fn(Handle, Handle, *mut c_void, isize) -> i32;
+
+/// A monitor behind a display, as dxva2 hands them out. Windows packs this struct.
+#[repr(C, packed(1))]
+#[derive(Clone, Copy)]
+struct PhysicalMonitorInfo {
+    handle: Handle,
+    _description: [u16; 128],
+}
+
+#[link(name = "user32")]
+extern "system" {
+    fn EnumDisplayMonitors(
+        dc: Handle,
+        clip: *const c_void,
+        callback: MonitorEnumProc,
+        data: isize,
+    ) -> i32;
+}
+
+#[link(name = "dxva2")]
+extern "system" {
+    fn GetNumberOfPhysicalMonitorsFromHMONITOR(monitor: Handle, count: *mut u32) -> i32;
+    fn GetPhysicalMonitorsFromHMONITOR(
+        monitor: Handle,
+        count: u32,
+        monitors: *mut PhysicalMonitorInfo,
+    ) -> i32;
+    fn DestroyPhysicalMonitor(monitor: Handle) -> i32;
+    fn GetMonitorBrightness(
+        monitor: Handle,
+        minimum: *mut u32,
+        current: *mut u32,
+        maximum: *mut u32,
+    ) -> i32;
+    fn SetMonitorBrightness(monitor: Handle, brightness: u32) -> i32;
+    fn GetVCPFeatureAndVCPFeatureReply(
+        monitor: Handle,
+        code: u8,
+        kind: *mut u32,
+        current: *mut u32,
+        maximum: *mut u32,
+    ) -> i32;
+    fn SetVCPFeature(monitor: Handle, code: u8, value: u32) -> i32;
+}
+
+/// The brightness of a display Windows sets through WMI, in `WmiMonitorBrightness`.
+#[derive(Debug, Deserialize)]
+#[serde(rename = "WmiMonitorBrightness", rename_all = "PascalCase")]
+struct MonitorBrightness {
+    instance_name: String,
+    active: bool,
+    current_brightness: u8,
+}
+
+/// The display to call `WmiSetBrightness` on.
+#[derive(Debug, Deserialize)]
+#[serde(rename = "WmiMonitorBrightnessMethods", rename_all = "PascalCase")]
+struct MonitorBrightnessMethods {
+    #[serde(rename = "__Path")]
+    path: String,
+    instance_name: String,
+}
+
+/// The arguments of `WmiSetBrightness`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SetBrightness {
+    /// How long the brightness holds before Windows' own policy may change it again, in seconds,
+    /// where 0 means until it's set again.
+    timeout: u32,
+    brightness: u8,
+}
+
+/// A monitor we control over DDC/CI, closed when dropped.
+#[derive(Debug)]
+struct PhysicalMonitor(Handle);
+
+// SAFETY: physical monitor handles aren't tied to the thread that opened them.
+unsafe impl Send for PhysicalMonitor {}
+
+impl Drop for PhysicalMonitor {
+    fn drop(&mut self) {
+        // SAFETY: the handle was opened by GetPhysicalMonitorsFromHMONITOR and is only closed here.
+        unsafe {
+            DestroyPhysicalMonitor(self.0);
+        }
+    }
+}
+
+impl PhysicalMonitor {
+    /// The brightness of the monitor and its maximum.
+    fn brightness(&self) -> std::io::Result<(u32, u32)> {
+        let (mut minimum, mut current, mut maximum) = (0, 0, 0);
+        // SAFETY: the handle is open, and the pointers point at valid places to store the values.
+        if unsafe { GetMonitorBrightness(self.0, &mut minimum, &mut current, &mut maximum) } == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok((current, maximum))
+    }
+
+    /// The value of the continuous VCP feature `code` of the monitor and its maximum.
+    fn feature(&self, code: u8) -> std::io::Result<(u32, u32)> {
+        let (mut kind, mut current, mut maximum) = (0, 0, 0);
+        // SAFETY: the handle is open, and the pointers point at valid places to store the values.
+        let replied = unsafe {
+            GetVCPFeatureAndVCPFeatureReply(self.0, code, &mut kind, &mut current, &mut maximum)
+        };
+        if replied == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if kind != MC_SET_PARAMETER {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("VCP code {code:#04x} isn't continuous"),
+            ));
+        }
+        Ok((current, maximum))
+    }
+
+    /// The value of the VCP feature `code` of the monitor, or its brightness if none is given.
+    fn value(&self, code: Option<u8>) -> std::io::Result<(u32, u32)> {
+        match code {
+            Some(code) => self.feature(code),
+            None => self.brightness(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Display {
+    /// A laptop's display, which Windows sets the backlight of through WMI.
+    Wmi { instance: String, path: String },
+    /// An external monitor, which we set the brightness of over DDC/CI, or another of its VCP
+    /// features if a code is given.
+    Ddc {
+        monitor: PhysicalMonitor,
+        code: Option<u8>,
+        max: u32,
+    },
+}
+
+/// Backend setting the brightness of a display on Windows: the backlight of a laptop's display
+/// through WMI's `WmiMonitorBrightnessMethods`, as the brightness keys do, and the brightness of
+/// external monitors over DDC/CI through the monitor configuration API.
+#[derive(Debug)]
+pub struct Windows {
+    name: String,
+    display: Display,
+}
+
+impl Windows {
+    /// Use the display called `name`: "built-in" for the display of a laptop, or "monitor1",
+    /// "monitor2" and so on for the others. Without a name, the laptop's display is used if there
+    /// is one, or else the first monitor that takes its brightness over DDC/CI.
+    pub fn open(name: Option<&str>) -> Result<Windows> {
+        if name.is_none() || name == Some(BUILT_IN) {
+            if let Some(display) = built_in()? {
+                return Ok(Windows {
+                    name: BUILT_IN.to_string(),
+                    display,
+                });
+            }
+            if name.is_some() {
+                return Err(DimmerError::DeviceNotFound(
+                    "a built-in display with an adjustable backlight".to_string(),
+                )
+                .into());
+            }
+        }
+        for (index, monitor) in physical_monitors()?.into_iter().enumerate() {
+            let monitor_name = format!("monitor{}", index + 1);
+            if name.is_some_and(|name| name != monitor_name) {
+                continue;
+            }
+            match monitor.brightness() {
+                Ok((_, max)) => {
+                    return Ok(Windows {
+                        name: monitor_name,
+                        display: Display::Ddc {
+                            monitor,
+                            code: None,
+                            max,
+                        },
+                    })
+                }
+                Err(err) if name.is_some() => {
+                    return Err(err).with_context(|| {
+                        format!("Failed to get the brightness of {monitor_name} over DDC/CI")
+                    })
+                }
+                Err(_) => {}
+            }
+        }
+        Err(match name {
+            Some(name) => DimmerError::DeviceNotFound(format!("display {name}")),
+            None => {
+                DimmerError::DeviceNotFound("a display with an adjustable brightness".to_string())
+            }
+        }
+        .into())
+    }
+
+    /// The names of the displays that are connected.
+    pub fn displays() -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        if built_in()?.is_some() {
+            names.push(BUILT_IN.to_string());
+        }
+        let monitors = physical_monitors()?.len();
+        names.extend((1..=monitors).map(|index| format!("monitor{index}")));
+        Ok(names)
+    }
+
+    pub fn display_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Control the VCP feature `code` of the monitor instead of its brightness, e.g. 0x12 for its
+    /// contrast. Only monitors controlled over DDC/CI have VCP features, and only the continuous
+    /// ones can be transitioned.
+    pub fn vcp(self, code: u8) -> Result<Windows> {
+        let Display::Ddc { monitor, .. } = self.display else {
+            bail!(
+                "{} isn't controlled over DDC/CI, so it has no VCP code {code:#04x}",
+                self.name
+            );
+        };
+        let (_, max) = monitor.feature(code).with_context(|| {
+            format!(
+                "Failed to get VCP code {code:#04x} of {} over DDC/CI",
+                self.name
+            )
+        })?;
+        Ok(Windows {
+            name: self.name,
+            display: Display::Ddc {
+                monitor,
+                code: Some(code),
+                max,
+            },
+        })
+    }
+}
+
+impl Backend for Windows {
+    fn class(&self) -> &str {
+        "windows"
+    }
+
+    fn current(&self) -> Result<Brightness> {
+        match &self.display {
+            Display::Wmi { instance, .. } => with_wmi(|wmi| {
+                let brightness = wmi
+                    .query::<MonitorBrightness>()?
+                    .into_iter()
+                    .find(|brightness| brightness.instance_name == *instance)
+                    .ok_or_else(|| DimmerError::DeviceNotFound(self.name.clone()))?;
+                Ok(Brightness(brightness.current_brightness.into()))
+            }),
+            Display::Ddc { monitor, code, .. } => {
+                let (current, _) = monitor.value(*code).with_context(|| {
+                    format!("Failed to get the brightness of {} over DDC/CI", self.name)
+                })?;
+                Ok(Brightness(current.into()))
+            }
+        }
+    }
+
+    fn max(&self) -> Result<Brightness> {
+        match &self.display {
+            Display::Wmi { .. } => Ok(WMI_MAX),
+            Display::Ddc { max, .. } => Ok(Brightness((*max).into())),
+        }
+    }
+
+    fn set(&mut self, brightness: Brightness) -> Result<()> {
+        match &self.display {
+            Display::Wmi { path, .. } => with_wmi(|wmi| {
+                let arguments = SetBrightness {
+                    timeout: 0,
+                    brightness: brightness.0.min(WMI_MAX.0) as u8,
+                };
+                wmi.exec_instance_method::<MonitorBrightnessMethods, ()>(
+                    path,
+                    "WmiSetBrightness",
+                    arguments,
+                )
+                .with_context(|| format!("Failed to set the brightness of {}", self.name))
+            }),
+            Display::Ddc { monitor, code, max } => {
+                let brightness = brightness.0.min((*max).into()) as u32;
+                // SAFETY: the handle is open.
+                let written = unsafe {
+                    match code {
+                        Some(code) => SetVCPFeature(monitor.0, *code, brightness),
+                        None => SetMonitorBrightness(monitor.0, brightness),
+                    }
+                };
+                if written == 0 {
+                    return Err(std::io::Error::last_os_error()).with_context(|| {
+                        format!("Failed to set the brightness of {} over DDC/CI", self.name)
+                    });
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Call `f` with this thread's connection to WMI, connecting first if this thread hasn't yet. A
+/// connection can't move between threads, and connecting for every frame would be too slow.
+fn with_wmi<T>(f: impl FnOnce(&WMIConnection) -> Result<T>) -> Result<T> {
+    thread_local! {
+        static WMI: RefCell<Option<WMIConnection>> = const { RefCell::new(None) };
+    }
+    WMI.with(|wmi| {
+        let mut wmi = wmi.borrow_mut();
+        if let Some(connection) = &*wmi {
+            return f(connection);
+        }
+        let connection = WMIConnection::with_namespace_path(WMI_NAMESPACE)
+            .context("Failed to connect to WMI")?;
+        f(wmi.insert(connection))
+    })
+}
+
+/// The laptop display Windows sets the backlight of through WMI, if any. Other displays don't
+/// show up in WMI's brightness classes.
+fn built_in() -> Result<Option<Display>> {
+    with_wmi(|wmi| {
+        let Some(brightness) = wmi
+            .query::<MonitorBrightness>()?
+            .into_iter()
+            .find(|brightness| brightness.active)
+        else {
+            return Ok(None);
+        };
+        let methods = wmi
+            .query::<MonitorBrightnessMethods>()?
+            .into_iter()
+            .find(|methods| methods.instance_name == brightness.instance_name)
+            .with_context(|| {
+                format!(
+                    "Failed to find the brightness methods of {}",
+                    brightness.instance_name
+                )
+            })?;
+        Ok(Some(Display::Wmi {
+            instance: brightness.instance_name,
+            path: methods.path,
+        }))
+    })
+}
+
+/// The monitors behind all connected displays, in the order Windows lists the displays.
+fn physical_monitors() -> Result<Vec<PhysicalMonitor>> {
+    unsafe extern "system" fn collect(
+        monitor: Handle,
+        _dc: Handle,
+        _rect: *mut c_void,
+        data: isize,
+    ) -> i32 {
+        // SAFETY: data is the vector EnumDisplayMonitors was given below.
+        let monitors = unsafe { &mut *(data as *mut Vec<Handle>) };
+        monitors.push(monitor);
+        1
+    }
+
+    let mut displays: Vec<Handle> = Vec::new();
+    // SAFETY: collect matches the callback EnumDisplayMonitors expects, and displays outlives
+    // the call.
+    let enumerated = unsafe {
+        EnumDisplayMonitors(
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            collect,
+            &mut displays as *mut Vec<Handle> as isize,
+        )
+    };
+    if enumerated == 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to list the displays");
+    }
+
+    let mut monitors = Vec::new();
+    for display in displays {
+        let mut count = 0;
+        // SAFETY: display was just handed to us by EnumDisplayMonitors.
+        if unsafe { GetNumberOfPhysicalMonitorsFromHMONITOR(display, &mut count) } == 0 {
+            continue;
+        }
+        let mut infos = vec![
+            PhysicalMonitorInfo {
+                handle: std::ptr::null_mut(),
+                _description: [0; 128],
+            };
+            count as usize
+        ];
+        // SAFETY: infos has room for the number of monitors we ask for.
+        if unsafe { GetPhysicalMonitorsFromHMONITOR(display, count, infos.as_mut_ptr()) } == 0 {
+            continue;
+        }
+        monitors.extend(infos.into_iter().map(|info| PhysicalMonitor(info.handle)));
+    }
+    Ok(monitors)
+}
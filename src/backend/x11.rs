@@ -0,0 +1,126 @@
+use anyhow::{bail, Context, Result};
+use x11rb::connection::Connection as _;
+use x11rb::protocol::randr::{self, ConnectionExt as _};
+use x11rb::rust_connection::RustConnection;
+
+use super::{gamma_brightness, gamma_ramp, Backend, GAMMA_MAX};
+use crate::{Brightness, DimmerError};
+
+/// Backend dimming an X11 output in software by scaling its CRTC gamma through XRandR, like
+/// `xrandr --brightness` and `xbacklight`'s fallback for screens without a backlight do.
+pub struct X11 {
+    conn: RustConnection,
+    name: String,
+    crtc: randr::Crtc,
+    gamma_size: u16,
+}
+
+impl X11 {
+    /// Connect to the X server and control the output called `name`, or the first connected
+    /// output if no name is given.
+    pub fn connect(name: Option<&str>) -> Result<X11> {
+        let (conn, screen) = x11rb::connect(None).context("Failed to connect to X server")?;
+        let (output_name, crtc) = outputs(&conn, screen)?
+            .into_iter()
+            .find(|(output_name, _crtc)| name.is_none_or(|name| name == output_name))
+            .ok_or_else(|| {
+                DimmerError::DeviceNotFound(match name {
+                    Some(name) => format!("active X11 output {name}"),
+                    None => "an active X11 output".to_string(),
+                })
+            })?;
+
+        let gamma_size = conn
+            .randr_get_crtc_gamma_size(crtc)?
+            .reply()
+            .context("Failed to get gamma size")?
+            .size;
+        if gamma_size < 2 {
+            bail!("Output {output_name} doesn't support gamma adjustment");
+        }
+
+        Ok(X11 {
+            conn,
+            name: output_name,
+            crtc,
+            gamma_size,
+        })
+    }
+
+    /// The names of all connected outputs that are driven by a CRTC.
+    pub fn outputs() -> Result<Vec<String>> {
+        let (conn, screen) = x11rb::connect(None).context("Failed to connect to X server")?;
+        Ok(outputs(&conn, screen)?
+            .into_iter()
+            .map(|(name, _crtc)| name)
+            .collect())
+    }
+
+    pub fn output_name(&self) -> &str {
+        &self.name
+    }
+}
+
+fn outputs(conn: &RustConnection, screen: usize) -> Result<Vec<(String, randr::Crtc)>> {
+    let root = conn.setup().roots[screen].root;
+    let resources = conn
+        .randr_get_screen_resources_current(root)?
+        .reply()
+        .context("Failed to get XRandR screen resources, does the X server support XRandR?")?;
+
+    let mut outputs = Vec::new();
+    for output in resources.outputs {
+        let info = conn
+            .randr_get_output_info(output, resources.config_timestamp)?
+            .reply()?;
+        if info.connection == randr::Connection::CONNECTED && info.crtc != 0 {
+            outputs.push((String::from_utf8_lossy(&info.name).into_owned(), info.crtc));
+        }
+    }
+    Ok(outputs)
+}
+
+impl std::fmt::Debug for X11 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("X11")
+            .field("output", &self.name)
+            .field("crtc", &self.crtc)
+            .finish()
+    }
+}
+
+impl Backend for X11 {
+    fn class(&self) -> &str {
+        "x11"
+    }
+
+    /// The gamma persists after we disconnect, so we read it back from the top of the ramp just
+    /// like xrandr does.
+    fn current(&self) -> Result<Brightness> {
+        let gamma = self
+            .conn
+            .randr_get_crtc_gamma(self.crtc)?
+            .reply()
+            .context("Failed to get gamma")?;
+        let top = gamma
+            .red
+            .iter()
+            .chain(&gamma.green)
+            .chain(&gamma.blue)
+            .max();
+        Ok(gamma_brightness(top.copied()))
+    }
+
+    fn max(&self) -> Result<Brightness> {
+        Ok(GAMMA_MAX)
+    }
+
+    fn set(&mut self, brightness: Brightness) -> Result<()> {
+        let ramp = gamma_ramp(self.gamma_size as usize, brightness);
+        self.conn
+            .randr_set_crtc_gamma(self.crtc, &ramp, &ramp, &ramp)?
+            .check()
+            .context("Failed to set gamma")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use anyhow::{bail, Result};
+use dimmer::{Brightness, Device};
+use std::time::{Duration, Instant};
+
+/// The highest framerate we recommend, as with `--framerate auto`.
+const MAX_FRAMERATE: u64 = 120;
+
+/// How long writes to a device take.
+#[derive(Debug)]
+pub struct Report {
+    /// How long every write took, fastest first.
+    latencies: Vec<Duration>,
+}
+
+/// Time `writes` writes to `device`, going back and forth between its current brightness and the
+/// level next to it, so the screen barely changes. The current brightness is restored afterwards.
+pub fn bench(device: &mut Device, writes: u64) -> Result<Report> {
+    let current = device.current()?;
+    let maximum = device.max()?;
+    if maximum.0 == 0 {
+        bail!("{} has only a single brightness level", device.name());
+    }
+    let neighbour = if current.0 > 0 {
+        Brightness(current.0 - 1)
+    } else {
+        Brightness(1)
+    };
+
+    let mut latencies = Vec::new();
+    let result = (0..writes).try_for_each(|write| {
+        let brightness = if write % 2 == 0 { neighbour } else { current };
+        let start = Instant::now();
+        device.set(brightness)?;
+        latencies.push(start.elapsed());
+        anyhow::Ok(())
+    });
+    device.set(current)?;
+    result?;
+
+    latencies.sort();
+    Ok(Report { latencies })
+}
+
+impl Report {
+    /// The latency `percent` percent of the writes took at most.
+    fn percentile(&self, percent: usize) -> Duration {
+        let index = (self.latencies.len() * percent).div_ceil(100);
+        self.latencies[index.saturating_sub(1)]
+    }
+
+    /// The highest framerate the device keeps up with nine times out of ten.
+    pub fn framerate(&self) -> u64 {
+        let latency = self.percentile(90).as_secs_f64();
+        if latency == 0.0 {
+            return MAX_FRAMERATE;
+        }
+        ((1.0 / latency) as u64).clamp(1, MAX_FRAMERATE)
+    }
+
+    pub fn print(&self, name: &str) {
+        if self.latencies.is_empty() {
+            println!("{name}: no writes");
+            return;
+        }
+        println!(
+            "{name}: {} writes, p50 {:.1?}, p90 {:.1?}, p99 {:.1?}, max {:.1?}",
+            self.latencies.len(),
+            self.percentile(50),
+            self.percentile(90),
+            self.percentile(99),
+            self.percentile(100),
+        );
+        let framerate = self.framerate();
+        if framerate < MAX_FRAMERATE {
+            println!(
+                "  keeps up with {framerate} writes per second, try `--framerate {framerate}`"
+            );
+        } else {
+            println!("  keeps up with any framerate");
+        }
+    }
+}
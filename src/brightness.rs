@@ -0,0 +1,170 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::DimmerError;
+
+/// A raw brightness value, as understood by the device it was read from.
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Brightness(pub u64);
+
+impl std::fmt::Display for Brightness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::str::FromStr for Brightness {
+    type Err = DimmerError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Ok(input.parse::<u64>().map(Brightness)?)
+    }
+}
+
+impl Brightness {
+    /// Parse either an absolute brightness or a percentage (e.g. "30%") of `max`. Prefixed with a
+    /// "+" or "-" (e.g. "+10%" or "-200"), the brightness is relative to `current` and clamped
+    /// between 0 and `max`.
+    ///
+    /// The keywords "max", "min" (the lowest brightness that isn't off) and "off" are accepted as
+    /// well.
+    pub fn parse_with_percentage(
+        input: &str,
+        current: Brightness,
+        max: Brightness,
+    ) -> Result<Brightness> {
+        match input {
+            "max" => return Ok(max),
+            "min" => return Ok(Brightness(max.0.min(1))),
+            "off" => return Ok(Brightness(0)),
+            _ => {}
+        }
+        if let Some(relative) = input.strip_prefix('+') {
+            let delta = Brightness::parse_absolute(relative, max)?;
+            return Ok(Brightness(current.0.saturating_add(delta.0).min(max.0)));
+        }
+        if let Some(relative) = input.strip_prefix('-') {
+            let delta = Brightness::parse_absolute(relative, max)?;
+            return Ok(Brightness(current.0.saturating_sub(delta.0).min(max.0)));
+        }
+        Brightness::parse_absolute(input, max)
+    }
+
+    fn parse_absolute(input: &str, max: Brightness) -> Result<Brightness> {
+        match input.strip_suffix('%') {
+            Some(percentage) => {
+                let percentage = percentage
+                    .parse::<f64>()
+                    .map_err(|_| DimmerError::InvalidPercentage)?;
+                if !(0.0..=100.0).contains(&percentage) {
+                    return Err(DimmerError::InvalidPercentage.into());
+                }
+                Ok(Brightness::from_percentage(percentage, max))
+            }
+            None => Ok(input
+                .parse::<u64>()
+                .map(Brightness)
+                .map_err(DimmerError::InvalidBrightness)?),
+        }
+    }
+
+    /// The brightness closest to `percentage` (which may be fractional) of `max`.
+    pub fn from_percentage(percentage: f64, max: Brightness) -> Brightness {
+        Brightness((percentage / 100.0 * max.0 as f64).round() as u64)
+    }
+
+    /// This brightness as a percentage of `max`.
+    pub fn percentage_of(&self, max: Brightness) -> f64 {
+        if max.0 == 0 {
+            return 0.0;
+        }
+        self.0 as f64 / max.0 as f64 * 100.0
+    }
+
+    /// Read a brightness from a file containing a single number, like the files in
+    /// `/sys/class/backlight/*/`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Brightness> {
+        let path = path.as_ref();
+        let res = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .trim()
+            .parse()
+            .with_context(|| format!("Failed to parse brightness from {}", path.display()))?;
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<Brightness> {
+        Brightness::parse_with_percentage(input, Brightness(500), Brightness(1000))
+    }
+
+    #[test]
+    fn parses_relative_brightness() {
+        assert_eq!(parse("+10%").unwrap(), Brightness(600));
+        assert_eq!(parse("-10%").unwrap(), Brightness(400));
+        assert_eq!(parse("+200").unwrap(), Brightness(700));
+        assert_eq!(parse("-200").unwrap(), Brightness(300));
+        assert_eq!(parse("+80%").unwrap(), Brightness(1000));
+        assert_eq!(parse("-80%").unwrap(), Brightness(0));
+    }
+
+    #[test]
+    fn rejects_invalid_relative_brightness() {
+        for input in ["+", "-", "+-5", "--5", "+abc", "-101%"] {
+            assert!(parse(input).is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn parses_keywords() {
+        assert_eq!(parse("max").unwrap(), Brightness(1000));
+        assert_eq!(parse("min").unwrap(), Brightness(1));
+        assert_eq!(parse("off").unwrap(), Brightness(0));
+        let off = Brightness::parse_with_percentage("min", Brightness(0), Brightness(0));
+        assert_eq!(off.unwrap(), Brightness(0));
+        for input in ["Max", "MIN", "+max", "maximum"] {
+            assert!(parse(input).is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn parses_absolute_brightness() {
+        assert_eq!(parse("0").unwrap(), Brightness(0));
+        assert_eq!(parse("300").unwrap(), Brightness(300));
+        assert_eq!(parse("30%").unwrap(), Brightness(300));
+        assert_eq!(parse("12.34%").unwrap(), Brightness(123));
+        assert_eq!(parse("100%").unwrap(), Brightness(1000));
+    }
+
+    #[test]
+    fn rejects_invalid_percentages() {
+        for input in ["%", "abc%", "101%", "+101%", "-0.5%%", "NaN%", "inf%"] {
+            assert!(
+                matches!(
+                    parse(input).unwrap_err().downcast_ref(),
+                    Some(DimmerError::InvalidPercentage)
+                ),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_brightness() {
+        for input in ["", "abc", "1.5", " 5"] {
+            assert!(
+                matches!(
+                    parse(input).unwrap_err().downcast_ref(),
+                    Some(DimmerError::InvalidBrightness(_))
+                ),
+                "{input}"
+            );
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use anyhow::{bail, Context, Result};
+use dimmer::{Brightness, Curve, Device};
+use std::io::{BufRead, Write};
+use std::time::Duration;
+
+/// How long looking back shows the step before.
+const LOOK_BACK: Duration = Duration::from_secs(1);
+
+/// Step `device` up from off to full brightness in `steps` steps, asking on `input` how to adjust
+/// every step until it looks as big as the others. The steps start out where `start` puts them,
+/// and the brightness the device was at is restored afterwards.
+///
+/// Returns the points of the calibrated curve: pairs of the perceived brightness of every step
+/// and the raw brightness that looks like it, both in percent.
+pub fn calibrate<R: BufRead>(
+    device: &mut Device,
+    steps: u64,
+    start: &Curve,
+    input: R,
+) -> Result<Vec<(f64, f64)>> {
+    let current = device.current()?;
+    let maximum = device.max()?;
+    if steps < 2 {
+        bail!("Calibrating takes at least 2 steps");
+    }
+    if maximum.0 < steps {
+        bail!(
+            "{} has too few brightness levels to calibrate in {steps} steps",
+            device.name()
+        );
+    }
+
+    println!(
+        "Calibrating {}, from off to full brightness in {steps} steps that should each look like \
+         the same step up. For every step, type + to brighten it or - to dim it (++ or -- for \
+         bigger changes), b to look back at the step before, and just Enter once it looks right.",
+        device.name()
+    );
+    let result = steps_through(device, steps, maximum, start, input);
+    device.set(current)?;
+    result
+}
+
+fn steps_through<R: BufRead>(
+    device: &mut Device,
+    steps: u64,
+    maximum: Brightness,
+    start: &Curve,
+    input: R,
+) -> Result<Vec<(f64, f64)>> {
+    let mut lines = input.lines();
+    let mut points = Vec::new();
+    let mut previous = Brightness(0);
+    for step in 1..steps {
+        let perceived = step as f64 / steps as f64;
+        // Every step has to be brighter than the one before, and leave a level for every step
+        // after it.
+        let lowest = previous.0 + 1;
+        let highest = maximum.0 - (steps - step);
+        let mut level =
+            ((start.raw(perceived) * maximum.0 as f64).round() as u64).clamp(lowest, highest);
+        loop {
+            device.set(Brightness(level))?;
+            print!(
+                "Step {step} of {steps}, at {level} ({:.1}%): ",
+                Brightness(level).percentage_of(maximum)
+            );
+            std::io::stdout().flush()?;
+            let line = match lines.next() {
+                Some(line) => line.context("Failed to read the answer")?,
+                None => bail!("Calibrating was cut short"),
+            };
+            match line.trim() {
+                "" => break,
+                "b" => {
+                    device.set(previous)?;
+                    std::thread::sleep(LOOK_BACK);
+                }
+                answer if answer.chars().all(|c| c == '+') => {
+                    level = (level + nudge(level, answer.len())).min(highest);
+                }
+                answer if answer.chars().all(|c| c == '-') => {
+                    level = level.saturating_sub(nudge(level, answer.len())).max(lowest);
+                }
+                _ => println!("Type +, -, b or just Enter"),
+            }
+        }
+        // Two decimals is finer than anyone can tell apart, and keeps the configuration tidy.
+        let raw = (Brightness(level).percentage_of(maximum) * 100.0).round() / 100.0;
+        points.push((perceived * 100.0, raw));
+        previous = Brightness(level);
+    }
+    Ok(points)
+}
+
+/// How many levels to move a step at `level` for a nudge of `size` pluses or minuses: a tenth of
+/// the level for every one of them, and at least a level.
+fn nudge(level: u64, size: usize) -> u64 {
+    (level * size as u64 / 10).max(1)
+}
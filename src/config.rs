@@ -0,0 +1,387 @@
+use anyhow::{bail, Context, Result};
+use dimmer::{Brightness, Curve};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::als;
+use crate::quirks::Quirks;
+use crate::schedule::TimeOfDay;
+
+/// Defaults for the command line options, read from `$XDG_CONFIG_HOME/dimmer/config.toml`.
+/// Options given on the command line take precedence.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// The lowest brightness to transition to, as for `--min`.
+    pub min: Option<String>,
+    /// How old a saved brightness may be to restore it, as for `restore --max-age`.
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub max_age: Option<Duration>,
+    /// The brightness to restore when the saved one is too old, as for `restore --fallback`.
+    pub fallback: Option<String>,
+    /// Transitions bundled under a name, for `dimmer preset`.
+    pub preset: BTreeMap<String, Preset>,
+    /// Where to keep the saved brightness, as for `--state-file`.
+    pub state_file: Option<PathBuf>,
+    /// The brightness the daemon transitions to when switching power source, by the name of the
+    /// power source: "ac" or "battery".
+    pub profiles: BTreeMap<String, Profile>,
+    /// When the daemon dims an idle session.
+    pub idle: Option<Idle>,
+    /// How the daemon dims a locked session.
+    pub lock: Option<Dim>,
+    /// How the daemon dims while the lid is closed.
+    pub lid: Option<Dim>,
+    /// Following the ambient light sensor.
+    pub als: Option<Als>,
+    /// The brightness the daemon transitions to at times of the day.
+    pub schedule: Option<Schedule>,
+    /// The brightness the daemon transitions to at sunrise and sunset, instead of a schedule.
+    pub sun: Option<Sun>,
+    /// Devices following along whenever another is transitioned, by name.
+    pub links: BTreeMap<String, Link>,
+    /// Following the brightness of one device with others.
+    pub mirror: Option<Mirror>,
+    /// How long the daemon takes to bring back the brightness after the system wakes up.
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub resume_duration: Option<Duration>,
+    /// Publishing the brightness to an MQTT broker, and taking commands from it.
+    pub mqtt: Option<Mqtt>,
+    /// Where the daemon serves its HTTP API, as for `daemon --http`.
+    pub http: Option<String>,
+    /// Workarounds for the hardware of devices, by a glob pattern matching their names, on top of
+    /// the quirks dimmer knows of.
+    pub quirks: BTreeMap<String, Quirks>,
+    /// How bright the levels of devices look, by the name of the device, as `dimmer calibrate`
+    /// measures it. Transitions of these devices fade along it at a visually constant pace.
+    pub calibration: BTreeMap<String, Curve>,
+    /// How the brightness of devices maps onto their raw brightness, by the name of the device,
+    /// as an exponent or points of a brightness and the raw brightness for it, both in percent.
+    /// Brightness values and percentages of these devices are on this logical scale.
+    pub mapping: BTreeMap<String, Curve>,
+}
+
+/// Dimming the session when it's idle, and restoring the brightness on activity.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Idle {
+    /// How long the session has to be idle before dimming, e.g. "5m".
+    #[serde(deserialize_with = "duration")]
+    pub timeout: Duration,
+    /// The brightness to dim to, off if not given.
+    pub target: Option<String>,
+    /// How long dimming takes, instead of the default.
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub duration: Option<Duration>,
+    /// How long restoring takes, instead of the default.
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub restore_duration: Option<Duration>,
+}
+
+/// What to transition to when a profile applies.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Profile {
+    /// The brightness to transition to, as for `dimmer set`.
+    pub target: String,
+    /// How long the transition takes, instead of the default.
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub duration: Option<Duration>,
+}
+
+/// A target, duration and easing bundled under a name, as in `[preset.movie]`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Preset {
+    /// The brightness to transition to, as for `dimmer set`.
+    pub target: String,
+    /// How long the transition takes, as for `--duration`.
+    pub duration: Option<String>,
+    /// The easing of the transition, as for `--ease`.
+    pub ease: Option<String>,
+}
+
+impl Config {
+    /// Read the configuration from `path`, or from the default location if not given. It's fine
+    /// for the file at the default location not to exist.
+    pub fn load(path: Option<&Path>) -> Result<Config> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_path()? {
+                Some(path) => path,
+                None => return Ok(Config::default()),
+            },
+        };
+        let config = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let config: Config = toml::from_str(&config)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        if config.schedule.is_some() && config.sun.is_some() {
+            bail!(
+                "Failed to load {}: configure either a schedule or the sun, not both",
+                path.display()
+            );
+        }
+        if let Some(pattern) = config
+            .quirks
+            .keys()
+            .find(|pattern| glob::Pattern::new(pattern).is_err())
+        {
+            bail!(
+                "Failed to load {}: {pattern:?} isn't a valid pattern for quirks",
+                path.display()
+            );
+        }
+        if let Some(als) = &config.als {
+            if als
+                .max_lux
+                .is_some_and(|max_lux| !(max_lux.is_finite() && max_lux > 0.0))
+            {
+                bail!(
+                    "Failed to load {}: the ambient light's max-lux has to be above 0",
+                    path.display()
+                );
+            }
+            if als
+                .hysteresis
+                .is_some_and(|hysteresis| !(hysteresis.is_finite() && hysteresis >= 0.0))
+            {
+                bail!(
+                    "Failed to load {}: the ambient light's hysteresis has to be 0 or above",
+                    path.display()
+                );
+            }
+        }
+        if let Some(sun) = &config.sun {
+            if sun.latitude.is_some() != sun.longitude.is_some() {
+                bail!(
+                    "Failed to load {}: give both the latitude and longitude, or neither to ask GeoClue",
+                    path.display()
+                );
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Write the calibration `points` of the device called `name` to the configuration file at
+/// `path`, or at the default location if not given, creating it if needed. The rest of the file,
+/// comments and all, stays as it is. Returns where the file is.
+pub fn save_calibration(path: Option<&Path>, name: &str, points: &[(f64, f64)]) -> Result<PathBuf> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => new_path()?,
+    };
+    let config = match std::fs::read_to_string(&path) {
+        Ok(config) => config,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let mut config: toml_edit::DocumentMut = config
+        .parse()
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    let calibration = config
+        .entry("calibration")
+        .or_insert_with(toml_edit::table)
+        .as_table_like_mut()
+        .with_context(|| {
+            format!(
+                "Failed to update {}: calibration isn't a table",
+                path.display()
+            )
+        })?;
+    let points: toml_edit::Array = points
+        .iter()
+        .map(|(perceived, raw)| toml_edit::Array::from_iter([*perceived, *raw]))
+        .collect();
+    calibration.insert(name, toml_edit::value(points));
+    std::fs::write(&path, config.to_string())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// The configuration file at its default location in `$XDG_CONFIG_HOME/dimmer`, if it exists.
+#[cfg(unix)]
+fn default_path() -> Result<Option<PathBuf>> {
+    let dirs = xdg::BaseDirectories::with_prefix("dimmer")
+        .context("Failed to setup XDG base directories")?;
+    Ok(dirs.find_config_file("config.toml"))
+}
+
+/// The configuration file at its default location in `%APPDATA%\dimmer`, if it exists.
+#[cfg(windows)]
+fn default_path() -> Result<Option<PathBuf>> {
+    let app_data = std::env::var_os("APPDATA").context("Failed to find %APPDATA%")?;
+    let path = PathBuf::from(app_data).join("dimmer").join("config.toml");
+    Ok(path.exists().then_some(path))
+}
+
+/// Where to create the configuration file in `$XDG_CONFIG_HOME/dimmer`, creating the directory.
+#[cfg(unix)]
+fn new_path() -> Result<PathBuf> {
+    let dirs = xdg::BaseDirectories::with_prefix("dimmer")
+        .context("Failed to setup XDG base directories")?;
+    dirs.place_config_file("config.toml")
+        .context("Failed to create the configuration directory")
+}
+
+/// Where to create the configuration file in `%APPDATA%\dimmer`, creating the directory.
+#[cfg(windows)]
+fn new_path() -> Result<PathBuf> {
+    let app_data = std::env::var_os("APPDATA").context("Failed to find %APPDATA%")?;
+    let dir = PathBuf::from(app_data).join("dimmer");
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    Ok(dir.join("config.toml"))
+}
+
+/// Dimming while something is going on, like the session being locked, and restoring the
+/// brightness once it's over.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Dim {
+    /// The brightness to dim to, off if not given.
+    pub target: Option<String>,
+    /// How long dimming takes, instead of the default.
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub duration: Option<Duration>,
+    /// How long restoring takes, instead of the default.
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub restore_duration: Option<Duration>,
+}
+
+/// Following the illuminance measured by the ambient light sensor with the brightness.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Als {
+    /// The illuminance at which we go to the maximum brightness, 1000 lux if not given.
+    pub max_lux: Option<f64>,
+    /// Pairs of an illuminance and the percentage of the maximum brightness for it, instead of
+    /// following the logarithm of the illuminance up to `max_lux`.
+    pub curve: Option<als::Curve>,
+    /// How many percent the brightness for the ambient light has to change before we follow it.
+    pub hysteresis: Option<f64>,
+    /// How long it takes to follow a change of the ambient light about two thirds of the way, to
+    /// even out flickering lamps and passing clouds.
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub smoothing: Option<Duration>,
+    /// How long the transitions take, instead of the default.
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub duration: Option<Duration>,
+}
+
+/// Transitioning to a brightness at given times of the day.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Schedule {
+    /// The brightness to transition to by the time of day the transition starts at, e.g.
+    /// `"21:00" = "30%"`.
+    pub levels: BTreeMap<TimeOfDay, String>,
+    /// How long the transitions take, an hour if not given.
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub duration: Option<Duration>,
+}
+
+/// Transitioning between a day and a night brightness around sunrise and sunset.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Sun {
+    /// Where we are, in degrees north. We ask GeoClue if not given.
+    pub latitude: Option<f64>,
+    /// Where we are, in degrees east. We ask GeoClue if not given.
+    pub longitude: Option<f64>,
+    /// The brightness during the day.
+    pub day: String,
+    /// The brightness during the night.
+    pub night: String,
+    /// How long the transitions around sunrise and sunset take, 40 minutes if not given.
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub twilight: Option<Duration>,
+}
+
+/// Transitioning a device along with another, to a brightness percentage in proportion to the
+/// other's, e.g. an external monitor at 80% of the laptop's panel.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Link {
+    /// The device to follow, by name.
+    pub primary: String,
+    /// What to multiply the brightness percentage of the primary device by, 1 if not given.
+    pub ratio: Option<f64>,
+    /// How many percent to add to that, none if not given.
+    pub offset: Option<f64>,
+}
+
+impl Link {
+    /// The brightness out of `maximum` for the primary device going to `target` out of
+    /// `primary_maximum`.
+    pub fn follow(
+        &self,
+        target: Brightness,
+        primary_maximum: Brightness,
+        maximum: Brightness,
+    ) -> Brightness {
+        let percent = match primary_maximum.0 {
+            0 => 0.0,
+            primary_maximum => target.0 as f64 * 100.0 / primary_maximum as f64,
+        };
+        let percent = percent * self.ratio.unwrap_or(1.0) + self.offset.unwrap_or(0.0);
+        Brightness::from_percentage(percent.clamp(0.0, 100.0), maximum)
+    }
+}
+
+/// Following the brightness percentage of one device with others, e.g. the keyboard backlight or
+/// an external monitor with the laptop's panel.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Mirror {
+    /// The device to follow, by name.
+    pub source: String,
+    /// The devices following it, by name.
+    pub devices: BTreeMap<String, Mirrored>,
+}
+
+/// How a device follows the brightness of another.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Mirrored {
+    /// How long it takes to catch up with a change, 300ms if not given.
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub smoothing: Option<Duration>,
+}
+
+/// Publishing the brightness to an MQTT broker and taking commands from it, as a light Home
+/// Assistant discovers.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+#[cfg_attr(not(feature = "mqtt"), allow(dead_code))]
+pub struct Mqtt {
+    /// The host name or address of the broker.
+    pub host: String,
+    /// The port the broker listens on, 1883 if not given.
+    pub port: Option<u16>,
+    /// Who to log in to the broker as, if it asks.
+    pub username: Option<String>,
+    /// The password to log in to the broker with.
+    pub password: Option<String>,
+    /// The topic the state is published under, with commands taken at its `set` subtopic,
+    /// "dimmer/<hostname>" if not given.
+    pub topic: Option<String>,
+    /// Whether to announce the screen to Home Assistant, true if not given.
+    pub discovery: Option<bool>,
+    /// The topic prefix Home Assistant discovers devices under, "homeassistant" if not given.
+    pub discovery_prefix: Option<String>,
+}
+
+/// Deserialize a human readable duration, e.g. "5m".
+pub fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let duration = String::deserialize(deserializer)?;
+    humantime::parse_duration(&duration).map_err(serde::de::Error::custom)
+}
+
+fn optional_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    duration(deserializer).map(Some)
+}
@@ -0,0 +1,122 @@
+use serde::Deserialize;
+
+/// How bright the brightness levels of a device look, mapping between the raw brightness and the
+/// perceived brightness, both as a fraction (0.0 to 1.0) of the maximum.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "Configured")]
+pub enum Curve {
+    /// The CIE L* lightness, which is how bright a white of a given luminance looks.
+    Lightness,
+    /// Pairs of a perceived brightness and the raw brightness that looks that bright, both in
+    /// percent of the maximum, as `dimmer calibrate` measures them. Off looks off and full
+    /// brightness looks like full brightness, and between the points we interpolate linearly.
+    Points(Vec<(f64, f64)>),
+    /// The raw brightness is the perceived brightness to the power of this, like the gamma of a
+    /// display.
+    Exponent(f64),
+}
+
+/// A curve as it's written in the configuration: either the exponent or the points.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Configured {
+    Exponent(f64),
+    Points(Vec<(f64, f64)>),
+}
+
+impl TryFrom<Configured> for Curve {
+    type Error = String;
+
+    fn try_from(configured: Configured) -> Result<Self, Self::Error> {
+        match configured {
+            Configured::Exponent(exponent) if exponent.is_finite() && exponent > 0.0 => {
+                Ok(Curve::Exponent(exponent))
+            }
+            Configured::Exponent(_) => Err("the curve's exponent has to be above 0".to_string()),
+            Configured::Points(points) => Curve::try_from(points),
+        }
+    }
+}
+
+impl TryFrom<Vec<(f64, f64)>> for Curve {
+    type Error = String;
+
+    fn try_from(points: Vec<(f64, f64)>) -> Result<Self, Self::Error> {
+        if points.is_empty() {
+            return Err("the curve needs at least one point".to_string());
+        }
+        if points.iter().any(|(perceived, raw)| {
+            !(0.0..=100.0).contains(perceived) || !(0.0..=100.0).contains(raw)
+        }) {
+            return Err("the curve's percentages have to be between 0 and 100".to_string());
+        }
+        if points
+            .windows(2)
+            .any(|pair| pair[0].0 >= pair[1].0 || pair[0].1 >= pair[1].1)
+        {
+            return Err("both percentages of the curve's points have to go up".to_string());
+        }
+        Ok(Curve::Points(points))
+    }
+}
+
+impl Curve {
+    /// How bright the `raw` brightness looks.
+    pub fn perceived(&self, raw: f64) -> f64 {
+        match self {
+            Curve::Lightness => lightness(raw) / 100.0,
+            Curve::Points(points) => {
+                interpolate(
+                    points.iter().map(|(perceived, raw)| (*raw, *perceived)),
+                    raw * 100.0,
+                ) / 100.0
+            }
+            Curve::Exponent(exponent) => raw.max(0.0).powf(1.0 / exponent),
+        }
+    }
+
+    /// The raw brightness that looks `perceived` bright, the inverse of `perceived`.
+    pub fn raw(&self, perceived: f64) -> f64 {
+        match self {
+            Curve::Lightness => luminance(perceived * 100.0),
+            Curve::Points(points) => interpolate(points.iter().copied(), perceived * 100.0) / 100.0,
+            Curve::Exponent(exponent) => perceived.max(0.0).powf(*exponent),
+        }
+    }
+}
+
+/// The value at `x` on the line through `points` of an x and y percentage, going from 0 to 100 on
+/// both ends. The points have to go up along both axes.
+fn interpolate(points: impl Iterator<Item = (f64, f64)>, x: f64) -> f64 {
+    let mut last = (0.0, 0.0);
+    for (to_x, to_y) in points.chain(std::iter::once((100.0, 100.0))) {
+        if x <= to_x {
+            let (from_x, from_y) = last;
+            if to_x <= from_x {
+                return to_y;
+            }
+            return from_y + (to_y - from_y) * (x - from_x) / (to_x - from_x);
+        }
+        last = (to_x, to_y);
+    }
+    100.0
+}
+
+/// The CIE L* lightness (0 to 100) we perceive for relative luminance `y` (0.0 to 1.0).
+fn lightness(y: f64) -> f64 {
+    if y > 216.0 / 24389.0 {
+        116.0 * y.cbrt() - 16.0
+    } else {
+        y * 24389.0 / 27.0
+    }
+}
+
+/// The relative luminance (0.0 to 1.0) perceived as CIE L* lightness `l`, the inverse of
+/// `lightness`.
+fn luminance(l: f64) -> f64 {
+    if l > 8.0 {
+        ((l + 16.0) / 116.0).powi(3)
+    } else {
+        l * 27.0 / 24389.0
+    }
+}
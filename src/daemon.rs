@@ -0,0 +1,1190 @@
+use anyhow::{bail, Context, Result};
+use dimmer::{Backend, Brightness, Control, Device, Easing, Outcome, Status, Transition};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, BufReader, Write};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread::JoinHandle;
+use std::time::Duration;
+#[cfg(windows)]
+use uds_windows::{UnixListener, UnixStream};
+
+use crate::config::{Config, Profile};
+use crate::hotplug::{self, Hotplug};
+use crate::metrics::Metrics;
+use crate::schedule::{self, TimeOfDay};
+use crate::sun::{self, Daylight};
+use crate::{als, instance, lid, mirror, power, signals, systemd, TransitionOpt};
+
+/// How long we take to bring back the brightness after waking up, unless configured otherwise.
+const RESUME_DURATION: Duration = Duration::from_millis(500);
+
+/// The illuminance at which we follow the ambient light sensor to the maximum brightness, unless
+/// configured otherwise.
+const DEFAULT_MAX_LUX: f64 = 1000.0;
+
+/// How many percent the brightness for the ambient light has to change before we follow it,
+/// unless configured otherwise.
+const DEFAULT_HYSTERESIS: f64 = 1.0;
+
+/// How long the transitions between the scheduled levels take, unless configured otherwise.
+const SCHEDULE_DURATION: Duration = Duration::from_secs(60 * 60);
+
+/// How long the mirrored devices take to catch up with a change of their source, unless configured
+/// otherwise.
+const MIRROR_SMOOTHING: Duration = Duration::from_millis(300);
+
+/// How long the transitions around sunrise and sunset take, unless configured otherwise.
+const TWILIGHT: Duration = Duration::from_secs(40 * 60);
+
+/// A command sent to the daemon, as a single line of JSON.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Request {
+    /// Transition to `target`, like `dimmer set`.
+    Set {
+        target: String,
+        duration: Option<String>,
+    },
+    /// Remember the current brightness unless we're dimmed already, and transition to `target`.
+    Dim {
+        #[serde(default = "off")]
+        target: String,
+        duration: Option<String>,
+    },
+    /// Transition back to the brightness remembered when dimming.
+    Restore { duration: Option<String> },
+    /// Report the brightness of the devices.
+    Status,
+    /// Read the configuration file again. Running transitions carry on as they were.
+    Reload,
+}
+
+fn off() -> String {
+    "0".to_string()
+}
+
+/// The daemon's answer to a request, as a single line of JSON.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub devices: Option<Vec<Status>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dimmed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transitioning: Option<bool>,
+}
+
+/// Where the daemon listens by default.
+pub fn socket_path() -> PathBuf {
+    instance::runtime_file("dimmer.sock")
+}
+
+/// Send `request` to the daemon listening on the socket at `path`, and wait for its response.
+pub fn request(path: &Path, request: &Request) -> Result<Response> {
+    let mut stream = UnixStream::connect(path).with_context(|| {
+        format!(
+            "Failed to connect to the daemon at {}, is `dimmer daemon` running?",
+            path.display()
+        )
+    })?;
+    writeln!(stream, "{}", serde_json::to_string(request)?)?;
+    let mut response = String::new();
+    BufReader::new(stream)
+        .read_line(&mut response)
+        .context("Failed to read the daemon's response")?;
+    let response: Response =
+        serde_json::from_str(&response).context("Failed to parse the daemon's response")?;
+    if !response.ok {
+        bail!(
+            "{}",
+            response
+                .error
+                .as_deref()
+                .unwrap_or("The daemon failed to handle the request")
+        );
+    }
+    Ok(response)
+}
+
+/// Keeps the devices open and the brightness to restore in memory between requests, so requests
+/// don't pay for discovering and opening the devices.
+pub struct Daemon {
+    devices: Vec<Arc<Mutex<Device>>>,
+    transition: TransitionOpt,
+    config: Config,
+    config_path: Option<PathBuf>,
+    saved: HashMap<String, Brightness>,
+    /// Whether we dimmed because the session went idle.
+    idle_dimmed: bool,
+    /// Whether we dimmed because the session was locked.
+    lock_dimmed: bool,
+    /// Whether we dimmed because the lid was closed.
+    lid_dimmed: bool,
+    /// The brightness of the devices when the system went to sleep, to bring back on waking up.
+    before_sleep: HashMap<String, Brightness>,
+    /// Whether we paused the running transition for the system to sleep.
+    paused_for_sleep: bool,
+    /// The illuminance the ambient light sensor measured, smoothed.
+    als_lux: Option<f64>,
+    /// The percentage we last followed the ambient light sensor to.
+    als_percent: Option<f64>,
+    /// Where we are, as GeoClue last told us.
+    location: Option<(f64, f64)>,
+    /// The scheduled level we last transitioned to.
+    scheduled: Option<String>,
+    running: Option<Running>,
+    /// Opens devices by name with the selected backend.
+    open: Open,
+    /// The mirrored devices we opened ourselves, as they're not among the devices.
+    mirrored: HashMap<String, Arc<Mutex<Device>>>,
+    /// The transitions of the mirrored devices catching up with their source, by device.
+    mirroring: HashMap<String, Running>,
+    listeners: Vec<Listener>,
+    metrics: Arc<Metrics>,
+    /// The daemon itself, for the watchers started when the configuration is reloaded.
+    this: Weak<Mutex<Daemon>>,
+    /// What we watch already, so reloading the configuration only starts what's new.
+    watching: Watching,
+}
+
+/// The watchers the daemon started. They keep running for as long as the daemon does, doing
+/// nothing while their part of the configuration is gone.
+#[derive(Debug, Default)]
+struct Watching {
+    idle: bool,
+    lid: bool,
+    als: bool,
+    mirror: bool,
+    location: bool,
+    schedule: bool,
+    lock: bool,
+}
+
+/// Opens a device that was plugged in while the daemon runs, given its name, if it's one of the
+/// selected devices.
+pub type Discover = Box<dyn Fn(&str) -> Option<Device> + Send>;
+
+/// Opens a device by name with the selected backend.
+pub type Open = Arc<dyn Fn(&str) -> Result<Device> + Send + Sync>;
+
+/// How the daemon opens the devices it didn't start with.
+pub struct Opener {
+    /// Opens the devices plugged in while we run, if they're among the selected devices.
+    pub discover: Option<Discover>,
+    /// Opens any device by name, like the devices in the mirror.
+    pub open: Open,
+}
+
+/// Called with the status of a device whenever a transition of it ends.
+pub type Listener = Arc<dyn Fn(&Status) + Send + Sync>;
+
+/// A device to transition, its name and target, and the transition taking it there.
+type Fade = (Arc<Mutex<Device>>, String, Brightness, Transition);
+
+/// The transition the daemon is running, with a thread for the devices it started with and one
+/// for every device plugged in while it runs, so they're paused and stopped together.
+struct Running {
+    control: Arc<Control>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Running {
+    fn is_finished(&self) -> bool {
+        self.threads.iter().all(|thread| thread.is_finished())
+    }
+
+    /// Interrupt the transition, leaving the brightness where it is, and wait for it to stop.
+    fn stop(self) {
+        self.control.interrupt();
+        for thread in self.threads {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Serve requests on the socket at `path` for `devices`, with `transition` as the defaults for
+/// the transitions requested. With `dbus`, requests are served on the session bus as well, and
+/// with an `http` address in the `config`, over HTTP. The devices plugged in while we run are
+/// added to them as the `opener` discovers them, and other devices, like the ones in the mirror,
+/// are opened with it.
+///
+/// When socket activated by systemd, we serve the socket it passed us instead, and let it know
+/// when we're ready. On SIGHUP, we read the configuration at `config_path` (or the default
+/// location) again. When switching between mains and battery power, we transition to the
+/// profile configured for it, and when configured, we dim while the session is idle or locked
+/// or the lid is closed, follow the ambient light, transition to the brightness scheduled for
+/// the time of day or sunrise and sunset, or mirror the brightness of one device to others.
+pub fn run(
+    path: &Path,
+    devices: Vec<Device>,
+    opener: Opener,
+    transition: TransitionOpt,
+    config: Config,
+    config_path: Option<PathBuf>,
+    dbus: bool,
+) -> Result<()> {
+    let listener = match systemd::listener() {
+        Some(listener) => listener,
+        None => bind(path)?,
+    };
+
+    let daemon = Arc::new_cyclic(|this| {
+        Mutex::new(Daemon {
+            devices: devices
+                .into_iter()
+                .map(|device| Arc::new(Mutex::new(device)))
+                .collect(),
+            transition,
+            config,
+            config_path,
+            saved: HashMap::new(),
+            idle_dimmed: false,
+            lock_dimmed: false,
+            lid_dimmed: false,
+            before_sleep: HashMap::new(),
+            paused_for_sleep: false,
+            als_lux: None,
+            als_percent: None,
+            location: None,
+            scheduled: None,
+            running: None,
+            open: opener.open,
+            mirrored: HashMap::new(),
+            mirroring: HashMap::new(),
+            listeners: Vec::new(),
+            metrics: Arc::default(),
+            this: this.clone(),
+            watching: Watching::default(),
+        })
+    });
+
+    {
+        let daemon = daemon.clone();
+        signals::on_hangup(move || {
+            if let Err(err) = daemon.lock().unwrap().reload() {
+                tracing::error!("Failed to reload the configuration: {err:#}");
+            }
+        });
+    }
+
+    {
+        let daemon = daemon.clone();
+        power::watch(move |on_ac| {
+            if let Err(err) = daemon.lock().unwrap().power_changed(on_ac) {
+                tracing::error!("Failed to switch power profile: {err:#}");
+            }
+        });
+    }
+
+    if let Some(discover) = opener.discover {
+        let daemon = daemon.clone();
+        let watched = hotplug::watch(move |hotplug| {
+            let result = match hotplug {
+                Hotplug::Added(name) => match discover(&name) {
+                    Some(device) => daemon.lock().unwrap().add(device),
+                    None => Ok(()),
+                },
+                Hotplug::Removed(name) => {
+                    daemon.lock().unwrap().remove(&name);
+                    Ok(())
+                }
+            };
+            if let Err(err) = result {
+                tracing::error!("Failed to set up the new device: {err:#}");
+            }
+        });
+        if let Err(err) = watched {
+            tracing::error!("Failed to watch for new devices: {err:#}");
+        }
+    }
+
+    daemon.lock().unwrap().watch()?;
+    // Not every system has logind, and we do fine without it unless asked to do more.
+    #[cfg(feature = "logind")]
+    if let Err(err) = crate::logind::watch_sleep(daemon.clone()) {
+        tracing::error!("Failed to watch for the system going to sleep: {err:#}");
+    }
+
+    #[cfg(feature = "dbus")]
+    let _connection = match dbus {
+        true => Some(crate::dbus::serve(daemon.clone())?),
+        false => None,
+    };
+    #[cfg(not(feature = "dbus"))]
+    if dbus {
+        bail!("D-Bus support requires building dimmer with the `dbus` feature");
+    }
+    let http = daemon.lock().unwrap().config.http.clone();
+    if let Some(address) = http {
+        crate::http::serve(daemon.clone(), &address)?;
+    }
+    let mqtt = daemon.lock().unwrap().config.mqtt.clone();
+    if let Some(mqtt) = mqtt {
+        #[cfg(feature = "mqtt")]
+        crate::mqtt::start(daemon.clone(), &mqtt)?;
+        #[cfg(not(feature = "mqtt"))]
+        bail!(
+            "Connecting to {} requires building dimmer with the `mqtt` feature",
+            mqtt.host
+        );
+    }
+
+    systemd::notify_ready();
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                tracing::error!("Failed to accept connection: {err}");
+                continue;
+            }
+        };
+        let daemon = daemon.clone();
+        std::thread::spawn(move || {
+            if let Err(err) = serve(stream, &daemon) {
+                tracing::error!("Failed to serve connection: {err:#}");
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Dim the session while it's idle, as the compositor tells us through ext-idle-notify-v1 if it
+/// supports that, the X server through its screen saver extension, or logind's idle hint
+/// otherwise.
+fn watch_idle(daemon: Arc<Mutex<Daemon>>) -> Result<()> {
+    #[cfg_attr(
+        not(any(feature = "wayland", feature = "x11", feature = "logind")),
+        allow(unused_mut)
+    )]
+    let mut errors: Vec<String> = Vec::new();
+    #[cfg(feature = "wayland")]
+    match crate::wayland_idle::watch(daemon.clone()) {
+        Ok(()) => return Ok(()),
+        Err(err) => errors.push(format!("{err:#}")),
+    }
+    #[cfg(feature = "x11")]
+    match crate::x11_idle::watch(daemon.clone()) {
+        Ok(()) => return Ok(()),
+        Err(err) => errors.push(format!("{err:#}")),
+    }
+    #[cfg(feature = "logind")]
+    match crate::logind::watch_idle(daemon.clone()) {
+        Ok(()) => return Ok(()),
+        Err(err) => errors.push(format!("{err:#}")),
+    }
+    drop(daemon);
+    if errors.is_empty() {
+        bail!("Dimming when idle requires building dimmer with the `wayland`, `x11` or `logind` feature");
+    }
+    bail!(
+        "Failed to find out when the session is idle: {}",
+        errors.join(", ")
+    )
+}
+
+/// Listen on the socket at `path`, unless another daemon is listening on it already.
+pub fn bind(path: &Path) -> Result<UnixListener> {
+    if UnixStream::connect(path).is_ok() {
+        bail!("A daemon is already listening on {}", path.display());
+    }
+    // A socket no one listens on is left over from a daemon that didn't exit cleanly.
+    let _ = std::fs::remove_file(path);
+    UnixListener::bind(path).with_context(|| format!("Failed to listen on {}", path.display()))
+}
+
+/// Answer the requests on `stream` until the client hangs up.
+fn serve(stream: UnixStream, daemon: &Mutex<Daemon>) -> Result<()> {
+    let mut output = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = serde_json::from_str(&line)
+            .context("Failed to parse request")
+            .and_then(|request| daemon.lock().unwrap().handle(request))
+            .unwrap_or_else(|err| Response {
+                error: Some(format!("{err:#}")),
+                ..Response::default()
+            });
+        writeln!(output, "{}", serde_json::to_string(&response)?)?;
+    }
+    Ok(())
+}
+
+impl Daemon {
+    /// Call `listener` whenever a transition of a device ends.
+    #[cfg_attr(not(any(feature = "dbus", feature = "mqtt")), allow(dead_code))]
+    pub fn listen(&mut self, listener: Listener) {
+        self.listeners.push(listener);
+    }
+
+    /// The metrics in Prometheus' text format, for `GET /metrics`.
+    pub fn metrics(&self) -> String {
+        let statuses: Vec<Status> = self
+            .devices
+            .iter()
+            .filter_map(|device| device.lock().unwrap().status().ok())
+            .collect();
+        self.metrics.render(&statuses)
+    }
+
+    pub fn handle(&mut self, request: Request) -> Result<Response> {
+        tracing::info!(?request, "Handling request");
+        match request {
+            Request::Set { target, duration } => {
+                self.start(parse_duration(duration)?, |_device, current, maximum| {
+                    Brightness::parse_with_percentage(&target, current, maximum)
+                })?;
+            }
+            Request::Dim { target, duration } => self.dim(&target, parse_duration(duration)?)?,
+            Request::Restore { duration } => self.restore(parse_duration(duration)?)?,
+            Request::Status => {
+                let devices = self
+                    .devices
+                    .iter()
+                    .map(|device| device.lock().unwrap().status())
+                    .collect::<Result<Vec<_>>>()?;
+                return Ok(Response {
+                    ok: true,
+                    devices: Some(devices),
+                    dimmed: Some(!self.saved.is_empty()),
+                    transitioning: Some(
+                        self.running
+                            .as_ref()
+                            .is_some_and(|running| !running.is_finished()),
+                    ),
+                    ..Response::default()
+                });
+            }
+            Request::Reload => self.reload()?,
+        }
+        Ok(Response {
+            ok: true,
+            ..Response::default()
+        })
+    }
+
+    /// Read the configuration again, for the transitions started from now on. We start watching
+    /// whatever it asks us to watch that we didn't yet, and map the devices along their new
+    /// curves. HTTP and MQTT keep going as they were until the daemon is restarted.
+    fn reload(&mut self) -> Result<()> {
+        let config = Config::load(self.config_path.as_deref())?;
+        let restart = [
+            ("http", config.http != self.config.http),
+            ("mqtt", config.mqtt != self.config.mqtt),
+        ];
+        for (table, _) in restart.iter().filter(|(_, changed)| *changed) {
+            tracing::warn!("Changes to {table} take effect once the daemon is restarted");
+        }
+        self.config = config;
+        // The schedule may have changed, so check which level applies again.
+        self.scheduled = None;
+        for device in &self.devices {
+            self.remap(&mut device.lock().unwrap());
+        }
+        self.watch()
+    }
+
+    /// Start watching whatever the configuration asks us to that we don't watch yet: idleness,
+    /// the lid, the ambient light, the mirrored device, our location, the time of day and the
+    /// session's lock.
+    fn watch(&mut self) -> Result<()> {
+        let daemon = self.this.upgrade().context("The daemon is shutting down")?;
+        if self.config.idle.is_some() && !self.watching.idle {
+            watch_idle(daemon.clone())?;
+            self.watching.idle = true;
+        }
+        if self.config.lid.is_some() && !self.watching.lid {
+            if lid::is_closed().is_none() {
+                bail!("Failed to find a lid to watch");
+            }
+            let daemon = daemon.clone();
+            lid::watch(move |closed| {
+                if let Err(err) = daemon.lock().unwrap().set_lid_closed(closed) {
+                    tracing::error!("Failed to handle the lid: {err:#}");
+                }
+            });
+            self.watching.lid = true;
+        }
+        if self.config.als.is_some() && !self.watching.als {
+            let sensor = als::Sensor::find()?;
+            let daemon = daemon.clone();
+            als::watch(sensor, move |lux| {
+                if let Err(err) = daemon.lock().unwrap().ambient_light(lux) {
+                    tracing::error!("Failed to follow the ambient light: {err:#}");
+                }
+            });
+            self.watching.als = true;
+        }
+        if self.config.mirror.is_some() && !self.watching.mirror {
+            mirror::start(daemon.clone());
+            self.watching.mirror = true;
+        }
+        let locate = self
+            .config
+            .sun
+            .as_ref()
+            .is_some_and(|sun| sun.latitude.is_none());
+        if locate && !self.watching.location {
+            #[cfg(feature = "geoclue")]
+            {
+                crate::geoclue::watch(daemon.clone())?;
+                self.watching.location = true;
+            }
+            #[cfg(not(feature = "geoclue"))]
+            bail!("Finding our location requires building dimmer with the `geoclue` feature, or configure the latitude and longitude");
+        }
+        let scheduled = self.config.schedule.is_some() || self.config.sun.is_some();
+        if scheduled && !self.watching.schedule {
+            let daemon = daemon.clone();
+            schedule::watch(move |now| {
+                if let Err(err) = daemon.lock().unwrap().tick(now) {
+                    tracing::error!("Failed to follow the schedule: {err:#}");
+                }
+            });
+            self.watching.schedule = true;
+        }
+        if self.config.lock.is_some() && !self.watching.lock {
+            #[cfg(feature = "logind")]
+            {
+                crate::logind::watch_lock(daemon)?;
+                self.watching.lock = true;
+            }
+            #[cfg(not(feature = "logind"))]
+            bail!("Dimming when locked requires building dimmer with the `logind` feature");
+        }
+        Ok(())
+    }
+
+    /// The device the configured mirror follows, if any.
+    pub fn mirror_source(&self) -> Option<String> {
+        self.config
+            .mirror
+            .as_ref()
+            .map(|mirror| mirror.source.clone())
+    }
+
+    /// Open the device called `name` with the selected backend, mapped along its curve.
+    pub fn open(&self, name: &str) -> Result<Device> {
+        let mut device = (self.open)(name)?;
+        self.remap(&mut device);
+        Ok(device)
+    }
+
+    /// Have the devices in the configured mirror catch up with their source, which is at
+    /// `percent` of its maximum, each within its smoothing. Devices the running transition takes
+    /// somewhere are left to it, and devices that aren't there are skipped until they're back.
+    pub fn mirror(&mut self, percent: f64) -> Result<()> {
+        let Some(mirror) = self.config.mirror.clone() else {
+            return Ok(());
+        };
+        let running = self
+            .running
+            .as_ref()
+            .is_some_and(|running| !running.is_finished());
+        for (name, mirrored) in &mirror.devices {
+            if running && self.device(name).is_some() {
+                continue;
+            }
+            let Some(device) = self.mirrored(name) else {
+                continue;
+            };
+            if let Some(running) = self.mirroring.remove(name) {
+                running.stop();
+            }
+            let (current, maximum) = {
+                let device = device.lock().unwrap();
+                (device.current()?, device.max()?)
+            };
+            let mut transition = self.transition.for_device(name, &self.config);
+            transition.duration = mirrored.smoothing.unwrap_or(MIRROR_SMOOTHING).into();
+            transition.ease = Easing::EaseOut;
+            let target = Brightness::from_percentage(percent, maximum);
+            let target = transition.clamp(&self.config, target, current, maximum)?;
+            let fade = transition.fade(current, target, maximum);
+            let control = Arc::new(Control::new());
+            let thread = spawn(
+                vec![(device, name.clone(), target, fade)],
+                transition,
+                control.clone(),
+                self.listeners.clone(),
+                self.metrics.clone(),
+            );
+            let running = Running {
+                control,
+                threads: vec![thread],
+            };
+            self.mirroring.insert(name.clone(), running);
+        }
+        Ok(())
+    }
+
+    /// The device called `name`, if it's one of ours.
+    fn device(&self, name: &str) -> Option<Arc<Mutex<Device>>> {
+        self.devices
+            .iter()
+            .find(|device| device.lock().unwrap().name() == name)
+            .cloned()
+    }
+
+    /// The mirrored device called `name`, opening it if it isn't one of ours, unless it's not
+    /// there.
+    fn mirrored(&mut self, name: &str) -> Option<Arc<Mutex<Device>>> {
+        if let Some(device) = self.device(name) {
+            return Some(device);
+        }
+        if let Some(device) = self.mirrored.get(name) {
+            match device.lock().unwrap().current() {
+                Err(err) if dimmer::is_removed(&err) => {
+                    tracing::warn!("{name} went away, mirroring to it again once it's back");
+                }
+                _ => return Some(device.clone()),
+            }
+        }
+        self.mirrored.remove(name);
+        let device = Arc::new(Mutex::new(self.open(name).ok()?));
+        self.mirrored.insert(name.to_string(), device.clone());
+        Some(device)
+    }
+
+    /// Map `device` along its curve in the configuration, or not at all if it has none.
+    fn remap(&self, device: &mut Device) {
+        device.remap(self.config.mapping.get(device.name()).cloned());
+    }
+
+    /// Transition to the profile for the power source we switched to, if one is configured. When
+    /// dimmed, the profile is what we restore to instead.
+    fn power_changed(&mut self, on_ac: bool) -> Result<()> {
+        tracing::info!(on_ac, "Power source changed");
+        let Some(profile) = self.profile(on_ac).cloned() else {
+            return Ok(());
+        };
+        self.retarget(profile.duration, |current, maximum| {
+            Brightness::parse_with_percentage(&profile.target, current, maximum)
+        })
+    }
+
+    /// The profile configured for mains or battery power, if any.
+    fn profile(&self, on_ac: bool) -> Option<&Profile> {
+        let name = if on_ac { "ac" } else { "battery" };
+        self.config.profiles.get(name)
+    }
+
+    /// Start using `device`, which was just plugged in, and transition it to the scheduled level,
+    /// or else the profile for the power source, if either applies. When dimmed, that's what we
+    /// restore it to instead. The other devices carry on with what they're doing.
+    fn add(&mut self, device: Device) -> Result<()> {
+        let name = device.name().to_string();
+        if self
+            .devices
+            .iter()
+            .any(|known| known.lock().unwrap().name() == name)
+        {
+            return Ok(());
+        }
+        tracing::info!("Found {name}");
+        // The mappings may have changed since the device was opened.
+        let mut device = device;
+        self.remap(&mut device);
+        let device = Arc::new(Mutex::new(device));
+        self.devices.push(device.clone());
+
+        let profile = power::on_ac().and_then(|on_ac| self.profile(on_ac));
+        let (target, duration) = match (&self.scheduled, profile) {
+            (Some(level), _) => (level.clone(), None),
+            (None, Some(profile)) => (profile.target.clone(), profile.duration),
+            (None, None) => return Ok(()),
+        };
+        let (current, maximum) = {
+            let device = device.lock().unwrap();
+            (device.current()?, device.max()?)
+        };
+        let target = Brightness::parse_with_percentage(&target, current, maximum)?;
+        if !self.saved.is_empty() {
+            self.saved.insert(name, target);
+            return Ok(());
+        }
+        let transition = self.transition(duration).for_device(&name, &self.config);
+        let target = transition.clamp(&self.config, target, current, maximum)?;
+        let fade = transition.fade(current, target, maximum);
+        let fades = vec![(device, name, target, fade)];
+        match self
+            .running
+            .as_mut()
+            .filter(|running| !running.is_finished())
+        {
+            // Join the running transition, so it's paused and stopped along with it.
+            Some(running) => running.threads.push(spawn(
+                fades,
+                transition,
+                running.control.clone(),
+                self.listeners.clone(),
+                self.metrics.clone(),
+            )),
+            None => self.run(fades, transition),
+        }
+        Ok(())
+    }
+
+    /// Stop using the device called `name`, which was just unplugged.
+    fn remove(&mut self, name: &str) {
+        self.devices.retain(|device| {
+            let removed = device.lock().unwrap().name() == name;
+            if removed {
+                tracing::warn!("{name} went away, carrying on without it");
+            }
+            !removed
+        });
+    }
+
+    /// Follow the illuminance of `lux` measured by the ambient light sensor, unless it changed too
+    /// little since the last time to bother.
+    fn ambient_light(&mut self, lux: f64) -> Result<()> {
+        let Some(als) = self.config.als.clone() else {
+            return Ok(());
+        };
+        let lux = match (self.als_lux, als.smoothing) {
+            (Some(last), Some(window)) => als::smooth(last, lux, window),
+            _ => lux,
+        };
+        self.als_lux = Some(lux);
+        let percent = match &als.curve {
+            Some(curve) => curve.percent(lux),
+            None => als::percent(lux, als.max_lux.unwrap_or(DEFAULT_MAX_LUX)),
+        };
+        let hysteresis = als.hysteresis.unwrap_or(DEFAULT_HYSTERESIS);
+        if !als::is_noticeable(self.als_percent, percent, hysteresis) {
+            return Ok(());
+        }
+        tracing::info!(lux, percent, "Following the ambient light");
+        self.als_percent = Some(percent);
+        // Even in the dark, the screen should stay readable.
+        self.retarget(als.duration, |_current, maximum| {
+            let brightness = Brightness::from_percentage(percent, maximum);
+            Ok(Brightness(brightness.0.max(maximum.0.min(1))))
+        })
+    }
+
+    /// Transition to the level scheduled for the time of day `now`, or for the time of day
+    /// relative to sunrise and sunset, when it's a different one than the last time. When we start
+    /// halfway through its transition, we take only as long as is left of it.
+    fn tick(&mut self, now: TimeOfDay) -> Result<()> {
+        let (levels, duration) = match (&self.config.schedule, &self.config.sun) {
+            (Some(schedule), _) => (
+                schedule.levels.clone(),
+                schedule.duration.unwrap_or(SCHEDULE_DURATION),
+            ),
+            // The transitions are centered around sunrise and sunset.
+            (None, Some(sun)) => {
+                let Some((latitude, longitude)) = sun.latitude.zip(sun.longitude).or(self.location)
+                else {
+                    // GeoClue hasn't told us where we are yet.
+                    return Ok(());
+                };
+                let twilight = sun.twilight.unwrap_or(TWILIGHT);
+                let levels = match sun::daylight(latitude, longitude)? {
+                    Daylight::Cycle { rise, set } => BTreeMap::from([
+                        (rise.before(twilight / 2), sun.day.clone()),
+                        (set.before(twilight / 2), sun.night.clone()),
+                    ]),
+                    Daylight::Day => BTreeMap::from([(TimeOfDay::MIDNIGHT, sun.day.clone())]),
+                    Daylight::Night => BTreeMap::from([(TimeOfDay::MIDNIGHT, sun.night.clone())]),
+                };
+                (levels, twilight)
+            }
+            (None, None) => return Ok(()),
+        };
+        let Some((time, target)) = schedule::current(&levels, now) else {
+            return Ok(());
+        };
+        // Sunrise and sunset move a little every day, so we go by the level rather than its time.
+        if self.scheduled.as_ref() == Some(target) {
+            return Ok(());
+        }
+        tracing::info!(%time, target, "Following the schedule");
+        self.scheduled = Some(target.clone());
+        let left = duration.saturating_sub(now.since(time));
+        let duration = (!left.is_zero()).then_some(left);
+        self.retarget(duration, |current, maximum| {
+            Brightness::parse_with_percentage(target, current, maximum)
+        })
+    }
+
+    /// Go by `latitude` and `longitude` for sunrise and sunset from now on.
+    #[cfg_attr(not(feature = "geoclue"), allow(dead_code))]
+    pub fn set_location(&mut self, latitude: f64, longitude: f64) -> Result<()> {
+        self.location = Some((latitude, longitude));
+        self.tick(TimeOfDay::now()?)
+    }
+
+    /// Transition to the brightness `target` picks given the current and maximum brightness, or
+    /// when dimmed, make it what we restore to instead.
+    fn retarget<F>(&mut self, duration: Option<Duration>, target: F) -> Result<()>
+    where
+        F: Fn(Brightness, Brightness) -> Result<Brightness>,
+    {
+        if !self.saved.is_empty() {
+            for device in &self.devices {
+                let device = device.lock().unwrap();
+                let max = device.max()?;
+                if let Some(saved) = self.saved.get_mut(device.name()) {
+                    *saved = target(*saved, max)?;
+                }
+            }
+            return Ok(());
+        }
+        self.start(duration, |_device, current, maximum| {
+            target(current, maximum)
+        })
+    }
+
+    /// How long the session has to be idle before we dim, if we dim idle sessions at all.
+    #[cfg_attr(not(feature = "wayland"), allow(dead_code))]
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.config.idle.as_ref().map(|idle| idle.timeout)
+    }
+
+    /// Dim when the session has been idle for longer than the configured timeout, and restore the
+    /// brightness when it's active again.
+    #[cfg_attr(not(any(feature = "logind", feature = "x11")), allow(dead_code))]
+    pub fn idle(&mut self, idle_for: Option<Duration>) -> Result<()> {
+        let Some(timeout) = self.idle_timeout() else {
+            return Ok(());
+        };
+        self.set_idle(idle_for.is_some_and(|idle_for| idle_for >= timeout))
+    }
+
+    /// Dim when the session went idle, and restore the brightness when it's active again.
+    #[cfg_attr(
+        not(any(feature = "logind", feature = "wayland", feature = "x11")),
+        allow(dead_code)
+    )]
+    pub fn set_idle(&mut self, is_idle: bool) -> Result<()> {
+        tracing::info!(is_idle, "Idleness changed");
+        let Some(idle) = self.config.idle.clone() else {
+            return Ok(());
+        };
+        let was_idle = std::mem::replace(&mut self.idle_dimmed, is_idle);
+        self.dim_while(
+            is_idle,
+            was_idle,
+            idle.target,
+            idle.duration,
+            idle.restore_duration,
+        )
+    }
+
+    /// Dim when the session is locked, and restore the brightness when it's unlocked.
+    #[cfg_attr(not(feature = "logind"), allow(dead_code))]
+    pub fn set_locked(&mut self, is_locked: bool) -> Result<()> {
+        tracing::info!(is_locked, "Lock changed");
+        let Some(lock) = self.config.lock.clone() else {
+            return Ok(());
+        };
+        let was_locked = std::mem::replace(&mut self.lock_dimmed, is_locked);
+        self.dim_while(
+            is_locked,
+            was_locked,
+            lock.target,
+            lock.duration,
+            lock.restore_duration,
+        )
+    }
+
+    /// Dim when the lid is closed, and restore the brightness when it's opened. This is for
+    /// setups where the panel stays on with the lid closed.
+    fn set_lid_closed(&mut self, is_closed: bool) -> Result<()> {
+        tracing::info!(is_closed, "Lid changed");
+        let Some(lid) = self.config.lid.clone() else {
+            return Ok(());
+        };
+        let was_closed = std::mem::replace(&mut self.lid_dimmed, is_closed);
+        self.dim_while(
+            is_closed,
+            was_closed,
+            lid.target,
+            lid.duration,
+            lid.restore_duration,
+        )
+    }
+
+    /// Pause the running transition when the system goes to sleep, and carry on with it when it
+    /// wakes up. Many panels come back at some default brightness, so if no transition was
+    /// running, we fade back to the brightness from before.
+    #[cfg_attr(not(feature = "logind"), allow(dead_code))]
+    pub fn prepare_for_sleep(&mut self, sleeping: bool) -> Result<()> {
+        tracing::info!(sleeping, "Preparing for sleep");
+        let running = self
+            .running
+            .as_ref()
+            .filter(|running| !running.is_finished());
+        if sleeping {
+            if let Some(running) = running.filter(|running| !running.control.is_paused()) {
+                running.control.toggle_pause();
+                self.paused_for_sleep = true;
+            }
+            self.before_sleep = self
+                .devices
+                .iter()
+                .map(|device| {
+                    let device = device.lock().unwrap();
+                    Ok((device.name().to_string(), device.current()?))
+                })
+                .collect::<Result<_>>()?;
+            return Ok(());
+        }
+
+        if std::mem::take(&mut self.paused_for_sleep) {
+            if let Some(running) = running.filter(|running| running.control.is_paused()) {
+                running.control.toggle_pause();
+                return Ok(());
+            }
+        }
+        let before_sleep = std::mem::take(&mut self.before_sleep);
+        if before_sleep.is_empty() {
+            return Ok(());
+        }
+        let duration = self.config.resume_duration.unwrap_or(RESUME_DURATION);
+        self.start(Some(duration), |device, current, _maximum| {
+            Ok(before_sleep.get(device).copied().unwrap_or(current))
+        })
+    }
+
+    /// Dim to `target` when something starts (`now` but not `before`), and restore the brightness
+    /// when it ends.
+    fn dim_while(
+        &mut self,
+        now: bool,
+        before: bool,
+        target: Option<String>,
+        duration: Option<Duration>,
+        restore_duration: Option<Duration>,
+    ) -> Result<()> {
+        match (now, before) {
+            (true, false) => self.dim(&target.unwrap_or_else(off), duration),
+            // Someone may have restored the brightness in the meantime.
+            (false, true) if !self.saved.is_empty() => self.restore(restore_duration),
+            _ => Ok(()),
+        }
+    }
+
+    /// Remember the current brightness unless we're dimmed already, and transition to `target`.
+    fn dim(&mut self, target: &str, duration: Option<Duration>) -> Result<()> {
+        self.stop();
+        if self.saved.is_empty() {
+            for device in &self.devices {
+                let device = device.lock().unwrap();
+                self.saved
+                    .insert(device.name().to_string(), device.current()?);
+            }
+        }
+        self.start(duration, |_device, current, maximum| {
+            Brightness::parse_with_percentage(target, current, maximum)
+        })
+    }
+
+    /// Transition back to the brightness remembered when dimming.
+    fn restore(&mut self, duration: Option<Duration>) -> Result<()> {
+        if self.saved.is_empty() {
+            bail!("There's no brightness to restore");
+        }
+        let saved = self.saved.clone();
+        self.start(duration, |device, current, _maximum| {
+            Ok(saved.get(device).copied().unwrap_or(current))
+        })?;
+        self.saved.clear();
+        Ok(())
+    }
+
+    /// Stop using the devices that went away, like unplugged monitors, so they don't fail every
+    /// request from now on.
+    fn drop_removed(&mut self) {
+        self.devices.retain(|device| {
+            let device = device.lock().unwrap();
+            match device.current() {
+                Err(err) if dimmer::is_removed(&err) => {
+                    tracing::warn!("{} went away, carrying on without it", device.name());
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+
+    /// Stop the running transition, if any, leaving the brightness where it is.
+    fn stop(&mut self) {
+        if let Some(running) = self.running.take() {
+            running.stop();
+        }
+    }
+
+    /// Transition every device to the brightness `target` picks for it given its name, current and
+    /// maximum brightness, taking over from the running transition.
+    fn start<F>(&mut self, duration: Option<Duration>, target: F) -> Result<()>
+    where
+        F: Fn(&str, Brightness, Brightness) -> Result<Brightness>,
+    {
+        let transition = self.transition(duration);
+        self.stop();
+        self.drop_removed();
+        // The mirror doesn't get to fight over our devices with the new transition.
+        let ours: Vec<String> = self
+            .devices
+            .iter()
+            .map(|device| device.lock().unwrap().name().to_string())
+            .collect();
+        for name in &ours {
+            if let Some(running) = self.mirroring.remove(name) {
+                running.stop();
+            }
+        }
+
+        // Work out the targets up front, so we can report invalid ones.
+        let mut fades = Vec::new();
+        for device in &self.devices {
+            let (name, current, maximum) = {
+                let device = device.lock().unwrap();
+                (device.name().to_string(), device.current()?, device.max()?)
+            };
+            let target = target(&name, current, maximum)?;
+            let quirked = transition.for_device(&name, &self.config);
+            let target = quirked.clamp(&self.config, target, current, maximum)?;
+            let fade = quirked.fade(current, target, maximum);
+            fades.push((device.clone(), name, target, fade));
+        }
+
+        self.run(fades, transition);
+        Ok(())
+    }
+
+    /// Start running `fades` as the running transition.
+    fn run(&mut self, fades: Vec<Fade>, transition: TransitionOpt) {
+        let control = Arc::new(Control::new());
+        let thread = spawn(
+            fades,
+            transition,
+            control.clone(),
+            self.listeners.clone(),
+            self.metrics.clone(),
+        );
+        self.running = Some(Running {
+            control,
+            threads: vec![thread],
+        });
+    }
+
+    /// The default transition, taking `duration` instead if given.
+    fn transition(&self, duration: Option<Duration>) -> TransitionOpt {
+        let mut transition = self.transition.clone();
+        if let Some(duration) = duration {
+            transition.duration = duration.into();
+        }
+        transition
+    }
+}
+
+/// The `duration` a client asked for, parsed.
+fn parse_duration(duration: Option<String>) -> Result<Option<Duration>> {
+    duration
+        .map(|duration| humantime::parse_duration(&duration).context("Failed to parse duration"))
+        .transpose()
+}
+
+/// Run `fades` of the devices to their targets from a thread of their own, under `control`,
+/// finishing them as the `transition` options say, and telling `listeners` when each is done.
+fn spawn(
+    fades: Vec<Fade>,
+    transition: TransitionOpt,
+    control: Arc<Control>,
+    listeners: Vec<Listener>,
+    metrics: Arc<Metrics>,
+) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        std::thread::scope(|scope| {
+            for (device, name, target, fade) in fades {
+                let control = &control;
+                let transition = &transition;
+                let listeners = &listeners;
+                let metrics = &metrics;
+                scope.spawn(move || {
+                    let shared = Shared(device.clone(), metrics.clone());
+                    // The device tries busy writes again itself.
+                    let mut shared = Device::new(name.clone(), shared).without_retries();
+                    metrics.transition_started();
+                    let result = (|| {
+                        if target.0 > 0 && !shared.is_powered()? {
+                            shared.set_powered(true)?;
+                        }
+                        let outcome = fade.run_reporting(&mut shared, control, |frame| {
+                            metrics.frame_written(frame)
+                        })?;
+                        if outcome != Outcome::Finished {
+                            return Ok(());
+                        }
+                        metrics.transition_finished();
+                        transition.verify(&mut shared, target)?;
+                        if transition.power_off && target.0 == 0 {
+                            shared.set_powered(false)?;
+                        }
+                        anyhow::Ok(())
+                    })();
+                    if let Err(err) = result {
+                        tracing::error!("Failed to transition {name}: {err:#}");
+                    }
+                    if let Ok(status) = device.lock().unwrap().status() {
+                        listeners.iter().for_each(|listener| listener(&status));
+                    }
+                });
+            }
+        });
+    })
+}
+
+/// A device shared between the daemon and its transitions, locked for every call only so the
+/// daemon can still read it while it's transitioning, counting the writes that still failed after
+/// trying again in the metrics.
+#[derive(Debug)]
+struct Shared(Arc<Mutex<Device>>, Arc<Metrics>);
+
+impl Backend for Shared {
+    fn class(&self) -> &str {
+        "shared"
+    }
+
+    fn current(&self) -> Result<Brightness> {
+        self.0.lock().unwrap().current()
+    }
+
+    fn max(&self) -> Result<Brightness> {
+        self.0.lock().unwrap().max()
+    }
+
+    fn set(&mut self, brightness: Brightness) -> Result<()> {
+        let result = self.0.lock().unwrap().set(brightness);
+        if result.is_err() {
+            self.1.write_failed();
+        }
+        result
+    }
+
+    fn is_volatile(&self) -> bool {
+        self.0.lock().unwrap().is_volatile()
+    }
+
+    fn is_powered(&self) -> Result<bool> {
+        self.0.lock().unwrap().is_powered()
+    }
+
+    fn set_powered(&mut self, powered: bool) -> Result<()> {
+        self.0.lock().unwrap().set_powered(powered)
+    }
+}
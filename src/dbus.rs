@@ -0,0 +1,108 @@
+use anyhow::{Context, Result};
+use std::sync::{Arc, Mutex};
+use zbus::blocking::{connection, Connection};
+use zbus::object_server::SignalEmitter;
+use zbus::{fdo, interface};
+
+use crate::daemon::{Daemon, Request, Response};
+
+/// The name we own on the session bus, and the interface we serve.
+const NAME: &str = "org.koenw.Dimmer1";
+const PATH: &str = "/org/koenw/Dimmer1";
+
+/// Serves the daemon on D-Bus, for desktop components and programs in other languages.
+struct Service {
+    daemon: Arc<Mutex<Daemon>>,
+}
+
+impl Service {
+    fn handle(&self, request: Request) -> fdo::Result<Response> {
+        self.daemon
+            .lock()
+            .unwrap()
+            .handle(request)
+            .map_err(|err| fdo::Error::Failed(format!("{err:#}")))
+    }
+}
+
+/// An empty duration stands for the daemon's default, as D-Bus has no optional arguments.
+fn duration(duration: String) -> Option<String> {
+    Some(duration).filter(|duration| !duration.is_empty())
+}
+
+#[interface(name = "org.koenw.Dimmer1")]
+impl Service {
+    /// Transition to `target`, like `dimmer set`.
+    fn set_brightness(&self, target: String, duration: String) -> fdo::Result<()> {
+        self.handle(Request::Set {
+            target,
+            duration: self::duration(duration),
+        })?;
+        Ok(())
+    }
+
+    /// Remember the current brightness unless dimmed already, and transition to `target`.
+    fn dim(&self, target: String, duration: String) -> fdo::Result<()> {
+        self.handle(Request::Dim {
+            target,
+            duration: self::duration(duration),
+        })?;
+        Ok(())
+    }
+
+    /// Transition back to the brightness remembered when dimming.
+    fn restore(&self, duration: String) -> fdo::Result<()> {
+        self.handle(Request::Restore {
+            duration: self::duration(duration),
+        })?;
+        Ok(())
+    }
+
+    /// The name, class, current and maximum brightness of every device, whether they're dimmed
+    /// and whether they're transitioning.
+    #[allow(clippy::type_complexity)]
+    fn get_state(&self) -> fdo::Result<(Vec<(String, String, u64, u64)>, bool, bool)> {
+        let response = self.handle(Request::Status)?;
+        let devices = response
+            .devices
+            .unwrap_or_default()
+            .into_iter()
+            .map(|status| (status.name, status.class, status.current.0, status.max.0))
+            .collect();
+        Ok((
+            devices,
+            response.dimmed.unwrap_or_default(),
+            response.transitioning.unwrap_or_default(),
+        ))
+    }
+
+    /// Emitted whenever a transition of a device ends.
+    #[zbus(signal)]
+    async fn brightness_changed(
+        emitter: &SignalEmitter<'_>,
+        device: &str,
+        brightness: u64,
+        max: u64,
+    ) -> zbus::Result<()>;
+}
+
+/// Serve `daemon` on the session bus until the returned connection is dropped.
+pub fn serve(daemon: Arc<Mutex<Daemon>>) -> Result<Connection> {
+    let service = Service {
+        daemon: daemon.clone(),
+    };
+    let connection = connection::Builder::session()?
+        .name(NAME)?
+        .serve_at(PATH, service)?
+        .build()
+        .with_context(|| format!("Failed to serve {NAME} on the session bus"))?;
+
+    let signals = connection.clone();
+    daemon.lock().unwrap().listen(Arc::new(move |status| {
+        let body = (&status.name, status.current.0, status.max.0);
+        if let Err(err) = signals.emit_signal(None::<()>, PATH, NAME, "BrightnessChanged", &body) {
+            tracing::error!("Failed to emit BrightnessChanged: {err}");
+        }
+    }));
+    Ok(connection)
+}
@@ -0,0 +1,275 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::backend::{Backend, Class, Sysfs, SYS_BACKLIGHT_PREFIX};
+use crate::{Brightness, Curve, DimmerError};
+
+/// How many times `Device::set` tries a write the device is too busy for.
+const WRITE_ATTEMPTS: u32 = 5;
+
+/// How long `Device::set` waits before trying a write again, doubling every time.
+const WRITE_BACKOFF: Duration = Duration::from_millis(5);
+
+/// How many times `Device::verify` writes the target again before giving up.
+const VERIFY_ATTEMPTS: u32 = 3;
+
+/// How long `Device::verify` gives the device to take a write before reading it back.
+const VERIFY_DELAY: Duration = Duration::from_millis(50);
+
+/// A snapshot of the brightness of a device.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Status {
+    pub name: String,
+    pub class: String,
+    pub current: Brightness,
+    pub max: Brightness,
+    pub percent: f64,
+}
+
+/// A named device whose brightness we can control through its backend.
+#[derive(Debug)]
+pub struct Device {
+    name: String,
+    backend: Box<dyn Backend>,
+    mapping: Option<Mapping>,
+    /// How many times `set` tries a write the device is too busy for.
+    write_attempts: u32,
+}
+
+/// How the brightness of a device maps onto what its backend takes.
+#[derive(Debug)]
+struct Mapping {
+    /// The raw brightness for a brightness, both as a fraction of the maximum.
+    curve: Curve,
+    /// The brightness we last set, and the raw brightness we wrote for it.
+    written: Option<(Brightness, Brightness)>,
+}
+
+impl Device {
+    pub fn new<S: Into<String>, B: Backend + 'static>(name: S, backend: B) -> Device {
+        Device {
+            name: name.into(),
+            backend: Box::new(backend),
+            mapping: None,
+            write_attempts: WRITE_ATTEMPTS,
+        }
+    }
+
+    /// Map the brightness of the device onto the raw brightness of its backend along `curve`, so
+    /// the brightness is a logical one, e.g. with 50% looking half as bright on panels whose raw
+    /// scale is far from that. The maximum stays the same.
+    pub fn mapped(mut self, curve: Curve) -> Device {
+        self.remap(Some(curve));
+        self
+    }
+
+    /// Map the brightness of the device along `curve` from now on, like `mapped`, or stop mapping
+    /// it if there's none.
+    pub fn remap(&mut self, curve: Option<Curve>) {
+        self.mapping = curve.map(|curve| Mapping {
+            curve,
+            written: None,
+        });
+    }
+
+    /// Write to the backend only once, even when it's busy, for backends that try again
+    /// themselves, like a device wrapping another device.
+    pub fn without_retries(mut self) -> Device {
+        self.write_attempts = 1;
+        self
+    }
+
+    /// Use the sysfs device in a `/sys/class/backlight` or `/sys/class/leds` style directory.
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Device {
+        let dir = dir.as_ref();
+        let name = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Device::new(name, Sysfs::from_dir(dir))
+    }
+
+    /// All devices of `class` found in sysfs.
+    pub fn discover(class: Class) -> Result<Vec<Device>> {
+        Ok(Sysfs::discover(class)?
+            .iter()
+            .map(Device::from_dir)
+            .collect())
+    }
+
+    /// The first device found in `/sys/class/backlight`.
+    pub fn first() -> Result<Device> {
+        Device::discover(Class::Backlight)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                DimmerError::DeviceNotFound(format!("a device in {SYS_BACKLIGHT_PREFIX}"))
+            })
+            .map_err(anyhow::Error::from)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The kind of device, e.g. "backlight" or "leds" for sysfs devices.
+    pub fn class(&self) -> &str {
+        self.backend.class()
+    }
+
+    /// The current and maximum brightness of the device.
+    pub fn status(&self) -> Result<Status> {
+        let current = self.current()?;
+        let max = self.max()?;
+        Ok(Status {
+            name: self.name.clone(),
+            class: self.class().to_string(),
+            current,
+            max,
+            percent: current.percentage_of(max),
+        })
+    }
+
+    /// The current brightness of the device.
+    pub fn current(&self) -> Result<Brightness> {
+        let raw = self.backend.current()?;
+        let Some(mapping) = &self.mapping else {
+            return Ok(raw);
+        };
+        // Where the raw scale is coarser than ours, several brightnesses write the same raw
+        // brightness, and mapping it back may not land on the one we set.
+        if let Some((brightness, written)) = mapping.written {
+            if written == raw {
+                return Ok(brightness);
+            }
+        }
+        let max = self.backend.max()?;
+        Ok(scale(mapping.curve.perceived(fraction(raw, max)), max))
+    }
+
+    /// The maximum brightness the device supports.
+    pub fn max(&self) -> Result<Brightness> {
+        self.backend.max()
+    }
+
+    /// Set the brightness of the device. DDC monitors and some ACPI backlights are busy every now
+    /// and then, so we back off and try again a few times before giving up.
+    pub fn set(&mut self, brightness: Brightness) -> Result<()> {
+        let raw = match &self.mapping {
+            Some(mapping) => {
+                let max = self.backend.max()?;
+                let raw = scale(mapping.curve.raw(fraction(brightness, max)), max);
+                // Anything but off stays on, however far down the curve takes it.
+                if brightness.0 > 0 && raw.0 == 0 {
+                    Brightness(max.0.min(1))
+                } else {
+                    raw
+                }
+            }
+            None => brightness,
+        };
+        self.write(raw)?;
+        if let Some(mapping) = &mut self.mapping {
+            mapping.written = Some((brightness, raw));
+        }
+        Ok(())
+    }
+
+    /// Write the raw `brightness` to the backend.
+    fn write(&mut self, brightness: Brightness) -> Result<()> {
+        let mut backoff = WRITE_BACKOFF;
+        for _ in 1..self.write_attempts {
+            match self.backend.set(brightness) {
+                Err(err) if is_transient(&err) => {
+                    tracing::debug!(device = self.name, ?backoff, "Device busy: {err:#}");
+                    std::thread::sleep(backoff);
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+        self.backend.set(brightness)
+    }
+
+    /// Check the device actually reached `target`, give or take `tolerance`, as some firmware
+    /// silently clamps or ignores writes. We write the target again a few times before giving up
+    /// with `DimmerError::TargetNotReached`.
+    pub fn verify(&mut self, target: Brightness, tolerance: u64) -> Result<()> {
+        let mut actual = self.current()?;
+        for _ in 0..VERIFY_ATTEMPTS {
+            if actual.0.abs_diff(target.0) <= tolerance {
+                return Ok(());
+            }
+            tracing::info!(
+                device = self.name,
+                target = target.0,
+                actual = actual.0,
+                "Device didn't reach its target, writing it again"
+            );
+            self.set(target)?;
+            std::thread::sleep(VERIFY_DELAY);
+            actual = self.current()?;
+        }
+        if actual.0.abs_diff(target.0) <= tolerance {
+            return Ok(());
+        }
+        Err(DimmerError::TargetNotReached {
+            device: self.name.clone(),
+            target,
+            actual,
+        }
+        .into())
+    }
+
+    /// Whether the brightness we set is undone when the device is dropped.
+    pub fn is_volatile(&self) -> bool {
+        self.backend.is_volatile()
+    }
+
+    /// Whether the device is powered on.
+    pub fn is_powered(&self) -> Result<bool> {
+        self.backend.is_powered()
+    }
+
+    /// Power the device on or off, for devices that support it.
+    pub fn set_powered(&mut self, powered: bool) -> Result<()> {
+        self.backend.set_powered(powered)
+    }
+
+    /// The files to watch for changes of the brightness, if the backend has any.
+    pub fn watched_paths(&self) -> Vec<PathBuf> {
+        self.backend.watched_paths()
+    }
+}
+
+/// `brightness` as a fraction of `max`.
+fn fraction(brightness: Brightness, max: Brightness) -> f64 {
+    if max.0 == 0 {
+        return 0.0;
+    }
+    (brightness.0 as f64 / max.0 as f64).clamp(0.0, 1.0)
+}
+
+/// The brightness at `fraction` of `max`.
+fn scale(fraction: f64, max: Brightness) -> Brightness {
+    Brightness((fraction.clamp(0.0, 1.0) * max.0 as f64).round() as u64)
+}
+
+/// Whether `err` means the device went away, e.g. because the monitor was unplugged while we were
+/// using it.
+pub fn is_removed(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<std::io::Error>())
+        .any(|err| {
+            err.kind() == std::io::ErrorKind::NotFound || err.raw_os_error() == Some(libc::ENODEV)
+        })
+}
+
+/// Whether `err` means the device was too busy for us, and trying again later may work.
+fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<std::io::Error>())
+        .any(|err| matches!(err.raw_os_error(), Some(libc::EBUSY | libc::EAGAIN)))
+}
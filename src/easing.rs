@@ -0,0 +1,158 @@
+use crate::DimmerError;
+
+/// The curve along which a transition moves from its start to its target brightness.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Start slowly and speed up towards the target.
+    EaseIn,
+    /// Start quickly and slow down towards the target.
+    EaseOut,
+    /// Start and end slowly, moving quickest halfway.
+    EaseInOut,
+    /// Like `EaseInOut`, but gentler.
+    Smoothstep,
+    /// A cubic Bézier curve from (0, 0) to (1, 1) with control points (x1, y1) and (x2, y2), like
+    /// CSS's `cubic-bezier()`.
+    CubicBezier(f64, f64, f64, f64),
+}
+
+impl Easing {
+    /// Map the linear progress `t` (0.0 to 1.0) of a transition onto the curve.
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut if t < 0.5 => 4.0 * t * t * t,
+            Easing::EaseInOut => 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0,
+            Easing::Smoothstep => t * t * (3.0 - 2.0 * t),
+            Easing::CubicBezier(x1, y1, x2, y2) => bezier(*y1, *y2, bezier_solve(*x1, *x2, t)),
+        }
+    }
+}
+
+/// One dimension of a cubic Bézier curve from 0 to 1 with control points `p1` and `p2`.
+fn bezier(p1: f64, p2: f64, t: f64) -> f64 {
+    let u = 1.0 - t;
+    3.0 * u * u * t * p1 + 3.0 * u * t * t * p2 + t * t * t
+}
+
+/// The parameter at which the Bézier curve with x control points `x1` and `x2` reaches `x`. With
+/// both control points between 0 and 1 the curve is monotonic in x, so we can use Newton's method
+/// and fall back to bisection when it doesn't converge.
+fn bezier_solve(x1: f64, x2: f64, x: f64) -> f64 {
+    const EPSILON: f64 = 1e-7;
+
+    let mut t = x;
+    for _ in 0..8 {
+        let error = bezier(x1, x2, t) - x;
+        if error.abs() < EPSILON {
+            return t;
+        }
+        let u = 1.0 - t;
+        let slope = 3.0 * u * u * x1 + 6.0 * u * t * (x2 - x1) + 3.0 * t * t * (1.0 - x2);
+        if slope.abs() < EPSILON {
+            break;
+        }
+        t -= error / slope;
+    }
+
+    let (mut low, mut high) = (0.0, 1.0);
+    t = x;
+    while high - low > EPSILON {
+        if bezier(x1, x2, t) < x {
+            low = t;
+        } else {
+            high = t;
+        }
+        t = (low + high) / 2.0;
+    }
+    t
+}
+
+impl std::str::FromStr for Easing {
+    type Err = DimmerError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "linear" => Ok(Easing::Linear),
+            "ease-in" => Ok(Easing::EaseIn),
+            "ease-out" => Ok(Easing::EaseOut),
+            "ease-in-out" => Ok(Easing::EaseInOut),
+            "smoothstep" => Ok(Easing::Smoothstep),
+            _ if input.starts_with("cubic-bezier") => parse_cubic_bezier(input),
+            _ => Err(DimmerError::UnknownEasing(input.to_string())),
+        }
+    }
+}
+
+/// Parse `cubic-bezier(x1,y1,x2,y2)`, where x1 and x2 should be between 0 and 1 like in CSS, and
+/// y1 and y2 may be any finite number.
+fn parse_cubic_bezier(input: &str) -> Result<Easing, DimmerError> {
+    let invalid = || DimmerError::InvalidCubicBezier(input.to_string());
+    let points = input
+        .strip_prefix("cubic-bezier(")
+        .and_then(|rest| rest.strip_suffix(')'))
+        .ok_or_else(invalid)?
+        .split(',')
+        .map(|point| point.trim().parse::<f64>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+    match points[..] {
+        [x1, y1, x2, y2]
+            if (0.0..=1.0).contains(&x1)
+                && (0.0..=1.0).contains(&x2)
+                && y1.is_finite()
+                && y2.is_finite() =>
+        {
+            Ok(Easing::CubicBezier(x1, y1, x2, y2))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cubic_bezier() {
+        assert_eq!(
+            "cubic-bezier(0.25, 0.1, 0.25, 1)"
+                .parse::<Easing>()
+                .unwrap(),
+            Easing::CubicBezier(0.25, 0.1, 0.25, 1.0)
+        );
+        assert_eq!(
+            "cubic-bezier(0,-0.5,1,1.5)".parse::<Easing>().unwrap(),
+            Easing::CubicBezier(0.0, -0.5, 1.0, 1.5)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_cubic_bezier() {
+        for input in [
+            "cubic-bezier",
+            "cubic-bezier()",
+            "cubic-bezier(0.25,0.1,0.25)",
+            "cubic-bezier(0.25,0.1,0.25,1,0)",
+            "cubic-bezier(0.25,0.1,0.25,1",
+            "cubic-bezier(a,0.1,0.25,1)",
+            "cubic-bezier(-0.1,0.1,0.25,1)",
+            "cubic-bezier(0.25,0.1,1.1,1)",
+            "cubic-bezier(0.25,NaN,0.25,1)",
+            "cubic-bezier(0.25,0.1,0.25,inf)",
+            "cubic-bezier(0.25,-infinity,0.25,1)",
+        ] {
+            assert!(
+                matches!(
+                    input.parse::<Easing>(),
+                    Err(DimmerError::InvalidCubicBezier(_))
+                ),
+                "{input}"
+            );
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! A small C API, for compositors and status bars written in C to run transitions without
+//! spawning `dimmer`. See `include/dimmer.h` for how to call it.
+//!
+//! Functions returning an `int` return 0 on success and -1 on failure, after which
+//! `dimmer_last_error` tells what went wrong.
+use anyhow::Result;
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::backend::Class;
+use crate::{Brightness, Control, Device, DimmerError, Transition};
+
+/// What `dimmer_list_devices` calls for every device: with its name, current and maximum
+/// brightness, and the data it was given.
+pub type ListCallback = extern "C" fn(*const c_char, u64, u64, *mut c_void);
+
+/// The controls of the transitions running, for `dimmer_cancel` to interrupt.
+static RUNNING: Mutex<Vec<Arc<Control>>> = Mutex::new(Vec::new());
+
+thread_local! {
+    /// What went wrong in the last call that failed on this thread.
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Call `callback` for every screen and keyboard backlight, passing `data` along. The name passed
+/// to `callback` is only valid during the call.
+#[no_mangle]
+pub extern "C" fn dimmer_list_devices(callback: ListCallback, data: *mut c_void) -> c_int {
+    report(|| {
+        for device in devices()? {
+            let name = CString::new(device.name())?;
+            callback(name.as_ptr(), device.current()?.0, device.max()?.0, data);
+        }
+        Ok(())
+    })
+}
+
+/// Transition the device called `name`, or the first backlight if it's NULL, to `target` over
+/// `duration_ms` milliseconds. The target is anything `dimmer set` takes, like "30%", "+10%" or
+/// "max". Blocks until the transition is done, or cancelled by `dimmer_cancel` from another
+/// thread, which isn't a failure.
+///
+/// # Safety
+///
+/// `name` has to be NULL or a valid, nul-terminated string, and `target` a valid, nul-terminated
+/// string.
+#[no_mangle]
+pub unsafe extern "C" fn dimmer_transition(
+    name: *const c_char,
+    target: *const c_char,
+    duration_ms: u64,
+) -> c_int {
+    report(|| {
+        let mut device = if name.is_null() {
+            Device::first()?
+        } else {
+            // SAFETY: the caller passes a valid string.
+            device(unsafe { CStr::from_ptr(name) }.to_str()?)?
+        };
+        // SAFETY: the caller passes a valid string.
+        let target = unsafe { CStr::from_ptr(target) }.to_str()?;
+        let current = device.current()?;
+        let target = Brightness::parse_with_percentage(target, current, device.max()?)?;
+
+        let control = Arc::new(Control::new());
+        RUNNING.lock().unwrap().push(control.clone());
+        let result = Transition::new(current, target)
+            .duration(Duration::from_millis(duration_ms))
+            .auto_framerate()
+            .run_with(&mut device, &control);
+        RUNNING
+            .lock()
+            .unwrap()
+            .retain(|running| !Arc::ptr_eq(running, &control));
+        result?;
+        Ok(())
+    })
+}
+
+/// Stop all transitions running in this process where they are. Returns how many there were.
+#[no_mangle]
+pub extern "C" fn dimmer_cancel() -> c_int {
+    let running = RUNNING.lock().unwrap();
+    running.iter().for_each(|control| control.interrupt());
+    running.len() as c_int
+}
+
+/// What went wrong in the last call on this thread that failed, or NULL if none did. The string
+/// stays valid until the next call that fails on this thread.
+#[no_mangle]
+pub extern "C" fn dimmer_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| {
+        error
+            .borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |error| error.as_ptr())
+    })
+}
+
+/// Run `f`, keeping its error around for `dimmer_last_error` if it fails.
+fn report(f: impl FnOnce() -> Result<()>) -> c_int {
+    match f() {
+        Ok(()) => 0,
+        Err(err) => {
+            let message =
+                CString::new(format!("{err:#}").replace('\0', "")).expect("nul bytes were removed");
+            LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+            -1
+        }
+    }
+}
+
+/// All screen and keyboard backlights.
+fn devices() -> Result<Vec<Device>> {
+    let mut devices = Device::discover(Class::Backlight)?;
+    devices.extend(Device::discover(Class::Keyboard)?);
+    Ok(devices)
+}
+
+/// The backlight called `name`, or at the absolute path `name`.
+fn device(name: &str) -> Result<Device> {
+    if Path::new(name).is_absolute() {
+        return Ok(Device::from_dir(name));
+    }
+    devices()?
+        .into_iter()
+        .find(|device| device.name() == name)
+        .ok_or_else(|| DimmerError::DeviceNotFound(name.to_string()).into())
+}
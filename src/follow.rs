@@ -0,0 +1,97 @@
+use anyhow::Result;
+use dimmer::{Brightness, Control, Device, Outcome};
+use std::io::BufRead;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use crate::config::Config;
+use crate::TransitionOpt;
+
+/// A transition of all devices, which hands them back when it ends.
+type Running = (Arc<Control>, JoinHandle<Vec<Device>>);
+
+/// Transition `devices` to every target read from `input`, a line at a time, taking over from the
+/// transition to the target before as soon as a new one comes in. Targets that don't parse are
+/// skipped. Once `input` ends, the last transition is left to finish.
+pub fn follow<R: BufRead>(
+    mut devices: Vec<Device>,
+    transition: &TransitionOpt,
+    config: &Config,
+    input: R,
+) -> Result<()> {
+    let mut running: Option<Running> = None;
+    for line in input.lines() {
+        let line = line?;
+        let target = line.trim();
+        if target.is_empty() {
+            continue;
+        }
+        // Whether a target parses doesn't depend on the brightness, so we can check it before
+        // stopping the running transition.
+        if let Err(err) = Brightness::parse_with_percentage(target, Brightness(0), Brightness(1)) {
+            tracing::warn!("Skipping target {target:?}: {err:#}");
+            continue;
+        }
+        if let Some((control, thread)) = running.take() {
+            control.interrupt();
+            devices = thread.join().expect("Transition thread panicked");
+        }
+        devices.retain(|device| match device.current() {
+            Err(err) if dimmer::is_removed(&err) => {
+                tracing::warn!("{} went away, carrying on without it", device.name());
+                false
+            }
+            _ => true,
+        });
+        let fades = devices
+            .iter()
+            .map(|device| {
+                let current = device.current()?;
+                let maximum = device.max()?;
+                let target = Brightness::parse_with_percentage(target, current, maximum)?;
+                let transition = transition.for_device(device.name(), config);
+                let target = transition.clamp(config, target, current, maximum)?;
+                Ok((target, transition.fade(current, target, maximum)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let control = Arc::new(Control::new());
+        let thread = {
+            let control = control.clone();
+            let transition = transition.clone();
+            let mut devices = std::mem::take(&mut devices);
+            std::thread::spawn(move || {
+                std::thread::scope(|scope| {
+                    for (device, (target, fade)) in devices.iter_mut().zip(fades) {
+                        let control = &control;
+                        let transition = &transition;
+                        scope.spawn(move || {
+                            let result = (|| {
+                                if target.0 > 0 && !device.is_powered()? {
+                                    device.set_powered(true)?;
+                                }
+                                if fade.run_with(device, control)? != Outcome::Finished {
+                                    return Ok(());
+                                }
+                                transition.verify(device, target)?;
+                                if transition.power_off && target.0 == 0 {
+                                    device.set_powered(false)?;
+                                }
+                                anyhow::Ok(())
+                            })();
+                            if let Err(err) = result {
+                                tracing::error!("Failed to transition {}: {err:#}", device.name());
+                            }
+                        });
+                    }
+                });
+                devices
+            })
+        };
+        running = Some((control, thread));
+    }
+    if let Some((_control, thread)) = running {
+        thread.join().expect("Transition thread panicked");
+    }
+    Ok(())
+}
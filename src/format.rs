@@ -0,0 +1,61 @@
+use anyhow::{bail, Result};
+use dimmer::Status;
+use serde_json::json;
+
+/// Fill in the placeholders in `template` from `status`: `{device}`, `{class}`, `{value}`, `{max}`
+/// and `{percent}`. Braces are escaped by doubling them, as in `{{` and `}}`.
+pub fn format(template: &str, status: &Status) -> Result<String> {
+    let mut output = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                output.push('{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                output.push('}');
+            }
+            '{' => {
+                let Some((name, rest)) = chars.as_str().split_once('}') else {
+                    bail!("Unclosed placeholder in format {template:?}");
+                };
+                match name {
+                    "device" => output.push_str(&status.name),
+                    "class" => output.push_str(&status.class),
+                    "value" => output.push_str(&status.current.to_string()),
+                    "max" => output.push_str(&status.max.to_string()),
+                    "percent" => output.push_str(&format!("{:.0}", status.percent)),
+                    _ => bail!("Unknown placeholder {{{name}}} in format {template:?}"),
+                }
+                chars = rest.chars();
+            }
+            '}' => bail!("Unmatched }} in format {template:?}, use }}}} for a literal one"),
+            c => output.push(c),
+        }
+    }
+    Ok(output)
+}
+
+/// The JSON object Waybar's custom modules read, with the percentage or `template` filled in as
+/// the text, and a class of "off", "low", "medium" or "high" to style the module by.
+pub fn waybar(status: &Status, template: Option<&str>) -> Result<String> {
+    let text = match template {
+        Some(template) => format(template, status)?,
+        None => format!("{:.0}%", status.percent),
+    };
+    let class = match status.percent {
+        percent if percent <= 0.0 => "off",
+        percent if percent < 100.0 / 3.0 => "low",
+        percent if percent < 200.0 / 3.0 => "medium",
+        _ => "high",
+    };
+    let module = json!({
+        "text": text,
+        "tooltip": format!("{}: {}/{}", status.name, status.current, status.max),
+        "percentage": status.percent.round() as u64,
+        "class": class,
+    });
+    Ok(module.to_string())
+}
@@ -0,0 +1,61 @@
+use anyhow::{Context, Result};
+use std::sync::{Arc, Mutex};
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::OwnedObjectPath;
+
+use crate::daemon::Daemon;
+
+const GEOCLUE: &str = "org.freedesktop.GeoClue2";
+const MANAGER_PATH: &str = "/org/freedesktop/GeoClue2/Manager";
+const MANAGER_INTERFACE: &str = "org.freedesktop.GeoClue2.Manager";
+const CLIENT_INTERFACE: &str = "org.freedesktop.GeoClue2.Client";
+const LOCATION_INTERFACE: &str = "org.freedesktop.GeoClue2.Location";
+
+/// GeoClue only hands out locations to clients identifying as an application it allows.
+const DESKTOP_ID: &str = "dimmer";
+
+/// GeoClue's accuracy level for the city we're in, which is plenty to work out sunrise and sunset.
+const ACCURACY_CITY: u32 = 4;
+
+/// How far we have to move, in meters, before GeoClue tells us about it.
+const DISTANCE_THRESHOLD: u32 = 10_000;
+
+/// Tell the daemon where we are whenever GeoClue finds out we moved, from a thread of its own.
+pub fn watch(daemon: Arc<Mutex<Daemon>>) -> Result<()> {
+    let connection = Connection::system().context("Failed to connect to the system bus")?;
+    let manager = Proxy::new(&connection, GEOCLUE, MANAGER_PATH, MANAGER_INTERFACE)?;
+    let path: OwnedObjectPath = manager
+        .call("GetClient", &())
+        .context("Failed to get a GeoClue client")?;
+    let client = Proxy::new(&connection, GEOCLUE, path, CLIENT_INTERFACE)?;
+    client.set_property("DesktopId", DESKTOP_ID)?;
+    client.set_property("RequestedAccuracyLevel", ACCURACY_CITY)?;
+    client.set_property("DistanceThreshold", DISTANCE_THRESHOLD)?;
+    let updates = client
+        .receive_signal("LocationUpdated")
+        .context("Failed to subscribe to GeoClue's signals")?;
+    client
+        .call_method("Start", &())
+        .context("Failed to ask GeoClue for our location, is dimmer allowed to?")?;
+
+    std::thread::spawn(move || {
+        // GeoClue stops the client when we disconnect.
+        let _client = client;
+        for signal in updates {
+            let result = signal
+                .body()
+                .deserialize::<(OwnedObjectPath, OwnedObjectPath)>()
+                .map_err(anyhow::Error::from)
+                .and_then(|(_old, new)| {
+                    let location = Proxy::new(&connection, GEOCLUE, new, LOCATION_INTERFACE)?;
+                    let latitude = location.get_property("Latitude")?;
+                    let longitude = location.get_property("Longitude")?;
+                    daemon.lock().unwrap().set_location(latitude, longitude)
+                });
+            if let Err(err) = result {
+                tracing::error!("Failed to handle our location: {err:#}");
+            }
+        }
+    });
+    Ok(())
+}
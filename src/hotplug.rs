@@ -0,0 +1,47 @@
+use anyhow::Result;
+use std::time::Duration;
+
+use crate::uevent::Monitor;
+
+/// How long we give udev to set up a new device, like giving write access to it, before we
+/// report it.
+const SETTLE: Duration = Duration::from_millis(500);
+
+/// How long we wait for a uevent at a time.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A screen or keyboard backlight the kernel told us about, by name.
+#[derive(Debug)]
+pub enum Hotplug {
+    Added(String),
+    Removed(String),
+}
+
+/// Call `changed` whenever a screen backlight or LED is added or removed, like when connecting a
+/// monitor or keyboard, from a thread of its own.
+pub fn watch<F>(mut changed: F) -> Result<()>
+where
+    F: FnMut(Hotplug) + Send + 'static,
+{
+    let monitor = Monitor::open()?;
+    std::thread::spawn(move || loop {
+        let uevent = match monitor.next(TIMEOUT) {
+            Ok(Some(uevent)) if ["backlight", "leds"].contains(&&uevent.subsystem[..]) => uevent,
+            Ok(_) => continue,
+            Err(err) => {
+                tracing::error!("{err:#}");
+                std::thread::sleep(TIMEOUT);
+                continue;
+            }
+        };
+        match &uevent.action[..] {
+            "add" => {
+                std::thread::sleep(SETTLE);
+                changed(Hotplug::Added(uevent.name));
+            }
+            "remove" => changed(Hotplug::Removed(uevent.name)),
+            _ => {}
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::daemon::{self, Daemon, Request, Response};
+
+/// The longest request line or header we read.
+const MAX_LINE: u64 = 8 * 1024;
+
+/// The longest request body we read.
+const MAX_BODY: usize = 64 * 1024;
+
+/// The body of `POST /transition`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Transition {
+    target: String,
+    duration: Option<String>,
+}
+
+/// What a request is answered with.
+#[derive(Debug)]
+enum Reply {
+    /// One of the daemon's JSON responses.
+    Response(Response),
+    /// Metrics in Prometheus' text format.
+    Metrics(String),
+}
+
+/// A request that's answered with an error status.
+#[derive(Debug)]
+struct Failed {
+    status: &'static str,
+    error: anyhow::Error,
+}
+
+impl Failed {
+    fn new<E: Into<anyhow::Error>>(status: &'static str, error: E) -> Failed {
+        Failed {
+            status,
+            error: error.into(),
+        }
+    }
+}
+
+/// Serve a small HTTP API for the daemon at `address`, from a thread of its own: a TCP address
+/// like "127.0.0.1:7878", or the path of a Unix socket. `GET /state` answers with the status of
+/// the devices, and `POST /transition` with a JSON body like `{"target": "40%", "duration":
+/// "2s"}` starts a transition, both with the daemon's JSON responses. `GET /metrics` answers with
+/// metrics for Prometheus.
+///
+/// There's no authentication, so anyone who can reach the address controls the brightness.
+pub fn serve(daemon: Arc<Mutex<Daemon>>, address: &str) -> Result<()> {
+    if address.starts_with('/') {
+        let listener = daemon::bind(Path::new(address))?;
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                accept(stream, &daemon);
+            }
+        });
+        return Ok(());
+    }
+    let listener = TcpListener::bind(address)
+        .with_context(|| format!("Failed to listen for HTTP requests on {address}"))?;
+    if !listener.local_addr()?.ip().is_loopback() {
+        tracing::warn!(%address, "Serving HTTP beyond localhost, to anyone who can reach it");
+    }
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            accept(stream, &daemon);
+        }
+    });
+    Ok(())
+}
+
+/// Answer the request on a newly accepted `stream`, from a thread of its own.
+fn accept<S: Read + Write + Send + 'static>(
+    stream: std::io::Result<S>,
+    daemon: &Arc<Mutex<Daemon>>,
+) {
+    let stream = match stream {
+        Ok(stream) => stream,
+        Err(err) => {
+            tracing::error!("Failed to accept HTTP connection: {err}");
+            return;
+        }
+    };
+    let daemon = daemon.clone();
+    std::thread::spawn(move || {
+        if let Err(err) = answer(stream, &daemon) {
+            tracing::error!("Failed to answer HTTP request: {err:#}");
+        }
+    });
+}
+
+/// Read a single request from `stream`, handle it and write the response.
+fn answer<S: Read + Write>(mut stream: S, daemon: &Mutex<Daemon>) -> Result<()> {
+    let (status, reply) = match handle(&mut stream, daemon) {
+        Ok(reply) => ("200 OK", reply),
+        Err(Failed { status, error }) => (
+            status,
+            Reply::Response(Response {
+                error: Some(format!("{error:#}")),
+                ..Response::default()
+            }),
+        ),
+    };
+    let (content_type, body) = match reply {
+        Reply::Response(response) => ("application/json", serde_json::to_string(&response)? + "\n"),
+        Reply::Metrics(metrics) => ("text/plain; version=0.0.4", metrics),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    Ok(())
+}
+
+/// Read and route a single request from `stream`.
+fn handle<S: Read>(stream: &mut S, daemon: &Mutex<Daemon>) -> Result<Reply, Failed> {
+    let mut reader = BufReader::new(stream);
+    let request_line = read_line(&mut reader).map_err(|err| Failed::new("400 Bad Request", err))?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(Failed::new(
+            "400 Bad Request",
+            anyhow::anyhow!("Malformed request line {request_line:?}"),
+        ));
+    };
+    let mut length = 0;
+    loop {
+        let header = read_line(&mut reader).map_err(|err| Failed::new("400 Bad Request", err))?;
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value
+                    .trim()
+                    .parse()
+                    .map_err(|err| Failed::new("400 Bad Request", err))?;
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Err(Failed::new(
+            "413 Payload Too Large",
+            anyhow::anyhow!("Request bodies are limited to {MAX_BODY} bytes"),
+        ));
+    }
+    let mut body = vec![0; length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|err| Failed::new("400 Bad Request", err))?;
+    tracing::info!(method, path, "Handling HTTP request");
+
+    let request = match (method, path) {
+        ("GET", "/metrics") => return Ok(Reply::Metrics(daemon.lock().unwrap().metrics())),
+        ("GET", "/state") => Request::Status,
+        ("POST", "/transition") => {
+            let transition: Transition = serde_json::from_slice(&body)
+                .context("Failed to parse transition, expected e.g. {\"target\": \"40%\"}")
+                .map_err(|err| Failed::new("400 Bad Request", err))?;
+            Request::Set {
+                target: transition.target,
+                duration: transition.duration,
+            }
+        }
+        (_, "/metrics" | "/state" | "/transition") => {
+            return Err(Failed::new(
+                "405 Method Not Allowed",
+                anyhow::anyhow!("{method} isn't allowed on {path}"),
+            ))
+        }
+        _ => {
+            return Err(Failed::new(
+                "404 Not Found",
+                anyhow::anyhow!("There's nothing at {path}"),
+            ))
+        }
+    };
+    daemon
+        .lock()
+        .unwrap()
+        .handle(request)
+        .map(Reply::Response)
+        .map_err(|err| Failed::new("422 Unprocessable Entity", err))
+}
+
+/// Read a line of the request head, without its line ending.
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String> {
+    let mut line = String::new();
+    reader.by_ref().take(MAX_LINE).read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        bail!("Request line too long, or cut short");
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
@@ -0,0 +1,91 @@
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use crate::signals;
+
+/// The lock held by the instance of dimmer that's transitioning, so a new instance can find it and
+/// take over instead of fighting over the brightness. The lock file holds the PID of the instance.
+pub struct Lock {
+    _file: File,
+}
+
+impl Lock {
+    /// Take the lock at `path`, first stopping the instance holding it and waiting for it to exit.
+    pub fn take_over(path: &Path) -> Result<Lock> {
+        let mut file = open(path)?;
+        if file.try_lock().is_err() {
+            stop(&mut file, path, false)?;
+        }
+
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", std::process::id())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(Lock { _file: file })
+    }
+}
+
+/// Stop the transition of the instance holding the lock at `path`, if any, and wait for it to
+/// exit. With `restore`, the instance jumps back to the brightness it started from. Returns
+/// whether there was an instance to stop.
+pub fn cancel(path: &Path, restore: bool) -> Result<bool> {
+    let mut file = open(path)?;
+    if file.try_lock().is_ok() {
+        return Ok(false);
+    }
+    stop(&mut file, path, restore)?;
+    Ok(true)
+}
+
+fn open(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))
+}
+
+/// Cancel the instance holding the lock on `file`, and wait until we hold the lock instead.
+fn stop(file: &mut File, path: &Path, restore: bool) -> Result<()> {
+    if let Some(pid) = read_pid(file) {
+        signals::cancel(pid, restore);
+    }
+    file.lock()
+        .with_context(|| format!("Failed to lock {}", path.display()))
+}
+
+/// Where the lock is kept.
+pub fn lock_file() -> PathBuf {
+    runtime_file("dimmer.lock")
+}
+
+/// The path to the file called `name` in `$XDG_RUNTIME_DIR` if there is one, as runtime files are
+/// only useful while we're running, or in the temporary directory otherwise.
+#[cfg(unix)]
+pub fn runtime_file(name: &str) -> PathBuf {
+    xdg::BaseDirectories::new()
+        .ok()
+        .and_then(|dirs| dirs.place_runtime_file(name).ok())
+        .unwrap_or_else(|| {
+            // SAFETY: getuid can't fail.
+            let uid = unsafe { libc::getuid() };
+            let (stem, extension) = name.rsplit_once('.').unwrap_or((name, ""));
+            std::env::temp_dir().join(format!("{stem}-{uid}.{extension}"))
+        })
+}
+
+/// The path to the file called `name` in the temporary directory, which is of our own on Windows.
+#[cfg(windows)]
+pub fn runtime_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(name)
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut pid = String::new();
+    file.read_to_string(&mut pid).ok()?;
+    pid.trim().parse().ok()
+}
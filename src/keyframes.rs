@@ -0,0 +1,139 @@
+use anyhow::{bail, Context, Result};
+use dimmer::Brightness;
+use serde::Deserialize;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::Segment;
+
+/// A brightness to reach at some time into the animation.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Keyframe {
+    /// How long after the start of the animation to reach the brightness.
+    #[serde(deserialize_with = "crate::config::duration")]
+    pub at: Duration,
+    /// The brightness to reach, as for `dimmer set`. Relative targets are relative to the
+    /// keyframe before.
+    pub target: String,
+}
+
+/// The keyframes of an animation, in the order they're reached.
+#[derive(Debug, Clone)]
+pub struct Keyframes(Vec<Keyframe>);
+
+/// A timeline file, with a `[[keyframe]]` table for every keyframe.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Timeline {
+    keyframe: Vec<Keyframe>,
+}
+
+impl Keyframes {
+    fn new(keyframes: Vec<Keyframe>) -> Result<Keyframes> {
+        if keyframes.is_empty() {
+            bail!("An animation needs at least one keyframe");
+        }
+        if let Some(pair) = keyframes.windows(2).find(|pair| pair[0].at > pair[1].at) {
+            bail!(
+                "Keyframes have to be in order, but {} comes after {}",
+                humantime::format_duration(pair[1].at),
+                humantime::format_duration(pair[0].at)
+            );
+        }
+        Ok(Keyframes(keyframes))
+    }
+
+    /// Read the keyframes from the timeline file at `path`.
+    pub fn load(path: &Path) -> Result<Keyframes> {
+        let timeline = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let timeline: Timeline = toml::from_str(&timeline)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        Keyframes::new(timeline.keyframe)
+    }
+
+    /// The segments from one keyframe to the next, for a device at brightness `current` out of
+    /// `maximum`. A keyframe at the very start jumps straight to its brightness.
+    pub fn segments(&self, current: Brightness, maximum: Brightness) -> Result<Vec<Segment>> {
+        let mut segments = Vec::new();
+        let (mut at, mut brightness) = (Duration::ZERO, current);
+        for keyframe in &self.0 {
+            brightness = Brightness::parse_with_percentage(&keyframe.target, brightness, maximum)?;
+            segments.push(Segment {
+                target: brightness,
+                duration: Some(keyframe.at - at),
+            });
+            at = keyframe.at;
+        }
+        Ok(segments)
+    }
+}
+
+impl FromStr for Keyframes {
+    type Err = anyhow::Error;
+
+    /// Parse comma separated keyframes like "0s=100%,2s=40%,10s=0%".
+    fn from_str(input: &str) -> Result<Self> {
+        let keyframes = input
+            .split(',')
+            .map(|keyframe| {
+                let Some((at, target)) = keyframe.split_once('=') else {
+                    bail!("Failed to parse keyframe {keyframe:?}, expected e.g. \"2s=40%\"");
+                };
+                Ok(Keyframe {
+                    at: humantime::parse_duration(at.trim())
+                        .with_context(|| format!("Failed to parse keyframe {keyframe:?}"))?,
+                    target: target.trim().to_string(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Keyframes::new(keyframes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_keyframes() {
+        let keyframes: Keyframes = "0s=100%, 2s = 40% ,10s=-10%".parse().unwrap();
+        let parsed: Vec<_> = keyframes
+            .0
+            .iter()
+            .map(|keyframe| (keyframe.at, keyframe.target.as_str()))
+            .collect();
+        assert_eq!(
+            parsed,
+            [
+                (Duration::ZERO, "100%"),
+                (Duration::from_secs(2), "40%"),
+                (Duration::from_secs(10), "-10%"),
+            ]
+        );
+        let segments = keyframes
+            .segments(Brightness(500), Brightness(1000))
+            .unwrap();
+        let segments: Vec<_> = segments
+            .iter()
+            .map(|segment| (segment.target, segment.duration))
+            .collect();
+        assert_eq!(
+            segments,
+            [
+                (Brightness(1000), Some(Duration::ZERO)),
+                (Brightness(400), Some(Duration::from_secs(2))),
+                (Brightness(300), Some(Duration::from_secs(8))),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_keyframes() {
+        for input in ["", "2s", "2s=40%,", "=40%", "soon=40%", "4s=0%,2s=40%"] {
+            assert!(input.parse::<Keyframes>().is_err(), "{input}");
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! Dimmer smoothly transitions your screen from one brightness to another.
+//!
+//! The `dimmer` binary is a thin command line wrapper around this library, which can be used to
+//! embed the same fading logic in other tools:
+//!
+//! ```no_run
+//! use dimmer::{Brightness, Device, Transition};
+//! use std::time::Duration;
+//!
+//! let mut device = Device::first()?;
+//! let current = device.current()?;
+//! Transition::new(current, Brightness(0))
+//!     .duration(Duration::from_secs(2))
+//!     .run(&mut device)?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+use thiserror::Error;
+
+pub mod backend;
+mod brightness;
+mod curve;
+mod device;
+mod easing;
+#[cfg(feature = "ffi")]
+mod ffi;
+#[cfg(feature = "python")]
+mod python;
+mod transition;
+
+pub use backend::Backend;
+pub use brightness::Brightness;
+pub use curve::Curve;
+pub use device::{is_removed, Device, Status};
+pub use easing::Easing;
+pub use transition::{Control, Frame, Frames, Outcome, Transition};
+
+#[derive(Error, Debug)]
+pub enum DimmerError {
+    #[error("Invalid percentage given by user")]
+    InvalidPercentage,
+    #[error("Failed to parse invalid Brightness")]
+    InvalidBrightness(#[from] std::num::ParseIntError),
+    #[error("Unknown backend {0}")]
+    UnknownBackend(String),
+    #[error("Unknown easing {0}")]
+    UnknownEasing(String),
+    #[error(
+        "Invalid easing {0}, expected cubic-bezier(x1,y1,x2,y2) with x1 and x2 between 0 and 1"
+    )]
+    InvalidCubicBezier(String),
+    #[error("Failed to find {0}")]
+    DeviceNotFound(String),
+    #[error("Permission denied writing {}", .0.display())]
+    PermissionDenied(std::path::PathBuf),
+    #[error("{device} stayed at {actual} instead of reaching {target}")]
+    TargetNotReached {
+        device: String,
+        target: Brightness,
+        actual: Brightness,
+    },
+}
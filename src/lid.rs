@@ -0,0 +1,33 @@
+use glob::glob;
+use std::time::Duration;
+
+const LID_STATE_GLOB: &str = "/proc/acpi/button/lid/*/state";
+
+/// How often we check whether the lid was opened or closed.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether the lid is closed, or `None` if there's no lid.
+pub fn is_closed() -> Option<bool> {
+    let path = glob(LID_STATE_GLOB).ok()?.flatten().next()?;
+    let state = std::fs::read_to_string(path).ok()?;
+    Some(state.contains("closed"))
+}
+
+/// Call `changed` with whether the lid is closed whenever it's opened or closed, from a thread of
+/// its own.
+pub fn watch<F>(mut changed: F)
+where
+    F: FnMut(bool) + Send + 'static,
+{
+    std::thread::spawn(move || {
+        let mut last = is_closed();
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+            let current = is_closed();
+            if let Some(closed) = current.filter(|_| current != last) {
+                changed(closed);
+            }
+            last = current;
+        }
+    });
+}
@@ -0,0 +1,36 @@
+use anyhow::Result;
+use std::io::IsTerminal;
+use tracing_subscriber::filter::LevelFilter;
+
+/// Log what we're doing to stderr, in more detail with every `verbosity` level (the number of
+/// times `-v` was given), or to journald instead with `journald`.
+pub fn init(verbosity: u8, journald: bool) -> Result<()> {
+    let level = match verbosity {
+        0 => LevelFilter::WARN,
+        1 => LevelFilter::INFO,
+        2 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+    if journald {
+        #[cfg(feature = "journald")]
+        {
+            use anyhow::Context;
+            use tracing_subscriber::layer::{Layer, SubscriberExt};
+            use tracing_subscriber::util::SubscriberInitExt;
+
+            let layer = tracing_journald::layer().context("Failed to connect to journald")?;
+            tracing_subscriber::registry()
+                .with(layer.with_filter(level))
+                .init();
+            return Ok(());
+        }
+        #[cfg(not(feature = "journald"))]
+        anyhow::bail!("Logging to journald requires building dimmer with the `journald` feature");
+    }
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .init();
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use anyhow::{Context, Result};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::OwnedObjectPath;
+
+use crate::daemon::Daemon;
+
+const LOGIND: &str = "org.freedesktop.login1";
+const SESSION_PATH: &str = "/org/freedesktop/login1/session/auto";
+const SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+const MANAGER_PATH: &str = "/org/freedesktop/login1";
+const MANAGER_INTERFACE: &str = "org.freedesktop.login1.Manager";
+
+/// How often we check whether the session is idle.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Our logind session. Signals are sent from its real path rather than the "auto" alias, so we
+/// look that up first.
+fn session() -> Result<Proxy<'static>> {
+    let connection = Connection::system().context("Failed to connect to the system bus")?;
+    let auto = Proxy::new(&connection, LOGIND, SESSION_PATH, SESSION_INTERFACE)
+        .context("Failed to find our logind session")?;
+    let id: String = auto.get_property("Id")?;
+    let path: OwnedObjectPath = connection
+        .call_method(
+            Some(LOGIND),
+            MANAGER_PATH,
+            Some(MANAGER_INTERFACE),
+            "GetSession",
+            &id,
+        )?
+        .body()
+        .deserialize()?;
+    Proxy::new(&connection, LOGIND, path, SESSION_INTERFACE)
+        .context("Failed to find our logind session")
+}
+
+/// Tell the daemon how long our logind session has been idle, every second, from a thread of its
+/// own. Logind learns the session is idle from the desktop environment, which sets its idle hint.
+pub fn watch_idle(daemon: Arc<Mutex<Daemon>>) -> Result<()> {
+    let session = session()?;
+    std::thread::spawn(move || loop {
+        std::thread::sleep(POLL_INTERVAL);
+        let result = idle_for(&session).and_then(|idle_for| daemon.lock().unwrap().idle(idle_for));
+        if let Err(err) = result {
+            tracing::error!("Failed to handle idleness: {err:#}");
+        }
+    });
+    Ok(())
+}
+
+/// Tell the daemon when our logind session is locked and unlocked, from a thread of its own.
+pub fn watch_lock(daemon: Arc<Mutex<Daemon>>) -> Result<()> {
+    let signals = session()?
+        .receive_all_signals()
+        .context("Failed to subscribe to logind's signals")?;
+    std::thread::spawn(move || {
+        for signal in signals {
+            let locked = match signal.header().member().map(|member| member.as_str()) {
+                Some("Lock") => true,
+                Some("Unlock") => false,
+                _ => continue,
+            };
+            if let Err(err) = daemon.lock().unwrap().set_locked(locked) {
+                tracing::error!("Failed to handle the session being locked: {err:#}");
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Tell the daemon when the system is about to sleep, and when it's woken up again, from a thread
+/// of its own.
+pub fn watch_sleep(daemon: Arc<Mutex<Daemon>>) -> Result<()> {
+    let connection = Connection::system().context("Failed to connect to the system bus")?;
+    let manager = Proxy::new(&connection, LOGIND, MANAGER_PATH, MANAGER_INTERFACE)?;
+    let signals = manager
+        .receive_signal("PrepareForSleep")
+        .context("Failed to subscribe to logind's signals")?;
+    std::thread::spawn(move || {
+        for signal in signals {
+            let result = signal
+                .body()
+                .deserialize::<bool>()
+                .map_err(anyhow::Error::from)
+                .and_then(|sleeping| daemon.lock().unwrap().prepare_for_sleep(sleeping));
+            if let Err(err) = result {
+                tracing::error!("Failed to handle sleep: {err:#}");
+            }
+        }
+    });
+    Ok(())
+}
+
+/// How long the session has been idle, if it is.
+fn idle_for(session: &Proxy) -> Result<Option<Duration>> {
+    if !session.get_property::<bool>("IdleHint")? {
+        return Ok(None);
+    }
+    let since = UNIX_EPOCH + Duration::from_micros(session.get_property::<u64>("IdleSinceHint")?);
+    Ok(Some(
+        SystemTime::now().duration_since(since).unwrap_or_default(),
+    ))
+}
@@ -1,63 +1,133 @@
-use anyhow::{Context, Result};
-use glob::glob;
+use anyhow::{bail, Context, Result};
+#[cfg(feature = "drm")]
+use dimmer::backend::Drm;
+#[cfg(target_os = "freebsd")]
+use dimmer::backend::FreeBsd;
+#[cfg(feature = "logind")]
+use dimmer::backend::Logind;
+#[cfg(all(feature = "macos", target_os = "macos"))]
+use dimmer::backend::MacOs;
+#[cfg(target_os = "openbsd")]
+use dimmer::backend::OpenBsd;
+#[cfg(feature = "wayland")]
+use dimmer::backend::Wayland;
+#[cfg(all(feature = "windows", target_os = "windows"))]
+use dimmer::backend::Windows;
+#[cfg(feature = "x11")]
+use dimmer::backend::X11;
+use dimmer::backend::{Class, Kind, Sysfs};
+use dimmer::{Brightness, Curve, Device, DimmerError, Easing, Outcome, Status, Transition};
+use glob::Pattern;
 use humantime::Duration;
-use std::fs::File;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
 use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::exit;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use structopt::clap::AppSettings;
 use structopt::StructOpt;
-use thiserror::Error;
 
-#[derive(Error, Debug)]
-enum DimmerError {
-    #[error("Invalid percentage given by user")]
-    InvalidPercentage,
-    #[error("Failed to parse invalid Brightness")]
-    InvalidBrightness(#[from] std::num::ParseIntError),
-}
+mod als;
+mod bench;
+mod calibrate;
+mod config;
+mod daemon;
+#[cfg(feature = "dbus")]
+mod dbus;
+mod follow;
+mod format;
+#[cfg(feature = "geoclue")]
+mod geoclue;
+mod hotplug;
+mod http;
+mod instance;
+mod keyframes;
+mod lid;
+mod logging;
+#[cfg(feature = "logind")]
+mod logind;
+mod metrics;
+mod mirror;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod permissions;
+mod plan;
+mod power;
+mod quirks;
+mod schedule;
+mod signals;
+mod state;
+mod sun;
+mod systemd;
+mod uevent;
+mod watch;
+#[cfg(feature = "wayland")]
+mod wayland_idle;
+#[cfg(feature = "x11")]
+mod x11_idle;
 
-#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
-struct Brightness(u64);
+use config::{Config, Link};
+use daemon::Request;
+use instance::Lock;
+use keyframes::Keyframes;
+use permissions::Access;
+use plan::Plan;
+use signals::{Signals, CONTROL};
+use state::State;
 
-impl std::fmt::Display for Brightness {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
-    }
-}
+/// The shortest a flash may take, keeping to at most three flashes a second, as photosensitivity
+/// guidelines do.
+const MIN_FLASH_PERIOD: std::time::Duration = std::time::Duration::from_millis(333);
 
-impl std::str::FromStr for Brightness {
-    type Err = DimmerError;
+/// How many percent of its maximum brightness a device may end up off its target.
+const VERIFY_TOLERANCE: u64 = 1;
 
-    fn from_str(input: &str) -> Result<Self, Self::Err> {
-        Ok(input.parse::<u64>().map(Brightness)?)
-    }
-}
+/// The backend used unless another is selected: the only one there is on macOS and Windows, or
+/// sysfs.
+const DEFAULT_BACKEND: &str = if cfg!(all(feature = "macos", target_os = "macos")) {
+    "macos"
+} else if cfg!(all(feature = "windows", target_os = "windows")) {
+    "windows"
+} else {
+    "sysfs"
+};
 
-impl Brightness {
-    fn parse_with_percentage(input: &str, max: Brightness) -> Result<Brightness> {
-        match input.strip_suffix('%') {
-            Some(percentage) => {
-                let percentage = percentage.parse::<u64>()?;
-                if percentage > 100 {
-                    return Err(DimmerError::InvalidPercentage.into());
-                }
-                Ok(Brightness(
-                    ((percentage as f64 / 100.0) * max.0 as f64) as u64,
-                ))
-            }
-            None => Ok(input.parse::<u64>().map(Brightness)?),
-        }
-    }
+/// The backend used instead of sysfs when there's no `/sys/class/backlight`.
+#[cfg(target_os = "freebsd")]
+const SYSFS_FALLBACK: Kind = Kind::FreeBsd;
+#[cfg(target_os = "openbsd")]
+const SYSFS_FALLBACK: Kind = Kind::OpenBsd;
 
-    fn from_file<P: AsRef<Path>>(path: P) -> Result<Brightness> {
-        let path = path.as_ref();
-        let res = std::fs::read_to_string(path)
-            .context("Failed to read {path}")?
-            .trim()
-            .parse()
-            .context("Failed to parse brightness from {path}")?;
-        Ok(res)
-    }
+/// The exit status when something went wrong that has no status of its own.
+const EXIT_FAILURE: i32 = 1;
+
+/// The exit status for invalid arguments, targets or configuration.
+const EXIT_INVALID: i32 = 2;
+
+/// The exit status when a device never reached the target of its transition.
+const EXIT_NOT_REACHED: i32 = 3;
+
+/// The exit status when we weren't allowed to write to a device, or another file we need.
+const EXIT_PERMISSION_DENIED: i32 = 4;
+
+/// The exit status when a device couldn't be found, or went away.
+const EXIT_NOT_FOUND: i32 = 5;
+
+/// The exit status when all devices were already at their target, so there was nothing to do.
+const EXIT_UNCHANGED: i32 = 6;
+
+/// Why a command stopped without an error to report, with an exit status of its own for scripts
+/// to tell apart.
+#[derive(Debug, thiserror::Error)]
+enum Stopped {
+    #[error("All devices were at their target already")]
+    Unchanged,
+    /// The devices that didn't reach their target were reported as they failed.
+    #[error("Not all devices reached their target")]
+    NotReached,
+    #[error("Interrupted by signal {0}")]
+    Interrupted(i32),
 }
 
 #[derive(Debug, StructOpt)]
@@ -67,153 +137,2489 @@ impl Brightness {
 ///
 /// Dim the screen to zero brightness over 5 seconds:
 ///
-/// `dimmer`
+/// `dimmer set 0`
 ///
 /// Dim the screen to 30% brightness over 3 seconds, storing the current brightness in the
 /// statefile:
 ///
-/// `dimmer --save --duration 3s 30%`
+/// `dimmer set --save --duration 3s 30%`
 ///
 /// Restore the screen to the previously saved brightness, using 2 seconds:
 ///
-/// `dimmer --restore --duration 2s`
+/// `dimmer restore --duration 2s`
 struct Opt {
-    /// Path to the file to write to set the brightness. We'll try to pick this from
-    /// `/sys/class/backlight` if not set.
+    /// Path to the file to write to set the brightness. We'll pick this from the selected device
+    /// if not set.
     ///
-    #[structopt(long = "set-brightness-path", parse(from_os_str))]
+    #[structopt(long = "set-brightness-path", parse(from_os_str), global = true)]
     brightness_file: Option<PathBuf>,
 
     /// Path to the file to read the current brightness from. This can be the same file as the file to
-    /// set the brightness.  We'll try to pick this from `/sys/class/backlight` if not set.
+    /// set the brightness. We'll pick this from the selected device if not set.
     ///
-    #[structopt(long = "get-brightness-path", parse(from_os_str))]
+    #[structopt(long = "get-brightness-path", parse(from_os_str), global = true)]
     current_brightness_file: Option<PathBuf>,
 
-    /// Path to the file to read the maximum possible brightness from. We'll try to pick this
-    /// from `/sys/class/backlight` if not set.
+    /// Path to the file to read the maximum possible brightness from. We'll pick this from the
+    /// selected device if not set.
     ///
-    #[structopt(long = "max-brightness-path", parse(from_os_str))]
+    #[structopt(long = "max-brightness-path", parse(from_os_str), global = true)]
     max_brightness_file: Option<PathBuf>,
 
-    /// The state file is used to keep track of the original brightness, so we
-    /// can later restore it.
+    /// The state file is used to keep track of the original brightness of every device, so we
+    /// can later restore it. Defaults to `$XDG_STATE_HOME/dimmer/state.toml`.
     ///
-    #[structopt(long, parse(from_os_str))]
+    #[structopt(long, parse(from_os_str), env = "DIMMER_STATE_FILE", global = true)]
     state_file: Option<PathBuf>,
 
+    /// The configuration file to read defaults from. We'll read
+    /// `$XDG_CONFIG_HOME/dimmer/config.toml` if it exists and this isn't set.
+    ///
+    #[structopt(long, parse(from_os_str), env = "DIMMER_CONFIG", global = true)]
+    config: Option<PathBuf>,
+
+    /// The socket the daemon listens on. Defaults to `$XDG_RUNTIME_DIR/dimmer.sock`.
+    ///
+    #[structopt(long, parse(from_os_str), env = "DIMMER_SOCKET", global = true)]
+    socket: Option<PathBuf>,
+
+    /// How to control the brightness: "sysfs" writes to the backlight in `/sys/class/backlight`,
+    /// "wayland" dims the first output in software using wlr-gamma-control, "x11" does the same
+    /// using XRandR and "drm" using the DRM/KMS gamma LUT when no compositor is running.
+    /// "logind" sets the sysfs backlight through systemd-logind, which doesn't need write access
+    /// to `/sys`, "macos" sets the backlight of a Mac's display and "windows" the brightness of a
+    /// display on Windows. These backends are only available when built with the `wayland`, `x11`,
+    /// `drm`, `logind`, `macos` or `windows` feature. On FreeBSD, "freebsd" sets the backlight
+    /// through backlight(9) or acpi_video(4), and on OpenBSD "openbsd" through wscons(4). They're
+    /// used instead of sysfs when there's no `/sys/class/backlight`.
+    ///
+    #[structopt(
+        long,
+        default_value = DEFAULT_BACKEND,
+        env = "DIMMER_BACKEND",
+        global = true
+    )]
+    backend: Kind,
+
+    /// Control the keyboard backlight in `/sys/class/leds` instead of the screen's backlight.
+    ///
+    #[structopt(long, global = true)]
+    keyboard: bool,
+
+    /// The name of a device to control, as shown by `dimmer list` (e.g. "intel_backlight"). For
+    /// sysfs this can also be the absolute path to a device directory. Can be given multiple times
+    /// to transition several devices at once. We'll pick the first device found if not set, or
+    /// the comma separated devices in `$DIMMER_DEVICE` if that is.
+    ///
+    #[structopt(long = "device", number_of_values = 1, global = true)]
+    devices: Vec<String>,
+
+    /// Control all devices found.
+    ///
+    #[structopt(long, conflicts_with = "devices", global = true)]
+    all: bool,
+
+    /// Control all devices whose name matches this glob pattern (e.g. "amdgpu*"). Can be given
+    /// multiple times.
+    ///
+    #[structopt(
+        long = "device-glob",
+        number_of_values = 1,
+        conflicts_with = "devices",
+        global = true
+    )]
+    device_globs: Vec<Pattern>,
+
+    /// Never pick devices whose name matches this glob pattern, unless they're named with
+    /// `--device`. Can be given multiple times.
+    ///
+    #[structopt(long = "exclude-device", number_of_values = 1, global = true)]
+    excluded_devices: Vec<Pattern>,
+
+    /// Control this continuous VCP code of a DDC/CI monitor instead of its brightness, in hex
+    /// (e.g. "0x12" for the contrast or "0x62" for the volume) or decimal, transitioning it just
+    /// like the brightness. Only for monitors the windows backend controls over DDC/CI.
+    ///
+    #[structopt(long, global = true)]
+    vcp: Option<Vcp>,
+
+    /// Log what dimmer is doing to stderr, with every write of a transition at `-vv`.
+    ///
+    #[structopt(short, long, parse(from_occurrences), global = true)]
+    verbose: u8,
+
+    /// How the brightness of devices maps onto their raw brightness, by name, from the
+    /// configuration.
+    #[structopt(skip)]
+    mappings: BTreeMap<String, Curve>,
+
+    #[structopt(subcommand)]
+    cmd: Command,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Smoothly transition the screen to the target brightness.
+    ///
+    /// Chain more transitions after it with `--then [--after <hold>] [--duration <duration>]
+    /// <target>`, e.g. `dimmer set --duration 2s 40% --then --after 30s --duration 10s 0` to dim,
+    /// hold for a while and then fade out.
+    #[structopt(setting = AppSettings::AllowLeadingHyphen)]
+    Set {
+        #[structopt(flatten)]
+        transition: TransitionOpt,
+
+        /// Save the current brightness to the statefile before transitioning.
+        ///
+        #[structopt(long, short)]
+        save: bool,
+
+        /// Save the current brightness to the statefile, but don't transition, like `dimmer
+        /// save`.
+        ///
+        #[structopt(long)]
+        save_only: bool,
+
+        /// Save to a named slot instead of the default one, to keep several brightness levels
+        /// around.
+        ///
+        #[structopt(long)]
+        slot: Option<String>,
+
+        /// Animate through several brightness levels instead of transitioning to the target, each
+        /// reached at a time counted from the start (e.g. "0s=100%,2s=40%,10s=0%"). Relative
+        /// levels are relative to the one before.
+        ///
+        #[structopt(long, conflicts_with = "timeline")]
+        keyframes: Option<Keyframes>,
+
+        /// Animate through the keyframes in this TOML file, like `--keyframes`, with a
+        /// `[[keyframe]]` table with an `at` and a `target` for every keyframe.
+        ///
+        #[structopt(long, parse(from_os_str))]
+        timeline: Option<PathBuf>,
+
+        /// Run through the keyframes, or the transitions chained with `--then`, this many times
+        /// over, or "forever" until stopped.
+        ///
+        #[structopt(long)]
+        repeat: Option<Repeat>,
+
+        /// Leave devices alone unless they're brighter than this level, absolute or as a
+        /// percentage, so dimming when idle doesn't brighten a screen that was already turned
+        /// down further.
+        ///
+        #[structopt(long)]
+        only_if_above: Option<String>,
+
+        /// Leave devices alone unless they're dimmer than this level, absolute or as a percentage,
+        /// so brightening doesn't dim a screen that was already turned up further.
+        ///
+        #[structopt(long)]
+        only_if_below: Option<String>,
+
+        /// The brightness to target. Can either be an absolute value between 0 and the value in the
+        /// file at `max-brightness-path`, or an percentage (e.g. "0%" to "100%", including
+        /// fractions like "12.5%"). Prefix it with "+" or "-" to target a brightness relative to
+        /// the current one (e.g. "+10%" or "-200"), or use one of the keywords "max", "min" (the
+        /// lowest brightness that isn't off) or "off". Off if not given.
+        ///
+        /// Give every device a target of its own by naming them, as in
+        /// "intel_backlight=30%,ddcci1=50%". This selects the devices named.
+        ///
+        #[structopt(
+            allow_hyphen_values = true,
+            env = "DIMMER_TARGET",
+            conflicts_with_all = &["keyframes", "timeline"]
+        )]
+        target: Option<Targets>,
+    },
+
+    /// Print the frames a transition like `dimmer set` would write and when, without writing
+    /// any, for other tools to check or draw.
+    Plan {
+        #[structopt(flatten)]
+        transition: TransitionOpt,
+
+        /// Plan an animation through these keyframes, as for `dimmer set`.
+        ///
+        #[structopt(long, conflicts_with = "timeline")]
+        keyframes: Option<Keyframes>,
+
+        /// Plan an animation through the keyframes in this TOML file, as for `dimmer set`.
+        ///
+        #[structopt(long, parse(from_os_str))]
+        timeline: Option<PathBuf>,
+
+        /// How many times the animation runs through, as for `dimmer set`.
+        ///
+        #[structopt(long)]
+        repeat: Option<Repeat>,
+
+        /// Print the plan of every device as JSON, with the time of every write in milliseconds
+        /// from the start of its leg.
+        ///
+        #[structopt(long)]
+        json: bool,
+
+        /// The brightness to target, as for `dimmer set`.
+        ///
+        #[structopt(
+            allow_hyphen_values = true,
+            env = "DIMMER_TARGET",
+            conflicts_with_all = &["keyframes", "timeline"]
+        )]
+        target: Option<Targets>,
+    },
+
+    /// Transition to a lower brightness, leaving devices that are already at or below it alone.
+    /// Unlike `dimmer set`, this never brightens a screen, so it's safe to run when idle.
+    Dim {
+        #[structopt(flatten)]
+        transition: TransitionOpt,
+
+        /// The brightness to dim to, as for `dimmer set`.
+        ///
+        #[structopt(long, allow_hyphen_values = true)]
+        to: String,
+    },
+
+    /// Transition to a higher brightness, leaving devices that are already at or above it alone.
+    /// Unlike `dimmer set`, this never dims a screen.
+    Brighten {
+        #[structopt(flatten)]
+        transition: TransitionOpt,
+
+        /// The brightness to brighten to, as for `dimmer set`.
+        ///
+        #[structopt(long, allow_hyphen_values = true)]
+        to: String,
+    },
+
+    /// Print the current and maximum brightness, and the percentage that is.
+    Get {
+        /// Print just the percentage.
+        ///
+        #[structopt(long, conflicts_with_all = &["raw", "json", "format"])]
+        percent: bool,
+
+        /// Print just the current brightness.
+        ///
+        #[structopt(long, conflicts_with_all = &["json", "format"])]
+        raw: bool,
+
+        /// Print the brightness of each device as a line of JSON, like `--output json`.
+        ///
+        #[structopt(long, conflicts_with_all = &["format", "output"])]
+        json: bool,
+
+        /// Print the brightness of each device in this format, filling in `{device}`, `{class}`,
+        /// `{value}`, `{max}` and `{percent}` (e.g. "{percent}% ({value}/{max}) on {device}").
+        /// With `--output waybar`, this is the text of the module.
+        ///
+        #[structopt(long)]
+        format: Option<String>,
+
+        /// How to print the brightness: "plain", "json", or "waybar" for the JSON Waybar's custom
+        /// modules expect.
+        ///
+        #[structopt(long, default_value = "plain")]
+        output: Output,
+    },
+
+    /// Print the brightness like `dimmer get`, and again whenever it changes, including changes
+    /// made by other programs.
+    Watch {
+        /// How often to check for changes the kernel doesn't report.
+        ///
+        #[structopt(long, default_value = "1s")]
+        interval: Duration,
+
+        /// Print the brightness of each device as a line of JSON, like `--output json`.
+        ///
+        #[structopt(long, conflicts_with_all = &["format", "output"])]
+        json: bool,
+
+        /// Print the brightness of each device in this format, as for `dimmer get --format`.
+        ///
+        #[structopt(long)]
+        format: Option<String>,
+
+        /// How to print the brightness, as for `dimmer get --output`. Use "waybar" for a Waybar
+        /// custom module with `exec = "dimmer watch --output waybar"`.
+        ///
+        #[structopt(long, default_value = "plain")]
+        output: Output,
+    },
+
+    /// Smoothly transition the screen to every target read from stdin, one per line, heading
+    /// for the new one as soon as it comes in. This adds smoothing to scripts, sensors and sliders
+    /// that just print brightness levels.
+    Follow {
+        #[structopt(flatten)]
+        transition: TransitionOpt,
+    },
+
+    /// Save the current brightness to the statefile, without changing it.
+    Save {
+        /// Save to a named slot instead of the default one, to keep several brightness levels
+        /// around.
+        ///
+        #[structopt(long)]
+        slot: Option<String>,
+    },
+
+    /// Smoothly restore the previously saved brightness from the statefile.
+    Restore {
+        #[structopt(flatten)]
+        transition: TransitionOpt,
+
+        /// Restore the brightness saved to a named slot instead of the default one.
+        ///
+        #[structopt(long)]
+        slot: Option<String>,
+
+        /// Don't restore a brightness saved longer ago than this (e.g. "2h"), but the fallback
+        /// brightness instead.
+        ///
+        #[structopt(long)]
+        max_age: Option<Duration>,
+
+        /// The brightness to restore when the saved brightness is older than `--max-age`, as for
+        /// `dimmer set`.
+        ///
+        #[structopt(long, allow_hyphen_values = true)]
+        fallback: Option<String>,
+    },
+
+    /// Save the current brightness and transition to the target brightness, or restore the
+    /// saved brightness if the last toggle did that already. Handy to bind to a single key.
+    #[structopt(setting = AppSettings::AllowLeadingHyphen)]
+    Toggle {
+        #[structopt(flatten)]
+        transition: TransitionOpt,
+
+        /// Decide which way to go from the current brightness instead of from the last toggle:
+        /// dim devices brighter than this to the target, and brighten the others again. This
+        /// keeps working when other tools change the brightness as well.
+        ///
+        #[structopt(long)]
+        threshold: Option<String>,
+
+        /// With `--threshold`, brighten to this brightness instead of the saved one (or the
+        /// maximum if none was saved).
+        ///
+        #[structopt(long, requires = "threshold")]
+        high: Option<String>,
+
+        /// The brightness to dim (or brighten) to, as for `dimmer set`.
+        ///
+        #[structopt(default_value = "0", allow_hyphen_values = true)]
+        target: String,
+    },
+
+    /// Transition to a preset from the configuration, which bundles a target, duration and easing
+    /// under a name (e.g. `dimmer preset movie`).
+    Preset {
+        /// The name of the preset, as in the `[preset.<name>]` table of the configuration.
+        ///
+        name: String,
+    },
+
+    /// Keep running, transitioning the devices as requested on a Unix socket. The transition
+    /// options are the defaults for the transitions requested.
+    Daemon {
+        #[structopt(flatten)]
+        transition: TransitionOpt,
+
+        /// Take requests on the D-Bus session bus as well, as `org.koenw.Dimmer1`. Requires
+        /// building dimmer with the `dbus` feature.
+        ///
+        #[structopt(long)]
+        dbus: bool,
+
+        /// Serve a small HTTP API as well, on a TCP address like "127.0.0.1:7878" or the path of a
+        /// Unix socket, instead of the `http` address in the configuration, if any.
+        ///
+        #[structopt(long)]
+        http: Option<String>,
+
+        /// Log to journald instead of stderr, at the level set with `-v`. Requires building
+        /// dimmer with the `journald` feature.
+        ///
+        #[structopt(long)]
+        journald: bool,
+    },
+
+    /// Send a request to the daemon.
+    Ctl {
+        #[structopt(subcommand)]
+        cmd: Ctl,
+    },
+
+    /// Print a udev rule giving write access to the selected devices, so dimmer works without
+    /// root.
+    SetupPermissions {
+        /// The group to give write access to. Defaults to "video".
+        ///
+        #[structopt(long)]
+        group: Option<String>,
+
+        /// Give write access to the user logged in at the seat through systemd's uaccess ACLs
+        /// instead of a group. This only works for device nodes, like those of the drm backend.
+        ///
+        #[structopt(long, conflicts_with = "group")]
+        uaccess: bool,
+
+        /// Install the rule in `/etc/udev/rules.d` and have udev apply it, using sudo unless
+        /// running as root.
+        ///
+        #[structopt(long)]
+        install: bool,
+    },
+
+    /// Pulse the brightness between a low and a high level with a smooth waveform until stopped,
+    /// e.g. as a notification or standby indicator.
+    #[structopt(setting = AppSettings::AllowLeadingHyphen)]
+    Pulse {
+        /// The brightness at the bottom of every pulse, as for `dimmer set`.
+        ///
+        #[structopt(long, default_value = "20%", allow_hyphen_values = true)]
+        low: String,
+
+        /// The brightness at the top of every pulse, as for `dimmer set`.
+        ///
+        #[structopt(long, default_value = "60%", allow_hyphen_values = true)]
+        high: String,
+
+        /// How long a single pulse takes, from the top down to the bottom and back up.
+        ///
+        #[structopt(long, default_value = "4s")]
+        period: Duration,
+
+        /// How many times to pulse before going back to the brightness from before, or "forever"
+        /// until stopped.
+        ///
+        #[structopt(long, default_value = "forever")]
+        repeat: Repeat,
+
+        /// Pulse at a visually constant pace, like `set --perceptual`.
+        ///
+        #[structopt(long)]
+        perceptual: bool,
+
+        /// What to do when stopped by SIGINT or SIGTERM, as for `dimmer set`, except that the
+        /// brightness from before pulsing is restored by default.
+        ///
+        #[structopt(long, default_value = "restore")]
+        on_interrupt: OnInterrupt,
+    },
+
+    /// Flash the screen a few times as an attention cue, by dipping the brightness and bringing it
+    /// back.
+    Flash {
+        /// How many times to flash.
+        ///
+        #[structopt(long, default_value = "3")]
+        count: u64,
+
+        /// How far to dip below the current brightness every flash, either absolute or as a
+        /// percentage (e.g. "50%"), as for a relative target.
+        ///
+        #[structopt(long, default_value = "50%")]
+        depth: String,
+
+        /// How long a single flash takes, down and back up. Flashing more than three times a
+        /// second can trigger seizures, so it can't be any shorter than a third of a second.
+        ///
+        #[structopt(long, default_value = "500ms")]
+        speed: Duration,
+    },
+
+    /// Stop the transition of the dimmer that's currently transitioning, leaving the brightness
+    /// where it is.
+    Cancel {
+        /// Jump back to the brightness the transition started from instead.
+        ///
+        #[structopt(long)]
+        restore: bool,
+    },
+
+    /// List the devices found for the backend, with their class, current and maximum brightness.
+    List {
+        /// Print the devices as JSON.
+        ///
+        #[structopt(long, conflicts_with = "format")]
+        json: bool,
+
+        /// Print each device in this format, as for `dimmer get --format`.
+        ///
+        #[structopt(long)]
+        format: Option<String>,
+    },
+
+    /// Measure how long writing the brightness takes on the selected devices, and recommend a
+    /// framerate they keep up with. DDC monitors often can't take 60 writes per second. The
+    /// brightness flickers between the current level and the one next to it while measuring.
+    Bench {
+        /// How many writes to time.
+        ///
+        #[structopt(long, default_value = "100")]
+        writes: u64,
+    },
+
+    /// Find out how bright the brightness levels of the selected devices look, by stepping up
+    /// from off to full brightness and adjusting every step until they all look alike. The
+    /// result is saved to the `calibration` table of the configuration, after which transitions
+    /// of the device fade at a visually constant pace along it.
+    Calibrate {
+        /// How many steps to go from off to full brightness in.
+        ///
+        #[structopt(long, default_value = "10")]
+        steps: u64,
+    },
+}
+
+/// The requests `dimmer ctl` sends to the daemon.
+#[derive(Debug, StructOpt)]
+enum Ctl {
+    /// Transition to the target brightness.
+    #[structopt(setting = AppSettings::AllowLeadingHyphen)]
+    Set {
+        /// How long the transition should take, instead of the daemon's default.
+        ///
+        #[structopt(long)]
+        duration: Option<Duration>,
+
+        /// The brightness to target, as for `dimmer set`.
+        ///
+        #[structopt(allow_hyphen_values = true)]
+        target: String,
+    },
+
+    /// Remember the current brightness, unless dimmed already, and transition to the target
+    /// brightness.
+    #[structopt(setting = AppSettings::AllowLeadingHyphen)]
+    Dim {
+        /// How long the transition should take, instead of the daemon's default.
+        ///
+        #[structopt(long)]
+        duration: Option<Duration>,
+
+        /// The brightness to target, as for `dimmer set`.
+        ///
+        #[structopt(default_value = "0", allow_hyphen_values = true)]
+        target: String,
+    },
+
+    /// Transition back to the brightness remembered when dimming.
+    Restore {
+        /// How long the transition should take, instead of the daemon's default.
+        ///
+        #[structopt(long)]
+        duration: Option<Duration>,
+    },
+
+    /// Have the daemon read its configuration file again, like on SIGHUP.
+    Reload,
+
+    /// Print the brightness of the daemon's devices.
+    Status {
+        /// Print the daemon's response as JSON.
+        ///
+        #[structopt(long)]
+        json: bool,
+    },
+}
+
+/// A transition chained after `dimmer set` with `--then`.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "--then", setting = AppSettings::AllowLeadingHyphen)]
+struct Then {
+    /// How long to hold the brightness reached so far before starting this transition.
+    ///
+    #[structopt(long)]
+    after: Option<Duration>,
+
+    /// How long this transition should take, instead of the duration of the first one.
+    ///
+    #[structopt(long)]
+    duration: Option<Duration>,
+
+    /// The brightness to target, as for `dimmer set`. Relative targets are relative to the target
+    /// of the transition before.
+    ///
+    #[structopt(allow_hyphen_values = true)]
+    target: String,
+}
+
+impl Then {
+    /// Take the transitions chained with `--then` off the end of the command line `args`.
+    fn split_off(args: &mut Vec<OsString>) -> Result<Vec<Then>, structopt::clap::Error> {
+        let Some(start) = args.iter().position(|arg| arg == "--then") else {
+            return Ok(Vec::new());
+        };
+        let chained = args.split_off(start);
+        let mut starts: Vec<_> = chained
+            .iter()
+            .enumerate()
+            .filter(|(_, arg)| *arg == "--then")
+            .map(|(index, _)| index)
+            .collect();
+        starts.push(chained.len());
+        starts
+            .windows(2)
+            .map(|range| Then::from_iter_safe(&chained[range[0]..range[1]]))
+            .collect()
+    }
+
+    /// Append the segments for this transition to the `segments` before it, for a device at
+    /// brightness `current` out of `maximum`.
+    fn append_to(
+        &self,
+        segments: &mut Vec<Segment>,
+        current: Brightness,
+        maximum: Brightness,
+    ) -> Result<()> {
+        let last = segments.last().map_or(current, |segment| segment.target);
+        if let Some(after) = self.after {
+            segments.push(Segment {
+                target: last,
+                duration: Some(after.into()),
+            });
+        }
+        segments.push(Segment {
+            target: Brightness::parse_with_percentage(&self.target, last, maximum)?,
+            duration: self.duration.map(Into::into),
+        });
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, StructOpt)]
+struct TransitionOpt {
     /// How long it should take for the screen to go from it's current
-    /// brightness to zero brightness.
+    /// brightness to the target brightness.
     ///
-    #[structopt(long, default_value = "5s")]
+    #[structopt(long, default_value = "5s", env = "DIMMER_DURATION")]
     duration: Duration,
 
-    /// How many times per second the brightness will be updated.
+    /// Treat the duration as the time it takes to go from zero to full brightness, so a 10% change
+    /// only takes a tenth of it.
+    ///
+    #[structopt(long)]
+    full_scale_duration: bool,
+
+    /// How many times per second the brightness will be updated, or "auto" to update it once for
+    /// every brightness level the transition crosses, up to 120 times per second.
+    ///
+    #[structopt(long, default_value = "auto")]
+    framerate: Framerate,
+
+    /// How many times the brightness will be updated over the whole transition, instead of a
+    /// number of times per second. This suits devices with only a few brightness levels.
+    ///
+    #[structopt(long, conflicts_with = "framerate")]
+    steps: Option<u64>,
+
+    /// The curve the brightness follows during the transition: "linear", "ease-in", "ease-out",
+    /// "ease-in-out", "smoothstep" or a CSS style "cubic-bezier(x1,y1,x2,y2)".
+    ///
+    #[structopt(long, default_value = "linear", env = "DIMMER_EASE")]
+    ease: Easing,
+
+    /// Fade at a visually constant pace, by interpolating the perceived (CIE L*) lightness instead
+    /// of the raw brightness.
+    ///
+    #[structopt(long)]
+    perceptual: bool,
+
+    /// Flicker between the two nearest brightness levels during the transition, so it doesn't
+    /// look like a staircase on devices with only a handful of levels.
     ///
-    #[structopt(long, default_value = "60")]
-    framerate: u64,
+    #[structopt(long)]
+    dither: bool,
 
-    /// Save the current brightness to the statefile.
+    /// The lowest brightness to transition to, either absolute or as a percentage (e.g. "5%").
+    /// Targets below it are raised to it, for panels that switch off entirely at 0.
     ///
-    #[structopt(long, short)]
-    save: bool,
+    #[structopt(long, env = "DIMMER_MIN")]
+    min: Option<String>,
 
-    /// Restore previously saved brightness from the statefile.
+    /// Power the backlight off through its `bl_power` file once it has faded to 0. It's powered
+    /// back on whenever it's brightened again.
     ///
-    #[structopt(long, short)]
-    restore: bool,
+    #[structopt(long)]
+    power_off: bool,
 
-    /// The brightness to target. Can either be an absolute value between 0 and the value in the
-    /// file at `max-brightness-path`, or an percentage (e.g. "0%" to "100%").
+    /// Don't read back the brightness once the transition is done to check the device got there.
+    /// Some firmware silently clamps or ignores writes, so by default we write the target again a
+    /// few times, and exit with status 3 if the device never reaches it.
     ///
-    #[structopt(default_value = "0")]
-    target_str: String,
+    #[structopt(long)]
+    no_verify: bool,
+
+    /// Print the frames the transition would write and when, without writing any, or saving or
+    /// interrupting anything.
+    ///
+    #[structopt(long)]
+    dry_run: bool,
+
+    /// Print the percentage on stdout whenever it changes during the transition, to drive OSD
+    /// popups or progress bars, or a line of JSON (or Waybar's JSON) per change with
+    /// `--progress=json` (or `--progress=waybar`).
+    ///
+    #[structopt(long, require_equals = true, min_values = 0)]
+    progress: Option<Option<Output>>,
+
+    /// What to do when interrupted by SIGINT or SIGTERM during the transition: "finish" jumps
+    /// straight to the target, "restore" jumps back to where the transition started and "stop"
+    /// leaves the brightness where it is. Defaults to "stop", or to "restore" for repeated
+    /// animations.
+    ///
+    #[structopt(long, env = "DIMMER_ON_INTERRUPT")]
+    on_interrupt: Option<OnInterrupt>,
+
+    /// The most writes per second the device takes, from its quirks.
+    #[structopt(skip)]
+    max_framerate: Option<u64>,
+
+    /// How bright the levels of the device look, from its calibration.
+    #[structopt(skip)]
+    calibration: Option<Curve>,
 }
 
-const SYS_BACKLIGHT_PREFIX: &str = "/sys/class/backlight";
+impl TransitionOpt {
+    /// The options for the segments of an animation, each taking `duration` along the `ease`
+    /// curve.
+    fn segments(
+        duration: std::time::Duration,
+        ease: Easing,
+        on_interrupt: OnInterrupt,
+    ) -> TransitionOpt {
+        TransitionOpt {
+            duration: duration.into(),
+            full_scale_duration: false,
+            framerate: Framerate::Auto,
+            steps: None,
+            ease,
+            perceptual: false,
+            dither: false,
+            min: None,
+            power_off: false,
+            no_verify: false,
+            dry_run: false,
+            progress: None,
+            on_interrupt: Some(on_interrupt),
+            max_framerate: None,
+            calibration: None,
+        }
+    }
 
-fn main() -> Result<()> {
-    let opt = Opt::from_args();
+    /// These options for the device called `name`, working around its quirks from the built-in
+    /// table and the `config`, and fading along its calibration if it has one.
+    fn for_device(&self, name: &str, config: &Config) -> TransitionOpt {
+        let quirks = quirks::of(name, &config.quirks);
+        let mut transition = self.clone();
+        if self.min.is_none() && config.min.is_none() {
+            transition.min = quirks.min;
+        }
+        transition.perceptual |= quirks.perceptual.unwrap_or(false);
+        transition.max_framerate = quirks.max_framerate;
+        transition.calibration = config.calibration.get(name).cloned();
+        transition
+    }
 
-    let brightness_file = opt.brightness_file.unwrap_or(find_file("brightness")?);
+    /// Keep `target` between the minimum brightness, from these options or the `config`, and
+    /// `maximum`.
+    fn clamp(
+        &self,
+        config: &Config,
+        target: Brightness,
+        current: Brightness,
+        maximum: Brightness,
+    ) -> Result<Brightness> {
+        let target = if target > maximum { maximum } else { target };
+        match self.min.as_deref().or(config.min.as_deref()) {
+            Some(min) => {
+                let min = Brightness::parse_with_percentage(min, current, maximum)
+                    .context("Failed to parse the minimum brightness")?;
+                let min = if min > maximum { maximum } else { min };
+                Ok(if target < min { min } else { target })
+            }
+            None => Ok(target),
+        }
+    }
 
-    let current_brightness_file = opt
-        .current_brightness_file
-        .unwrap_or(find_file("actual_brightness")?);
+    /// The transition from `from` through `segment`, which may take a duration of its own, for a
+    /// device with brightness `maximum`.
+    fn segment(&self, from: Brightness, segment: &Segment, maximum: Brightness) -> Transition {
+        match segment.duration {
+            Some(duration) => TransitionOpt {
+                duration: duration.into(),
+                full_scale_duration: false,
+                ..self.clone()
+            }
+            .fade(from, segment.target, maximum),
+            None => self.fade(from, segment.target, maximum),
+        }
+    }
 
-    let max_brightness_file = opt
-        .max_brightness_file
-        .unwrap_or(find_file("max_brightness")?);
+    /// Check `device` actually reached `target`, give or take a percent of its maximum
+    /// brightness, unless told not to.
+    fn verify(&self, device: &mut Device, target: Brightness) -> Result<()> {
+        if self.no_verify {
+            return Ok(());
+        }
+        device.verify(target, verify_tolerance(device.max()?))
+    }
 
-    let state_file = opt.state_file.unwrap_or_else(|| {
-        let dirs = xdg::BaseDirectories::with_prefix("dimmer")
-            .expect("Failed to setup XDG base directories");
-        dirs.place_config_file("stored_brightness")
-            .expect("Failed to create xdg config path")
-    });
+    /// The transition from `current` to `target` these options make for a device with brightness
+    /// `maximum`.
+    fn fade(&self, current: Brightness, target: Brightness, maximum: Brightness) -> Transition {
+        let mut fade = Transition::new(current, target)
+            .duration(self.duration.into())
+            .easing(self.ease);
+        fade = match self.framerate {
+            Framerate::Auto => fade.auto_framerate(),
+            Framerate::Fixed(framerate) => fade.framerate(framerate),
+        };
+        if let Some(curve) = &self.calibration {
+            fade = fade.perceptual_curve(maximum, curve.clone());
+        } else if self.perceptual {
+            fade = fade.perceptual(maximum);
+        }
+        if self.dither {
+            fade = fade.dither();
+        }
+        if self.full_scale_duration {
+            fade = fade.full_scale(maximum);
+        }
+        if let Some(steps) = self.steps {
+            fade = fade.steps(steps);
+        }
+        if let Some(framerate) = self.max_framerate {
+            fade = fade.max_framerate(framerate);
+        }
+        fade
+    }
+}
 
-    let duration = opt.duration.as_secs();
+/// A VCP code of a DDC/CI monitor, as the MCCS standard numbers them.
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(not(all(feature = "windows", target_os = "windows")), allow(dead_code))]
+struct Vcp(u8);
 
-    let stored: Brightness = Brightness::from_file(&current_brightness_file)?;
-    let maximum: Brightness = Brightness::from_file(&max_brightness_file)?;
+impl std::str::FromStr for Vcp {
+    type Err = std::num::ParseIntError;
 
-    if opt.save {
-        save(&state_file, stored)?;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input
+            .strip_prefix("0x")
+            .or_else(|| input.strip_prefix("0X"))
+        {
+            Some(hex) => u8::from_str_radix(hex, 16).map(Vcp),
+            None => input.parse().map(Vcp),
+        }
     }
+}
 
-    let target: Brightness = if opt.restore {
-        Brightness::from_file(state_file)?
-    } else {
-        Brightness::parse_with_percentage(&opt.target_str, maximum)?
-    };
-    let target = if target > maximum { maximum } else { target };
+#[derive(Debug, Copy, Clone)]
+enum Framerate {
+    Auto,
+    Fixed(u64),
+}
 
-    let total_frames = duration * opt.framerate;
+impl std::str::FromStr for Framerate {
+    type Err = std::num::ParseIntError;
 
-    let (step_size, dimming): (u64, bool) = match (target.0, stored.0) {
-        (t, o) if t > o => ((t - o) / total_frames, false),
-        (t, o) if o > t => ((o - t) / total_frames, true),
-        (_t, _o) => exit(0),
-    };
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "auto" => Ok(Framerate::Auto),
+            _ => input.parse().map(Framerate::Fixed),
+        }
+    }
+}
+
+/// The target of `dimmer set`, either for every device, or for each device by name.
+#[derive(Debug, Clone)]
+enum Targets {
+    All(String),
+    PerDevice(BTreeMap<String, String>),
+}
+
+impl Targets {
+    /// The target for the device called `name`, which may have been given by the path to its
+    /// directory.
+    fn get(&self, name: &str) -> Result<&str> {
+        match self {
+            Targets::All(target) => Ok(target),
+            Targets::PerDevice(targets) => targets
+                .iter()
+                .find(|(device, _)| {
+                    *device == name || Path::new(device).file_name() == Some(name.as_ref())
+                })
+                .map(|(_, target)| target.as_str())
+                .with_context(|| format!("No target given for {name}")),
+        }
+    }
+}
+
+impl std::str::FromStr for Targets {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        if !input.contains('=') {
+            return Ok(Targets::All(input.to_string()));
+        }
+        input
+            .split(',')
+            .map(|target| match target.split_once('=') {
+                Some((name, target)) if !name.is_empty() => {
+                    Ok((name.to_string(), target.to_string()))
+                }
+                _ => bail!("Failed to parse {target:?}, expected a device and a target like \"intel_backlight=30%\""),
+            })
+            .collect::<Result<_>>()
+            .map(Targets::PerDevice)
+    }
+}
 
-    let output = File::create(&brightness_file)?;
-    let mut brightness = stored;
-    for _i in 0..total_frames {
-        if dimming {
-            if brightness.0 < step_size {
-                brightness = Brightness(0);
+/// How `get`, `watch` and `--progress` print the brightness.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Output {
+    Plain,
+    Json,
+    Waybar,
+}
+
+impl std::str::FromStr for Output {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "plain" => Ok(Output::Plain),
+            "json" => Ok(Output::Json),
+            "waybar" => Ok(Output::Waybar),
+            _ => bail!("Unknown output {input}"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum OnInterrupt {
+    Finish,
+    Restore,
+    Stop,
+}
+
+impl std::str::FromStr for OnInterrupt {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "finish" => Ok(OnInterrupt::Finish),
+            "restore" => Ok(OnInterrupt::Restore),
+            "stop" => Ok(OnInterrupt::Stop),
+            _ => bail!("Unknown interrupt action {input}"),
+        }
+    }
+}
+
+/// The `dimmer set` command the preset called `name` in `config` stands for. Options the preset
+/// leaves out come from the environment or the defaults, as they would for `dimmer set`.
+fn preset(config: &Config, name: &str) -> Result<Command> {
+    let preset = config
+        .preset
+        .get(name)
+        .with_context(|| format!("There is no preset called {name} in the configuration"))?;
+    let mut args = vec!["dimmer".to_string(), "set".to_string()];
+    if let Some(duration) = &preset.duration {
+        args.extend(["--duration".to_string(), duration.clone()]);
+    }
+    if let Some(ease) = &preset.ease {
+        args.extend(["--ease".to_string(), ease.clone()]);
+    }
+    args.push(preset.target.clone());
+    Command::from_iter_safe(args).map_err(|err| {
+        let message = err.message.lines().next().unwrap_or_default();
+        anyhow::anyhow!(
+            "Invalid preset {name}: {}",
+            message.trim_start_matches("error: ")
+        )
+    })
+}
+
+fn main() {
+    if let Err(err) = run() {
+        if !err.is::<Stopped>() {
+            eprintln!("Error: {err:?}");
+            if let Some(hint) = permissions::diagnose(&err) {
+                eprintln!("\n{hint}");
+            }
+        }
+        std::process::exit(exit_status(&err));
+    }
+}
+
+/// The status to exit with after `err`, so scripts can tell what went wrong.
+fn exit_status(err: &anyhow::Error) -> i32 {
+    match err.downcast_ref::<Stopped>() {
+        Some(Stopped::Unchanged) => return EXIT_UNCHANGED,
+        Some(Stopped::NotReached) => return EXIT_NOT_REACHED,
+        // Like shells report a command killed by a signal.
+        Some(Stopped::Interrupted(signal)) => return 128 + signal,
+        None => {}
+    }
+    for cause in err.chain() {
+        match cause.downcast_ref::<DimmerError>() {
+            Some(
+                DimmerError::InvalidPercentage
+                | DimmerError::InvalidBrightness(_)
+                | DimmerError::UnknownBackend(_)
+                | DimmerError::UnknownEasing(_)
+                | DimmerError::InvalidCubicBezier(_),
+            ) => return EXIT_INVALID,
+            Some(DimmerError::DeviceNotFound(_)) => return EXIT_NOT_FOUND,
+            Some(DimmerError::PermissionDenied(_)) => return EXIT_PERMISSION_DENIED,
+            Some(DimmerError::TargetNotReached { .. }) => return EXIT_NOT_REACHED,
+            None => {}
+        }
+        if cause.is::<toml::de::Error>() {
+            return EXIT_INVALID;
+        }
+        if let Some(err) = cause.downcast_ref::<std::io::Error>() {
+            if err.kind() == std::io::ErrorKind::PermissionDenied {
+                return EXIT_PERMISSION_DENIED;
+            }
+        }
+    }
+    if dimmer::is_removed(err) {
+        return EXIT_NOT_FOUND;
+    }
+    EXIT_FAILURE
+}
+
+/// Exit for the command line clap failed to parse with `err`, with `EXIT_INVALID` unless it was
+/// asked for help or the version.
+fn invalid_arguments<T>(err: structopt::clap::Error) -> T {
+    if !err.use_stderr() {
+        // Help and version information, which clap exits successfully after printing.
+        err.exit();
+    }
+    eprintln!("{}", err.message);
+    std::process::exit(EXIT_INVALID);
+}
+
+fn run() -> Result<()> {
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    let chained = Then::split_off(&mut args).unwrap_or_else(invalid_arguments);
+    let mut opt = Opt::from_iter_safe(args).unwrap_or_else(invalid_arguments);
+    let journald = matches!(opt.cmd, Command::Daemon { journald: true, .. });
+    logging::init(opt.verbose, journald)?;
+    opt.devices_from_env();
+    #[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+    opt.fall_back_from_sysfs();
+    let config = Config::load(opt.config.as_deref())?;
+    opt.mappings = config.mapping.clone();
+    if opt.vcp.is_some() && !opt.takes_vcp() {
+        bail!("Only monitors controlled over DDC/CI by the windows backend take --vcp");
+    }
+    if let Command::Preset { name } = &opt.cmd {
+        opt.cmd = preset(&config, name)?;
+    }
+    if !chained.is_empty() && !matches!(opt.cmd, Command::Set { .. } | Command::Plan { .. }) {
+        bail!("Only `dimmer set` and `dimmer plan` take transitions chained with --then");
+    }
+
+    match &opt.cmd {
+        Command::Set {
+            transition,
+            save: should_save,
+            save_only,
+            slot,
+            keyframes,
+            timeline,
+            repeat,
+            only_if_above,
+            only_if_below,
+            target,
+        } => {
+            let devices = || match target {
+                Some(Targets::PerDevice(targets)) => opt.named_devices(targets.keys()),
+                _ => opt.devices(),
+            };
+            if *save_only {
+                return save(&opt.state_file(&config)?, &devices()?, slot.as_deref());
+            }
+            let _lock = take_over(transition)?;
+            let devices = devices()?;
+            if *should_save && !transition.dry_run {
+                save(&opt.state_file(&config)?, &devices, slot.as_deref())?;
+            }
+            let keyframes = match (keyframes, timeline) {
+                (Some(keyframes), _) => Some(keyframes.clone()),
+                (None, Some(timeline)) => Some(Keyframes::load(timeline)?),
+                (None, None) => None,
+            };
+            if repeat.is_some() && keyframes.is_none() && chained.is_empty() {
+                bail!("Only animations with --keyframes, --timeline or --then can be repeated");
+            }
+            let mut transition = transition.clone();
+            if repeat.is_some() && transition.on_interrupt.is_none() {
+                transition.on_interrupt = Some(OnInterrupt::Restore);
+            }
+            let changed = animate_devices(
+                devices,
+                &opt,
+                &transition,
+                &config,
+                repeat.unwrap_or(Repeat::Times(1)),
+                |name, current, maximum| {
+                    let guards = (only_if_above.as_deref(), only_if_below.as_deref());
+                    if !passes_guards(guards, current, maximum)? {
+                        tracing::info!(device = name, current = current.0, "Leaving alone");
+                        return Ok(Vec::new());
+                    }
+                    set_segments(
+                        keyframes.as_ref(),
+                        target.as_ref(),
+                        &chained,
+                        name,
+                        current,
+                        maximum,
+                    )
+                },
+            )?;
+            if !changed {
+                return Err(Stopped::Unchanged.into());
+            }
+            Ok(())
+        }
+        Command::Plan {
+            transition,
+            keyframes,
+            timeline,
+            repeat,
+            json,
+            target,
+        } => {
+            let devices = match target {
+                Some(Targets::PerDevice(targets)) => opt.named_devices(targets.keys())?,
+                _ => opt.devices()?,
+            };
+            let keyframes = match (keyframes, timeline) {
+                (Some(keyframes), _) => Some(keyframes.clone()),
+                (None, Some(timeline)) => Some(Keyframes::load(timeline)?),
+                (None, None) => None,
+            };
+            let plans = plan_devices(
+                devices,
+                &opt,
+                transition,
+                &config,
+                repeat.unwrap_or(Repeat::Times(1)),
+                |name, current, maximum| {
+                    set_segments(
+                        keyframes.as_ref(),
+                        target.as_ref(),
+                        &chained,
+                        name,
+                        current,
+                        maximum,
+                    )
+                },
+            )?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&plans)?);
+            } else {
+                plans.iter().for_each(Plan::print);
+            }
+            Ok(())
+        }
+        Command::Get {
+            percent,
+            raw,
+            json,
+            format,
+            output,
+        } => {
+            let devices = opt.devices()?;
+            let named = devices.len() > 1;
+            for device in &devices {
+                let status = device.status()?;
+                let line = if *percent {
+                    format!("{:.0}", status.percent)
+                } else if *raw {
+                    status.current.to_string()
+                } else {
+                    let output = if *json { Output::Json } else { *output };
+                    println!("{}", render(&status, output, format.as_deref(), named)?);
+                    continue;
+                };
+                if named {
+                    println!("{}: {line}", device.name());
+                } else {
+                    println!("{line}");
+                }
+            }
+            Ok(())
+        }
+        Command::Watch {
+            interval,
+            json,
+            format,
+            output,
+        } => {
+            let devices = opt.devices()?;
+            let named = devices.len() > 1;
+            let output = if *json { Output::Json } else { *output };
+            watch::watch(&devices, (*interval).into(), |status| {
+                let line = render(status, output, format.as_deref(), named)?;
+                writeln!(std::io::stdout(), "{line}")?;
+                Ok(())
+            })
+        }
+        Command::Follow { transition } => {
+            if transition.dry_run {
+                bail!("Following levels from stdin can't be dry run");
+            }
+            if transition.progress.is_some() {
+                bail!("`dimmer follow` doesn't print its progress, try `dimmer watch` instead");
+            }
+            let _lock = Lock::take_over(&instance::lock_file())?;
+            let devices = opt.devices()?;
+            follow::follow(devices, transition, &config, std::io::stdin().lock())
+        }
+        Command::Save { slot } => save(&opt.state_file(&config)?, &opt.devices()?, slot.as_deref()),
+        Command::Dim { transition, to } => {
+            let _lock = take_over(transition)?;
+            transition_towards(opt.devices()?, &opt, transition, &config, to, true)
+        }
+        Command::Brighten { transition, to } => {
+            let _lock = take_over(transition)?;
+            transition_towards(opt.devices()?, &opt, transition, &config, to, false)
+        }
+        Command::Restore {
+            transition,
+            slot,
+            max_age,
+            fallback,
+        } => {
+            let _lock = take_over(transition)?;
+            let devices = opt.devices()?;
+            let state = State::load(&opt.state_file(&config)?)?;
+            let max_age = max_age.or(config.max_age.map(Duration::from));
+            let fallback = fallback.as_deref().or(config.fallback.as_deref());
+            transition_devices(
+                devices,
+                &opt,
+                transition,
+                &config,
+                |name, current, maximum| {
+                    let saved =
+                        state.restore(name, maximum, slot.as_deref(), max_age.map(Into::into))?;
+                    match (saved, fallback, max_age) {
+                        (Some(saved), _, _) => Ok(saved),
+                        (None, Some(fallback), _) => {
+                            Brightness::parse_with_percentage(fallback, current, maximum)
+                        }
+                        (None, None, Some(max_age)) => bail!(
+                        "The brightness saved for {name} is older than {max_age}, and there's no \
+                         fallback to restore instead"
+                    ),
+                        (None, None, None) => {
+                            unreachable!("only a maximum age makes a saved brightness too old")
+                        }
+                    }
+                },
+            )
+        }
+        Command::Toggle {
+            transition,
+            threshold,
+            high,
+            target,
+        } => {
+            let _lock = take_over(transition)?;
+            let devices = opt.devices()?;
+            let state_file = opt.state_file(&config)?;
+            if let Some(threshold) = threshold {
+                let is_bright = |current, maximum| -> Result<bool> {
+                    Ok(current > Brightness::parse_with_percentage(threshold, current, maximum)?)
+                };
+                if !transition.dry_run {
+                    State::update(&state_file, |state| {
+                        for device in &devices {
+                            if is_bright(device.current()?, device.max()?)? {
+                                state.save(device, None)?;
+                            }
+                        }
+                        Ok(())
+                    })?;
+                }
+                let state = State::load(&state_file)?;
+                return transition_devices(
+                    devices,
+                    &opt,
+                    transition,
+                    &config,
+                    |name, current, maximum| match (is_bright(current, maximum)?, high) {
+                        (true, _) => Brightness::parse_with_percentage(target, current, maximum),
+                        (false, Some(high)) => {
+                            Brightness::parse_with_percentage(high, current, maximum)
+                        }
+                        (false, None) if !state.is_saved(name, None) => Ok(maximum),
+                        (false, None) => {
+                            Ok(state.restore(name, maximum, None, None)?.unwrap_or(maximum))
+                        }
+                    },
+                );
+            }
+
+            let state = State::load(&state_file)?;
+            if state.is_toggled() {
+                if !transition.dry_run {
+                    State::update(&state_file, |state| {
+                        state.set_toggled(false);
+                        Ok(())
+                    })?;
+                }
+                transition_devices(
+                    devices,
+                    &opt,
+                    transition,
+                    &config,
+                    |name, current, maximum| {
+                        Ok(state.restore(name, maximum, None, None)?.unwrap_or(current))
+                    },
+                )
+            } else {
+                if !transition.dry_run {
+                    State::update(&state_file, |state| {
+                        for device in &devices {
+                            state.save(device, None)?;
+                        }
+                        state.set_toggled(true);
+                        Ok(())
+                    })?;
+                }
+                transition_devices(
+                    devices,
+                    &opt,
+                    transition,
+                    &config,
+                    |_name, current, maximum| {
+                        Brightness::parse_with_percentage(target, current, maximum)
+                    },
+                )
+            }
+        }
+        Command::Preset { .. } => unreachable!("presets are resolved into `dimmer set` above"),
+        Command::Daemon {
+            transition,
+            dbus,
+            http,
+            ..
+        } => {
+            if transition.dry_run {
+                bail!("The daemon can't be dry run, try `dimmer set --dry-run` instead");
+            }
+            if transition.progress.is_some() {
+                bail!("The daemon doesn't print its progress, try `dimmer watch` instead");
+            }
+            let socket = opt.socket.clone().unwrap_or_else(daemon::socket_path);
+            let mut config = config;
+            if let Some(http) = http {
+                config.http = Some(http.clone());
+            }
+            daemon::run(
+                &socket,
+                opt.devices()?,
+                opt.opener(),
+                transition.clone(),
+                config,
+                opt.config.clone(),
+                *dbus,
+            )
+        }
+        Command::Ctl { cmd } => {
+            let socket = opt.socket.clone().unwrap_or_else(daemon::socket_path);
+            let duration =
+                |duration: &Option<Duration>| duration.map(|duration| duration.to_string());
+            let request = match cmd {
+                Ctl::Set {
+                    duration: time,
+                    target,
+                } => Request::Set {
+                    target: target.clone(),
+                    duration: duration(time),
+                },
+                Ctl::Dim {
+                    duration: time,
+                    target,
+                } => Request::Dim {
+                    target: target.clone(),
+                    duration: duration(time),
+                },
+                Ctl::Restore { duration: time } => Request::Restore {
+                    duration: duration(time),
+                },
+                Ctl::Reload => Request::Reload,
+                Ctl::Status { .. } => Request::Status,
+            };
+            let response = daemon::request(&socket, &request)?;
+            match cmd {
+                Ctl::Status { json: true } => {
+                    println!("{}", serde_json::to_string_pretty(&response)?)
+                }
+                Ctl::Status { json: false } => {
+                    response.devices.iter().flatten().for_each(print_status);
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+        Command::SetupPermissions {
+            group,
+            uaccess,
+            install,
+        } => {
+            let access = match (uaccess, group) {
+                (true, _) => Access::Uaccess,
+                (false, Some(group)) => Access::Group(group.clone()),
+                (false, None) => Access::Group(permissions::DEFAULT_GROUP.to_string()),
+            };
+            for device in opt.devices()? {
+                let file = format!("/sys/class/{}/{}/brightness", device.class(), device.name());
+                if opt.backend == Kind::Sysfs && permissions::is_writable(Path::new(&file)) {
+                    eprintln!("Note: {file} is writable already");
+                }
+                let rule = permissions::udev_rule(opt.backend, &device, &access)?;
+                print!("{rule}");
+                if *install {
+                    let path = permissions::rule_path(device.name());
+                    permissions::install(&rule, &path)?;
+                    eprintln!("Installed {}", path.display());
+                }
+            }
+            Ok(())
+        }
+        Command::Pulse {
+            low,
+            high,
+            period,
+            repeat,
+            perceptual,
+            on_interrupt,
+        } => {
+            let _lock = Lock::take_over(&instance::lock_file())?;
+            let devices = opt.devices()?;
+            let transition = TransitionOpt {
+                perceptual: *perceptual,
+                ..TransitionOpt::segments(**period / 2, Easing::EaseInOut, *on_interrupt)
+            };
+            // A bounded number of pulses is spelled out, so we can head back to where we started.
+            let (pulses, repeat) = match repeat {
+                Repeat::Times(times) => (*times, Repeat::Times(1)),
+                Repeat::Forever => (1, Repeat::Forever),
+            };
+            animate_devices(
+                devices,
+                &opt,
+                &transition,
+                &config,
+                repeat,
+                |_name, current, maximum| {
+                    let level = |level| Brightness::parse_with_percentage(level, current, maximum);
+                    let pulse = [
+                        Segment {
+                            target: level(high)?,
+                            duration: None,
+                        },
+                        Segment {
+                            target: level(low)?,
+                            duration: None,
+                        },
+                    ];
+                    let mut segments = pulse.repeat(pulses as usize);
+                    if repeat != Repeat::Forever {
+                        segments.push(Segment {
+                            target: current,
+                            duration: None,
+                        });
+                    }
+                    Ok(segments)
+                },
+            )?;
+            Ok(())
+        }
+        Command::Flash {
+            count,
+            depth,
+            speed,
+        } => {
+            if **speed < MIN_FLASH_PERIOD {
+                bail!(
+                    "Flashing faster than every {} can trigger seizures",
+                    humantime::format_duration(MIN_FLASH_PERIOD)
+                );
+            }
+            let _lock = Lock::take_over(&instance::lock_file())?;
+            let devices = opt.devices()?;
+            let transition =
+                TransitionOpt::segments(**speed / 2, Easing::Linear, OnInterrupt::Restore);
+            animate_devices(
+                devices,
+                &opt,
+                &transition,
+                &config,
+                Repeat::Times(*count),
+                |_name, current, maximum| {
+                    let dip =
+                        Brightness::parse_with_percentage(&format!("-{depth}"), current, maximum)?;
+                    Ok(vec![
+                        Segment {
+                            target: dip,
+                            duration: None,
+                        },
+                        Segment {
+                            target: current,
+                            duration: None,
+                        },
+                    ])
+                },
+            )?;
+            Ok(())
+        }
+        Command::Cancel { restore } => {
+            if !instance::cancel(&instance::lock_file(), *restore)? {
+                bail!("No transition is running");
+            }
+            Ok(())
+        }
+        Command::Bench { writes } => {
+            let _lock = Lock::take_over(&instance::lock_file())?;
+            for mut device in opt.devices()? {
+                bench::bench(&mut device, *writes)?.print(device.name());
+            }
+            Ok(())
+        }
+        Command::Calibrate { steps } => {
+            let _lock = Lock::take_over(&instance::lock_file())?;
+            for mut device in opt.devices()? {
+                let start = config
+                    .calibration
+                    .get(device.name())
+                    .unwrap_or(&Curve::Lightness);
+                let points =
+                    calibrate::calibrate(&mut device, *steps, start, std::io::stdin().lock())?;
+                let path = config::save_calibration(opt.config.as_deref(), device.name(), &points)?;
+                println!(
+                    "Saved the calibration of {} to {}",
+                    device.name(),
+                    path.display()
+                );
+            }
+            Ok(())
+        }
+        Command::List { json, format } => {
+            let statuses = opt
+                .all_devices()?
+                .iter()
+                .map(Device::status)
+                .collect::<Result<Vec<_>>>()?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&statuses)?);
+            } else if let Some(format) = format {
+                for status in &statuses {
+                    println!("{}", format::format(format, status)?);
+                }
             } else {
-                brightness = Brightness(brightness.0 - step_size);
+                statuses.iter().for_each(print_status);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The line `get` and `watch` print for `status` as `output`, in the `format` given, prefixed by
+/// the name of the device if it's one of several.
+fn render(status: &Status, output: Output, format: Option<&str>, named: bool) -> Result<String> {
+    match (output, format) {
+        (Output::Json, _) => Ok(serde_json::to_string(status)?),
+        (Output::Waybar, format) => format::waybar(status, format),
+        (Output::Plain, Some(format)) => format::format(format, status),
+        (Output::Plain, None) if named => Ok(format!("{}: {}", status.name, status_line(status))),
+        (Output::Plain, None) => Ok(status_line(status)),
+    }
+}
+
+/// The brightness, maximum and percentage of a device, as `dimmer get` prints it.
+fn status_line(status: &Status) -> String {
+    format!("{}/{} ({:.0}%)", status.current, status.max, status.percent)
+}
+
+/// Print the name, class, brightness and percentage of a device on a line.
+fn print_status(status: &Status) {
+    println!(
+        "{}\t{}\t{}/{}\t{:.0}%",
+        status.name, status.class, status.current, status.max, status.percent
+    );
+}
+
+impl Opt {
+    /// Select the devices in `$DIMMER_DEVICE` unless devices were selected on the command line.
+    /// Unlike the other environment variables, this isn't left to structopt, as it would make
+    /// `--all` and `--device-glob` conflict with the variable.
+    fn devices_from_env(&mut self) {
+        if !self.devices.is_empty() || self.all || !self.device_globs.is_empty() {
+            return;
+        }
+        if let Ok(devices) = std::env::var("DIMMER_DEVICE") {
+            self.devices = devices
+                .split(',')
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect();
+        }
+    }
+
+    /// Use the BSD's own backlight interfaces instead of sysfs, which there only is under Linux
+    /// emulation on FreeBSD, unless the brightness paths were given.
+    #[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+    fn fall_back_from_sysfs(&mut self) {
+        if self.backend == Kind::Sysfs
+            && !self.keyboard
+            && self.brightness_file.is_none()
+            && self.current_brightness_file.is_none()
+            && self.max_brightness_file.is_none()
+            && !Path::new(dimmer::backend::SYS_BACKLIGHT_PREFIX).exists()
+        {
+            tracing::info!(backend = ?SYSFS_FALLBACK, "Found no sysfs backlights");
+            self.backend = SYSFS_FALLBACK;
+        }
+    }
+
+    /// Whether the selected backend controls other VCP codes than the brightness, with `--vcp`.
+    fn takes_vcp(&self) -> bool {
+        #[cfg(all(feature = "windows", target_os = "windows"))]
+        if self.backend == Kind::Windows {
+            return true;
+        }
+        false
+    }
+
+    /// The devices to control, with any of their paths overridden by the user.
+    fn devices(&self) -> Result<Vec<Device>> {
+        if self.brightness_file.is_some()
+            || self.current_brightness_file.is_some()
+            || self.max_brightness_file.is_some()
+        {
+            if self.all || self.devices.len() > 1 {
+                bail!("Brightness paths can only be overridden for a single device");
             }
-        } else if (target.0 - brightness.0) < step_size {
-            brightness = target;
+            return Ok(vec![mapped(self.sysfs_device()?, &self.mappings)]);
+        }
+
+        let names = if self.devices.is_empty() {
+            let mut names = self.device_names()?;
+            names.retain(|name| self.is_selected(name));
+            if !self.all && self.device_globs.is_empty() {
+                names.truncate(1);
+            }
+            if names.is_empty() {
+                bail!(DimmerError::DeviceNotFound(
+                    "a device matching the device selection".to_string()
+                ));
+            }
+            names
         } else {
-            brightness = Brightness(brightness.0 + step_size);
+            self.devices.clone()
+        };
+        names.iter().map(|name| self.device(name)).collect()
+    }
+
+    /// The devices called `names`, for per-device targets, which select the devices themselves.
+    fn named_devices<'a>(&self, names: impl Iterator<Item = &'a String>) -> Result<Vec<Device>> {
+        if !self.devices.is_empty() || self.all || !self.device_globs.is_empty() {
+            bail!("Devices with a target of their own can't be selected otherwise as well");
         }
+        names.map(|name| self.device(name)).collect()
+    }
 
-        set_brightness(&output, brightness)?;
-        std::thread::sleep(std::time::Duration::from_millis(1000 / 60));
+    /// Every device found for the backend, including the keyboard backlights for sysfs.
+    fn all_devices(&self) -> Result<Vec<Device>> {
+        if self.backend == Kind::Sysfs {
+            let mut devices = Device::discover(Class::Backlight)?;
+            devices.extend(Device::discover(Class::Keyboard)?);
+            return Ok(devices
+                .into_iter()
+                .map(|device| mapped(device, &self.mappings))
+                .collect());
+        }
+        self.device_names()?
+            .iter()
+            .map(|name| self.device(name))
+            .collect()
+    }
+
+    /// Whether the device called `name` matches the `--device-glob` and `--exclude-device`
+    /// patterns.
+    fn is_selected(&self, name: &str) -> bool {
+        is_selected(&self.device_globs, &self.excluded_devices, name)
+    }
+
+    /// How the daemon opens the devices plugged in while it runs, when they're picked with `--all`
+    /// or `--device-glob` rather than by name. Only sysfs devices come and go like that.
+    fn discover(&self) -> Option<daemon::Discover> {
+        if self.backend != Kind::Sysfs || (!self.all && self.device_globs.is_empty()) {
+            return None;
+        }
+        let class = self.class();
+        let globs = self.device_globs.clone();
+        let excluded = self.excluded_devices.clone();
+        let mappings = self.mappings.clone();
+        Some(Box::new(move |name| {
+            if !is_selected(&globs, &excluded, name) {
+                return None;
+            }
+            // Not every LED is a keyboard backlight.
+            let device = Device::from_dir(find_dir(class, Some(name)).ok()?);
+            Some(mapped(device, &mappings))
+        }))
+    }
+
+    /// The device called `name`, mapped along its curve if it has one.
+    fn device(&self, name: &str) -> Result<Device> {
+        Ok(mapped(self.open(name)?, &self.mappings))
+    }
+
+    /// Open the device called `name` with the selected backend.
+    fn open(&self, name: &str) -> Result<Device> {
+        open(self.backend, self.class(), self.vcp, name)
+    }
+
+    /// The device called `name` with the selected backend, screen or keyboard backlight, mapped
+    /// along its curve if it has one, like the devices linked to the selected devices.
+    fn any_device(&self, name: &str) -> Result<Device> {
+        let device = open(self.backend, Class::Backlight, self.vcp, name)
+            .or_else(|_| open(self.backend, Class::Keyboard, self.vcp, name))?;
+        Ok(mapped(device, &self.mappings))
+    }
+
+    /// How the daemon opens the devices it didn't start with: the devices plugged in while it
+    /// runs, and devices by name with the selected backend, like the devices in its mirror.
+    /// Screens often mirror to keyboard backlights, so that's where we look as well.
+    fn opener(&self) -> daemon::Opener {
+        let (backend, vcp) = (self.backend, self.vcp);
+        daemon::Opener {
+            discover: self.discover(),
+            open: Arc::new(move |name| {
+                open(backend, Class::Backlight, vcp, name)
+                    .or_else(|_| open(backend, Class::Keyboard, vcp, name))
+            }),
+        }
+    }
+
+    /// The names of all devices found for the selected backend, sorted so picking from them is
+    /// deterministic.
+    fn device_names(&self) -> Result<Vec<String>> {
+        let mut names: Vec<String> = match self.backend {
+            Kind::Sysfs => Device::discover(self.class())?
+                .iter()
+                .map(|device| device.name().to_string())
+                .collect(),
+            #[cfg(feature = "logind")]
+            Kind::Logind => Device::discover(self.class())?
+                .iter()
+                .map(|device| device.name().to_string())
+                .collect(),
+            #[cfg(feature = "wayland")]
+            Kind::Wayland => Wayland::outputs()?,
+            #[cfg(feature = "x11")]
+            Kind::X11 => X11::outputs()?,
+            #[cfg(feature = "drm")]
+            Kind::Drm => Drm::outputs()?,
+            #[cfg(all(feature = "macos", target_os = "macos"))]
+            Kind::MacOs => MacOs::displays()?,
+            #[cfg(all(feature = "windows", target_os = "windows"))]
+            Kind::Windows => Windows::displays()?,
+            #[cfg(target_os = "freebsd")]
+            Kind::FreeBsd => FreeBsd::backlights()?,
+            #[cfg(target_os = "openbsd")]
+            Kind::OpenBsd => OpenBsd::displays()?,
+        };
+        names.sort();
+        Ok(names)
+    }
+
+    /// The sysfs device with any of its paths overridden by the user. Paths that aren't
+    /// overridden are resolved relative to the selected device.
+    fn sysfs_device(&self) -> Result<Device> {
+        let name = self.devices.first().map(String::as_str);
+        let (name, mut sysfs) = match (
+            &self.brightness_file,
+            &self.current_brightness_file,
+            &self.max_brightness_file,
+        ) {
+            (Some(brightness), Some(current), Some(max)) => (
+                name.unwrap_or("custom").to_string(),
+                Sysfs::new(brightness.clone(), current.clone(), max.clone()),
+            ),
+            _ => {
+                let dir = find_dir(self.class(), name)?;
+                let name = dir
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                (name, Sysfs::from_dir(dir))
+            }
+        };
+        if let Some(path) = &self.brightness_file {
+            sysfs.set_brightness_path(path.clone());
+        }
+        if let Some(path) = &self.current_brightness_file {
+            sysfs.set_actual_brightness_path(path.clone());
+        }
+        if let Some(path) = &self.max_brightness_file {
+            sysfs.set_max_brightness_path(path.clone());
+        }
+        Ok(Device::new(name, sysfs))
+    }
+
+    fn class(&self) -> Class {
+        if self.keyboard {
+            Class::Keyboard
+        } else {
+            Class::Backlight
+        }
+    }
+
+    fn state_file(&self, config: &Config) -> Result<PathBuf> {
+        match self.state_file.as_ref().or(config.state_file.as_ref()) {
+            Some(path) => Ok(path.clone()),
+            None => state::default_path(),
+        }
+    }
+}
+
+/// The sysfs directory of the device called `name`, or of the first device if no name is given.
+/// The name can also be the path to a device directory outside the usual sysfs location.
+fn find_dir(class: Class, name: Option<&str>) -> Result<PathBuf> {
+    if let Some(dir) = name.map(Path::new).filter(|dir| dir.is_absolute()) {
+        if !dir.is_dir() {
+            bail!(DimmerError::DeviceNotFound(format!(
+                "device directory {}",
+                dir.display()
+            )));
+        }
+        return Ok(dir.to_path_buf());
+    }
+
+    let mut dirs = Sysfs::discover(class)?.into_iter();
+    match name {
+        Some(name) => dirs
+            .find(|dir| dir.file_name().is_some_and(|dir_name| dir_name == name))
+            .ok_or_else(|| {
+                DimmerError::DeviceNotFound(format!("device {name} at {}", class.glob()))
+            })
+            .map_err(anyhow::Error::from),
+        None => dirs
+            .next()
+            .ok_or_else(|| DimmerError::DeviceNotFound(format!("a device at {}", class.glob())))
+            .map_err(anyhow::Error::from),
     }
-    Ok(())
 }
 
-fn find_file(filename: &str) -> Result<PathBuf> {
-    let glob_path = format!("{SYS_BACKLIGHT_PREFIX}/*/{filename}");
-    let path = glob(&glob_path)
-        .context("Failed to glob {glob_path}")?
-        .next()
-        .context("Failed to find match at {glob_path}")?
-        .context("Glob error trying to match {glob_path}")?;
-    Ok(path)
+/// Whether the device called `name` matches any of `globs`, if there are any, and none of
+/// `excluded`.
+fn is_selected(globs: &[Pattern], excluded: &[Pattern], name: &str) -> bool {
+    let included = globs.is_empty() || globs.iter().any(|glob| glob.matches(name));
+    included && !excluded.iter().any(|glob| glob.matches(name))
 }
 
-fn set_brightness<F: Write>(mut f: F, brightness: Brightness) -> Result<()> {
-    write!(f, "{}", brightness.0)?;
-    Ok(())
+/// Open the device called `name` of `class` with the `backend`, for the VCP code `vcp` of
+/// DDC/CI monitors if given.
+#[cfg_attr(
+    not(all(feature = "windows", target_os = "windows")),
+    allow(unused_variables)
+)]
+fn open(backend: Kind, class: Class, vcp: Option<Vcp>, name: &str) -> Result<Device> {
+    match backend {
+        Kind::Sysfs => {
+            let dir = find_dir(class, Some(name))?;
+            Ok(Device::from_dir(dir))
+        }
+        #[cfg(feature = "wayland")]
+        Kind::Wayland => {
+            let wayland = Wayland::connect(Some(name))?;
+            let name = wayland.output_name().unwrap_or("wayland").to_string();
+            Ok(Device::new(name, wayland))
+        }
+        #[cfg(feature = "x11")]
+        Kind::X11 => {
+            let x11 = X11::connect(Some(name))?;
+            let name = x11.output_name().to_string();
+            Ok(Device::new(name, x11))
+        }
+        #[cfg(feature = "drm")]
+        Kind::Drm => {
+            let drm = Drm::open(Some(name))?;
+            let name = drm.output_name().to_string();
+            Ok(Device::new(name, drm))
+        }
+        #[cfg(feature = "logind")]
+        Kind::Logind => {
+            let dir = find_dir(class, Some(name))?;
+            let logind = Logind::from_dir(&dir)?;
+            let name = dir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            Ok(Device::new(name, logind))
+        }
+        #[cfg(all(feature = "macos", target_os = "macos"))]
+        Kind::MacOs => {
+            let macos = MacOs::open(Some(name))?;
+            let name = macos.display_name().to_string();
+            Ok(Device::new(name, macos))
+        }
+        #[cfg(all(feature = "windows", target_os = "windows"))]
+        Kind::Windows => {
+            let mut windows = Windows::open(Some(name))?;
+            if let Some(Vcp(code)) = vcp {
+                windows = windows.vcp(code)?;
+            }
+            let name = windows.display_name().to_string();
+            Ok(Device::new(name, windows))
+        }
+        #[cfg(target_os = "freebsd")]
+        Kind::FreeBsd => {
+            let freebsd = FreeBsd::open(Some(name))?;
+            let name = freebsd.backlight_name().to_string();
+            Ok(Device::new(name, freebsd))
+        }
+        #[cfg(target_os = "openbsd")]
+        Kind::OpenBsd => {
+            let openbsd = OpenBsd::open(Some(name))?;
+            let name = openbsd.display_name().to_string();
+            Ok(Device::new(name, openbsd))
+        }
+    }
 }
 
-fn save<P: AsRef<Path>>(state_file: P, brightness: Brightness) -> Result<()> {
-    let mut output = File::create(&state_file)?;
-    write!(output, "{}", brightness.0)?;
+/// `device`, mapped along its curve in `mappings` if it has one.
+fn mapped(device: Device, mappings: &BTreeMap<String, Curve>) -> Device {
+    match mappings.get(device.name()) {
+        Some(curve) => device.mapped(curve.clone()),
+        None => device,
+    }
+}
+
+/// How far off its target a device with brightness `maximum` may end up: a percent of it, rounded
+/// up, and at least a step, as devices with few steps can't always land on the one we wrote.
+fn verify_tolerance(maximum: Brightness) -> u64 {
+    (maximum.0 * VERIFY_TOLERANCE).div_ceil(100).max(1)
+}
+
+/// Transition all `devices` at the same time, each to the brightness `target` picks for it given
+/// its name, current and maximum brightness.
+fn transition_devices<F>(
+    devices: Vec<Device>,
+    opt: &Opt,
+    transition: &TransitionOpt,
+    config: &Config,
+    target: F,
+) -> Result<()>
+where
+    F: Fn(&str, Brightness, Brightness) -> Result<Brightness> + Sync,
+{
+    animate_devices(
+        devices,
+        opt,
+        transition,
+        config,
+        Repeat::Times(1),
+        |name, current, maximum| {
+            Ok(vec![Segment {
+                target: target(name, current, maximum)?,
+                duration: None,
+            }])
+        },
+    )?;
     Ok(())
 }
+
+/// Transition `devices` to `to` like `transition_devices`, but only those still on the far side of
+/// it when `dimming` (or brightening), leaving the others as they are.
+fn transition_towards(
+    devices: Vec<Device>,
+    opt: &Opt,
+    transition: &TransitionOpt,
+    config: &Config,
+    to: &str,
+    dimming: bool,
+) -> Result<()> {
+    let target = |_name: &str, current: Brightness, maximum: Brightness| -> Result<Brightness> {
+        let target = Brightness::parse_with_percentage(to, current, maximum)?;
+        let past = if dimming {
+            current <= target
+        } else {
+            current >= target
+        };
+        Ok(if past { current } else { target })
+    };
+    // Being past the target already is what these commands are for, so there's nothing to do.
+    let changes = devices
+        .iter()
+        .map(|device| {
+            let current = device.current()?;
+            Ok(target(device.name(), current, device.max()?)? != current)
+        })
+        .collect::<Result<Vec<bool>>>()?;
+    if !changes.contains(&true) && !transition.dry_run {
+        return Ok(());
+    }
+    transition_devices(devices, opt, transition, config, target)
+}
+
+/// One leg of an animation: a transition to `target`, taking `duration` if given instead of the
+/// duration from the transition options.
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    target: Brightness,
+    duration: Option<std::time::Duration>,
+}
+
+/// How many times to run through the segments of an animation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Repeat {
+    Times(u64),
+    /// Until interrupted.
+    Forever,
+}
+
+impl std::str::FromStr for Repeat {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "forever" => Ok(Repeat::Forever),
+            _ => match input.parse() {
+                Ok(0) | Err(_) => bail!(
+                    "Failed to parse repeat {input}, expected a number above 0 or \"forever\""
+                ),
+                Ok(times) => Ok(Repeat::Times(times)),
+            },
+        }
+    }
+}
+
+/// Animate all `devices` at the same time, each through the segments `plan` picks for it given its
+/// name, current and maximum brightness, one after another, and `repeat` times over. The devices
+/// linked to them in the `config` follow along, in proportion. Returns whether any device was
+/// taken anywhere, for `dimmer set` to exit with `EXIT_UNCHANGED` if none was. Fails with
+/// `Stopped` when a signal stops us, or when a device didn't reach its target.
+///
+/// Volatile backends undo the brightness when we exit, so when one of them is left dimmed, we
+/// keep running until we're stopped, like by a signal or another instance taking over.
+fn animate_devices<F>(
+    devices: Vec<Device>,
+    opt: &Opt,
+    transition: &TransitionOpt,
+    config: &Config,
+    repeat: Repeat,
+    plan: F,
+) -> Result<bool>
+where
+    F: Fn(&str, Brightness, Brightness) -> Result<Vec<Segment>> + Sync,
+{
+    if transition.dry_run {
+        for plan in plan_devices(devices, opt, transition, config, repeat, plan)? {
+            plan.print();
+        }
+        return Ok(true);
+    }
+
+    let devices = with_linked(devices, opt, config)?;
+    let names: Vec<String> = devices
+        .iter()
+        .map(|(device, _)| device.name().to_string())
+        .collect();
+    let named = devices.len() > 1;
+    let plan = &plan;
+    let signals = Signals::catch();
+    let changed = AtomicBool::new(false);
+    let results: Vec<Result<(Device, bool)>> = std::thread::scope(|scope| {
+        let signals = &signals;
+        let changed = &changed;
+        let handles: Vec<_> = devices
+            .into_iter()
+            .map(|(mut device, link)| {
+                scope.spawn(move || {
+                    let current = device.current()?;
+                    let maximum = device.max()?;
+                    let transition = &transition.for_device(device.name(), config);
+                    let segments =
+                        plan_segments(&device, link, current, maximum, transition, config, plan)?;
+                    let target = segments.last().map_or(current, |segment| segment.target);
+                    tracing::info!(
+                        device = device.name(),
+                        current = current.0,
+                        target = target.0,
+                        segments = segments.len(),
+                        "Animating"
+                    );
+                    if segments.iter().any(|segment| segment.target != current) {
+                        changed.store(true, Ordering::Relaxed);
+                    }
+
+                    let rounds = match repeat {
+                        Repeat::Times(times) => times,
+                        Repeat::Forever => u64::MAX,
+                    };
+                    let mut progress = Progress::new(&device, maximum, transition, named)?;
+                    let mut from = current;
+                    'animation: for segment in (0..rounds).flat_map(|_| &segments) {
+                        let fade = transition.segment(from, segment, maximum);
+                        if segment.target.0 > 0 && !device.is_powered()? {
+                            device.set_powered(true)?;
+                        }
+                        let outcome = fade
+                            .run_reporting(&mut device, &CONTROL, |frame| {
+                                progress.show(frame.brightness)
+                            })
+                            .with_context(|| format!("Failed to transition {}", device.name()))?;
+                        let on_interrupt = if signals.restore_requested() {
+                            OnInterrupt::Restore
+                        } else if signals.cancelled() {
+                            OnInterrupt::Stop
+                        } else {
+                            transition.on_interrupt.unwrap_or(OnInterrupt::Stop)
+                        };
+                        match (outcome, on_interrupt) {
+                            (Outcome::Finished, _) => {}
+                            (Outcome::Reversed, _) => return Ok((device, from != maximum)),
+                            (Outcome::Interrupted, OnInterrupt::Finish) => {
+                                device.set(target)?;
+                                progress.show(target);
+                                break 'animation;
+                            }
+                            (Outcome::Interrupted, OnInterrupt::Restore) => {
+                                device.set(current)?;
+                                progress.show(current);
+                                return Ok((device, current != maximum));
+                            }
+                            (Outcome::Interrupted, OnInterrupt::Stop) => return Ok((device, true)),
+                        }
+                        from = segment.target;
+                    }
+                    transition.verify(&mut device, target)?;
+                    if transition.power_off && target.0 == 0 {
+                        device.set_powered(false)?;
+                    }
+                    Ok((device, target != maximum))
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("Transition thread panicked"))
+            .collect()
+    });
+    // A device that went away mid-transition, like an unplugged monitor, shouldn't take the others
+    // down with it.
+    let mut devices = Vec::new();
+    let mut not_reached = false;
+    for (name, result) in names.iter().zip(results) {
+        match result {
+            Ok(device) => devices.push(device),
+            Err(err) if dimmer::is_removed(&err) => {
+                eprintln!("{name} went away, carrying on without it: {err:#}");
+            }
+            // The other devices may have made it, so they're left as they are.
+            Err(err) if is_not_reached(&err) => {
+                eprintln!("Error: {err:#}");
+                not_reached = true;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    // Leave the brightness to the instance that cancelled us.
+    if signals.cancelled() {
+        return Ok(true);
+    }
+    if let Some(signal) = signals.interrupted_by() {
+        return Err(Stopped::Interrupted(signal).into());
+    }
+    drop(signals);
+    if not_reached {
+        return Err(Stopped::NotReached.into());
+    }
+
+    // Keep volatile backends alive, or the brightness would be reset as soon as we exit. There's
+    // no telling how long the brightness is wanted for, so we wait to be stopped, which the
+    // default signal handlers are back for.
+    if devices
+        .iter()
+        .any(|(device, dimmed)| device.is_volatile() && *dimmed)
+    {
+        loop {
+            std::thread::park();
+        }
+    }
+    Ok(changed.load(Ordering::Relaxed))
+}
+
+/// Prints the brightness of a device as it changes during a transition, with `--progress`.
+struct Progress {
+    output: Option<Output>,
+    status: Status,
+    named: bool,
+    /// The last line printed, so the same line isn't printed for every write.
+    last: String,
+}
+
+impl Progress {
+    fn new(
+        device: &Device,
+        maximum: Brightness,
+        transition: &TransitionOpt,
+        named: bool,
+    ) -> Result<Progress> {
+        let current = device.current()?;
+        Ok(Progress {
+            output: transition
+                .progress
+                .map(|output| output.unwrap_or(Output::Plain)),
+            status: Status {
+                name: device.name().to_string(),
+                class: device.class().to_string(),
+                current,
+                max: maximum,
+                percent: current.percentage_of(maximum),
+            },
+            named,
+            last: String::new(),
+        })
+    }
+
+    /// Print the brightness of the device now it's been set to `value`, if the line changed.
+    fn show(&mut self, value: Brightness) {
+        let Some(output) = self.output else {
+            return;
+        };
+        self.status.current = value;
+        self.status.percent = value.percentage_of(self.status.max);
+        let line = match output {
+            Output::Plain if self.named => {
+                format!("{}: {:.0}", self.status.name, self.status.percent)
+            }
+            Output::Plain => format!("{:.0}", self.status.percent),
+            output => match render(&self.status, output, None, self.named) {
+                Ok(line) => line,
+                Err(err) => {
+                    tracing::warn!("Failed to render the progress: {err:#}");
+                    return;
+                }
+            },
+        };
+        if line != self.last {
+            // A closed pipe, like a progress bar that went away, shouldn't stop the transition.
+            let _ = writeln!(std::io::stdout(), "{line}");
+            self.last = line;
+        }
+    }
+}
+
+/// Whether the `current` brightness is above and below the levels given with `--only-if-above`
+/// and `--only-if-below`, if any, so `dimmer set` should transition the device.
+fn passes_guards(
+    (above, below): (Option<&str>, Option<&str>),
+    current: Brightness,
+    maximum: Brightness,
+) -> Result<bool> {
+    let level = |level| Brightness::parse_with_percentage(level, current, maximum);
+    if let Some(above) = above {
+        if current <= level(above)? {
+            return Ok(false);
+        }
+    }
+    if let Some(below) = below {
+        if current >= level(below)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// The segments `dimmer set` animates the device called `name` through: the `keyframes`, or else
+/// a transition to its `target`, followed by the transitions `chained` with `--then`.
+fn set_segments(
+    keyframes: Option<&Keyframes>,
+    target: Option<&Targets>,
+    chained: &[Then],
+    name: &str,
+    current: Brightness,
+    maximum: Brightness,
+) -> Result<Vec<Segment>> {
+    let mut segments = match keyframes {
+        Some(keyframes) => keyframes.segments(current, maximum)?,
+        None => {
+            let target = match target {
+                Some(target) => target.get(name)?,
+                None => "0",
+            };
+            vec![Segment {
+                target: Brightness::parse_with_percentage(target, current, maximum)?,
+                duration: None,
+            }]
+        }
+    };
+    for then in chained {
+        then.append_to(&mut segments, current, maximum)?;
+    }
+    Ok(segments)
+}
+
+/// The link of a device following another, and the status of that primary device, if it follows
+/// one.
+type Linked<'a> = Option<(&'a Link, Status)>;
+
+/// `devices` along with the devices linked to them in the `config`, opened with the backend
+/// selected in `opt`, each with the link and the status of its primary.
+fn with_linked<'a>(
+    devices: Vec<Device>,
+    opt: &Opt,
+    config: &'a Config,
+) -> Result<Vec<(Device, Linked<'a>)>> {
+    let mut devices: Vec<_> = devices.into_iter().map(|device| (device, None)).collect();
+    for (name, link) in &config.links {
+        let Some((primary, _)) = devices
+            .iter()
+            .find(|(device, _)| device.name() == link.primary)
+        else {
+            continue;
+        };
+        let primary = primary.status()?;
+        devices.retain(|(device, _)| device.name() != name);
+        devices.push((opt.any_device(name)?, Some((link, primary))));
+    }
+    Ok(devices)
+}
+
+/// The segments `plan` picks for `device`, or for a linked device those of its primary, in
+/// proportion, kept within the limits of the `transition` options and the `config`.
+fn plan_segments<F>(
+    device: &Device,
+    link: Linked,
+    current: Brightness,
+    maximum: Brightness,
+    transition: &TransitionOpt,
+    config: &Config,
+    plan: &F,
+) -> Result<Vec<Segment>>
+where
+    F: Fn(&str, Brightness, Brightness) -> Result<Vec<Segment>>,
+{
+    let segments = match link {
+        Some((link, primary)) => plan(&primary.name, primary.current, primary.max)?
+            .into_iter()
+            .map(|segment| Segment {
+                target: link.follow(segment.target, primary.max, maximum),
+                ..segment
+            })
+            .collect(),
+        None => plan(device.name(), current, maximum)?,
+    };
+    segments
+        .into_iter()
+        .map(|segment| {
+            let target = transition.clamp(config, segment.target, current, maximum)?;
+            Ok(Segment { target, ..segment })
+        })
+        .collect()
+}
+
+/// What animating `devices` like `animate_devices` would write when, without writing anything.
+fn plan_devices<F>(
+    devices: Vec<Device>,
+    opt: &Opt,
+    transition: &TransitionOpt,
+    config: &Config,
+    repeat: Repeat,
+    plan: F,
+) -> Result<Vec<Plan>>
+where
+    F: Fn(&str, Brightness, Brightness) -> Result<Vec<Segment>>,
+{
+    with_linked(devices, opt, config)?
+        .into_iter()
+        .map(|(device, link)| {
+            let current = device.current()?;
+            let maximum = device.max()?;
+            let transition = &transition.for_device(device.name(), config);
+            let segments =
+                plan_segments(&device, link, current, maximum, transition, config, &plan)?;
+            Ok(Plan::new(
+                device.name(),
+                current,
+                maximum,
+                &segments,
+                repeat,
+                transition,
+            ))
+        })
+        .collect()
+}
+
+/// Whether `err` means a device never reached the target of its transition.
+fn is_not_reached(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<DimmerError>(),
+        Some(DimmerError::TargetNotReached { .. })
+    )
+}
+
+/// Take over from the running instance, unless we're only printing what the `transition` would do.
+fn take_over(transition: &TransitionOpt) -> Result<Option<Lock>> {
+    if transition.dry_run {
+        return Ok(None);
+    }
+    Lock::take_over(&instance::lock_file()).map(Some)
+}
+
+/// Save the current brightness of `devices` to the state file, in the named `slot` if given, next
+/// to what was saved for other devices and slots.
+fn save(state_file: &Path, devices: &[Device], slot: Option<&str>) -> Result<()> {
+    State::update(state_file, |state| {
+        devices
+            .iter()
+            .try_for_each(|device| state.save(device, slot))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stopping_exits_with_a_status_of_its_own() {
+        assert_eq!(exit_status(&Stopped::Unchanged.into()), EXIT_UNCHANGED);
+        assert_eq!(exit_status(&Stopped::NotReached.into()), EXIT_NOT_REACHED);
+        assert_eq!(exit_status(&Stopped::Interrupted(15).into()), 128 + 15);
+        let err = anyhow::Error::from(Stopped::Unchanged).context("Failed to set");
+        assert_eq!(exit_status(&err), EXIT_UNCHANGED);
+    }
+
+    #[test]
+    fn verifies_within_a_percent_or_a_step() {
+        assert_eq!(verify_tolerance(Brightness(1000)), 10);
+        assert_eq!(verify_tolerance(Brightness(1050)), 11);
+        assert_eq!(verify_tolerance(Brightness(100)), 1);
+        // Rounding down would leave no tolerance at all.
+        assert_eq!(verify_tolerance(Brightness(7)), 1);
+        assert_eq!(verify_tolerance(Brightness(0)), 1);
+    }
+}
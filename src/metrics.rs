@@ -0,0 +1,138 @@
+use dimmer::{Frame, Status};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// The upper bounds of the buckets frames are counted in by how late they were written, in
+/// seconds.
+const LATENESS_BUCKETS: [f64; 9] = [0.001, 0.002, 0.005, 0.01, 0.02, 0.05, 0.1, 0.25, 1.0];
+
+/// What the daemon counts while it runs, for Prometheus to scrape at `GET /metrics`.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    transitions_started: AtomicU64,
+    transitions_finished: AtomicU64,
+    write_errors: AtomicU64,
+    lateness: Mutex<Histogram>,
+}
+
+/// How many observations fell in each bucket, and in all of them together.
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [u64; LATENESS_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Metrics {
+    pub fn transition_started(&self) {
+        self.transitions_started.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn transition_finished(&self) {
+        self.transitions_finished.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn write_failed(&self) {
+        self.write_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn frame_written(&self, frame: Frame) {
+        let late = frame.late.as_secs_f64();
+        let mut lateness = self.lateness.lock().unwrap();
+        for (bucket, bound) in lateness.buckets.iter_mut().zip(LATENESS_BUCKETS) {
+            if late <= bound {
+                *bucket += 1;
+            }
+        }
+        lateness.count += 1;
+        lateness.sum += late;
+    }
+
+    /// The metrics in Prometheus' text format, with the brightness of the devices in `statuses`.
+    pub fn render(&self, statuses: &[Status]) -> String {
+        let mut out = String::new();
+        let counter = |out: &mut String, name: &str, help: &str, value: &AtomicU64| {
+            let value = value.load(Ordering::Relaxed);
+            let _ = writeln!(
+                out,
+                "# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}"
+            );
+        };
+
+        let _ = writeln!(
+            out,
+            "# HELP dimmer_brightness The current brightness of the device.\n\
+             # TYPE dimmer_brightness gauge"
+        );
+        for status in statuses {
+            let _ = writeln!(
+                out,
+                "dimmer_brightness{{device=\"{}\"}} {}",
+                escape(&status.name),
+                status.current
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP dimmer_max_brightness The maximum brightness of the device.\n\
+             # TYPE dimmer_max_brightness gauge"
+        );
+        for status in statuses {
+            let _ = writeln!(
+                out,
+                "dimmer_max_brightness{{device=\"{}\"}} {}",
+                escape(&status.name),
+                status.max
+            );
+        }
+        counter(
+            &mut out,
+            "dimmer_transitions_started_total",
+            "Transitions of a device started.",
+            &self.transitions_started,
+        );
+        counter(
+            &mut out,
+            "dimmer_transitions_finished_total",
+            "Transitions of a device that reached their target.",
+            &self.transitions_finished,
+        );
+        counter(
+            &mut out,
+            "dimmer_write_errors_total",
+            "Writes to a device that failed, after trying again.",
+            &self.write_errors,
+        );
+
+        let lateness = self.lateness.lock().unwrap();
+        let _ = writeln!(
+            out,
+            "# HELP dimmer_frame_lateness_seconds How long after it was due a frame was written.\n\
+             # TYPE dimmer_frame_lateness_seconds histogram"
+        );
+        for (count, bound) in lateness.buckets.iter().zip(LATENESS_BUCKETS) {
+            let _ = writeln!(
+                out,
+                "dimmer_frame_lateness_seconds_bucket{{le=\"{bound}\"}} {count}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "dimmer_frame_lateness_seconds_bucket{{le=\"+Inf\"}} {count}\n\
+             dimmer_frame_lateness_seconds_sum {sum}\n\
+             dimmer_frame_lateness_seconds_count {count}",
+            count = lateness.count,
+            sum = lateness.sum,
+        );
+        out
+    }
+}
+
+/// `value` escaped for a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
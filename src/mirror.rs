@@ -0,0 +1,52 @@
+use anyhow::{bail, Result};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::daemon::Daemon;
+use crate::watch;
+
+/// How often we check for changes of the mirrored device the kernel doesn't report.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long we wait before watching the source again after losing it.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Have the devices in the daemon's mirror follow the brightness percentage of its source device,
+/// from a thread of its own. When we lose the source, e.g. because it was unplugged, we watch it
+/// again once it's back, and when the configuration names another source, we switch to it.
+pub fn start(daemon: Arc<Mutex<Daemon>>) {
+    std::thread::spawn(move || {
+        // Only tell about losing the source once, rather than every time we look for it.
+        let mut lost = None;
+        loop {
+            let source = daemon.lock().unwrap().mirror_source();
+            if let Some(source) = source {
+                if let Err(err) = follow(&daemon, &source, &mut lost) {
+                    let err = format!("{err:#}");
+                    if lost.as_ref() != Some(&err) {
+                        tracing::warn!("Stopped mirroring {source}, trying again: {err}");
+                        lost = Some(err);
+                    }
+                }
+            }
+            std::thread::sleep(RETRY_DELAY);
+        }
+    });
+}
+
+/// Have the daemon's mirrored devices follow every change of the device called `source`, until
+/// watching it fails or the configuration names another source.
+fn follow(daemon: &Mutex<Daemon>, source: &str, lost: &mut Option<String>) -> Result<()> {
+    let device = daemon.lock().unwrap().open(source)?;
+    watch::watch(&[device], POLL_INTERVAL, |status| {
+        *lost = None;
+        let mut daemon = daemon.lock().unwrap();
+        if daemon.mirror_source().as_deref() != Some(source) {
+            bail!("The configuration names another source");
+        }
+        if let Err(err) = daemon.mirror(status.percent) {
+            tracing::error!("Failed to mirror {source}: {err:#}");
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,283 @@
+use anyhow::{bail, Context, Result};
+use dimmer::Status;
+use rumqttc::{Client, Connection, Event, LastWill, MqttOptions, Packet, QoS};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::config::Mqtt;
+use crate::daemon::{Daemon, Request};
+
+/// The port brokers listen on without TLS.
+const PORT: u16 = 1883;
+
+/// Where Home Assistant looks for devices to discover by default.
+const DISCOVERY_PREFIX: &str = "homeassistant";
+
+/// How often we let the broker know we're still there.
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// How long we wait before connecting to the broker again after losing the connection.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// How many messages may be waiting to be sent to the broker.
+const CAPACITY: usize = 16;
+
+/// A command in the JSON schema of Home Assistant's MQTT lights.
+#[derive(Debug, Deserialize)]
+struct Command {
+    /// "ON" or "OFF".
+    state: Option<String>,
+    /// The brightness as a percentage, as we announce a brightness scale of 100.
+    brightness: Option<f64>,
+    /// How long the transition takes, in seconds.
+    transition: Option<f64>,
+}
+
+/// The state of a device in the JSON schema of Home Assistant's MQTT lights.
+#[derive(Debug, Serialize)]
+struct State {
+    state: &'static str,
+    brightness: u64,
+    color_mode: &'static str,
+}
+
+/// The topics we publish and subscribe to.
+#[derive(Debug, Clone)]
+struct Topics {
+    state: String,
+    command: String,
+    availability: String,
+    /// Where we announce ourselves to Home Assistant, unless discovery is turned off.
+    discovery: Option<String>,
+}
+
+/// Publish the brightness of the daemon's devices to the MQTT broker in the `config`, and take
+/// commands from it, from a thread of its own. We announce the devices to Home Assistant as a
+/// single dimmable light, unless turned off in the `config`.
+pub fn start(daemon: Arc<Mutex<Daemon>>, config: &Mqtt) -> Result<()> {
+    let host = hostname()?;
+    let topic = config
+        .topic
+        .clone()
+        .unwrap_or_else(|| format!("dimmer/{host}"));
+    let discovery_prefix = config
+        .discovery_prefix
+        .as_deref()
+        .unwrap_or(DISCOVERY_PREFIX);
+    let topics = Topics {
+        state: format!("{topic}/state"),
+        command: format!("{topic}/set"),
+        availability: format!("{topic}/availability"),
+        discovery: config
+            .discovery
+            .unwrap_or(true)
+            .then(|| format!("{discovery_prefix}/light/{}/config", object_id(&host))),
+    };
+
+    let mut options = MqttOptions::new(
+        format!("dimmer-{host}"),
+        &config.host,
+        config.port.unwrap_or(PORT),
+    );
+    options.set_keep_alive(KEEP_ALIVE);
+    options.set_last_will(LastWill::new(
+        &topics.availability,
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+    if let Some(username) = &config.username {
+        options.set_credentials(username, config.password.clone().unwrap_or_default());
+    }
+    let (client, connection) = Client::new(options, CAPACITY);
+
+    {
+        let client = client.clone();
+        let topic = topics.state.clone();
+        daemon.lock().unwrap().listen(Arc::new(move |status| {
+            // Never hold up a transition for the broker, it gets the next state instead.
+            if let Err(err) = publish_state(&client, &topic, status, false) {
+                tracing::error!("Failed to publish the brightness over MQTT: {err:#}");
+            }
+        }));
+    }
+    std::thread::spawn(move || serve(connection, client, daemon, topics, host));
+    Ok(())
+}
+
+/// Handle the events on the `connection` to the broker, announcing ourselves from another thread
+/// whenever we (re)connect, and handling the commands we receive.
+fn serve(
+    mut connection: Connection,
+    client: Client,
+    daemon: Arc<Mutex<Daemon>>,
+    topics: Topics,
+    host: String,
+) {
+    for event in connection.iter() {
+        match event {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                tracing::info!("Connected to the MQTT broker");
+                // Announcing waits for room in the queue this loop empties, so it can't run on
+                // this thread.
+                let client = client.clone();
+                let daemon = daemon.clone();
+                let topics = topics.clone();
+                let host = host.clone();
+                std::thread::spawn(move || {
+                    if let Err(err) = announce(&client, &daemon, &topics, &host) {
+                        tracing::error!("Failed to announce ourselves over MQTT: {err:#}");
+                    }
+                });
+            }
+            Ok(Event::Incoming(Packet::Publish(publish))) if publish.topic == topics.command => {
+                tracing::info!(payload = ?publish.payload, "Received MQTT command");
+                if let Err(err) = handle(&daemon, &publish.payload) {
+                    tracing::error!("Failed to handle MQTT command: {err:#}");
+                }
+            }
+            Ok(_) => {}
+            Err(err) => {
+                tracing::warn!("Lost the connection to the MQTT broker: {err}");
+                std::thread::sleep(RECONNECT_DELAY);
+            }
+        }
+    }
+}
+
+/// Subscribe to commands, and tell Home Assistant about us, that we're online and how bright the
+/// screen is.
+fn announce(client: &Client, daemon: &Mutex<Daemon>, topics: &Topics, host: &str) -> Result<()> {
+    client.subscribe(&topics.command, QoS::AtLeastOnce)?;
+    if let Some(discovery) = &topics.discovery {
+        let id = object_id(host);
+        let config = serde_json::json!({
+            "name": null,
+            "unique_id": id,
+            "schema": "json",
+            "state_topic": topics.state,
+            "command_topic": topics.command,
+            "availability_topic": topics.availability,
+            "supported_color_modes": ["brightness"],
+            "brightness_scale": 100,
+            "device": {
+                "identifiers": [id],
+                "name": format!("{host} screen"),
+                "manufacturer": "dimmer",
+                "sw_version": env!("CARGO_PKG_VERSION"),
+            },
+        });
+        client.publish(
+            discovery,
+            QoS::AtLeastOnce,
+            true,
+            serde_json::to_vec(&config)?,
+        )?;
+    }
+    client.publish(&topics.availability, QoS::AtLeastOnce, true, "online")?;
+    let response = daemon.lock().unwrap().handle(Request::Status)?;
+    if let Some(status) = response.devices.unwrap_or_default().first() {
+        publish_state(client, &topics.state, status, true)?;
+    }
+    Ok(())
+}
+
+/// Transition as the command in `payload` says.
+fn handle(daemon: &Mutex<Daemon>, payload: &[u8]) -> Result<()> {
+    let command: Command = serde_json::from_slice(payload).with_context(|| {
+        format!(
+            "Failed to parse command {:?}",
+            String::from_utf8_lossy(payload)
+        )
+    })?;
+    let duration = command
+        .transition
+        .map(|seconds| format!("{}ms", (seconds.max(0.0) * 1000.0).round() as u64));
+    let mut daemon = daemon.lock().unwrap();
+    let request = match (command.state.as_deref(), command.brightness) {
+        (Some("OFF"), _) => Request::Dim {
+            target: "off".to_string(),
+            duration,
+        },
+        (_, Some(brightness)) => Request::Set {
+            target: format!("{}%", brightness.clamp(0.0, 100.0)),
+            duration,
+        },
+        // Switching on brings back the brightness from before switching off, or full brightness
+        // if the screen was off some other way.
+        (Some("ON"), None) => {
+            let status = daemon.handle(Request::Status)?;
+            let dark = status
+                .devices
+                .unwrap_or_default()
+                .iter()
+                .all(|device| device.current.0 == 0);
+            match (status.dimmed, dark) {
+                (Some(true), _) => Request::Restore { duration },
+                (_, true) => Request::Set {
+                    target: "max".to_string(),
+                    duration,
+                },
+                (_, false) => return Ok(()),
+            }
+        }
+        _ => bail!(
+            "Unknown command {:?}, expected an \"ON\" or \"OFF\" state or a brightness",
+            String::from_utf8_lossy(payload)
+        ),
+    };
+    daemon.handle(request)?;
+    Ok(())
+}
+
+/// Publish the brightness of the device in `status` to `topic`, kept by the broker for newcomers.
+/// Unless we may `wait` for the broker, this fails rather than blocks when it's behind.
+fn publish_state(client: &Client, topic: &str, status: &Status, wait: bool) -> Result<()> {
+    let state = State {
+        state: if status.current.0 > 0 { "ON" } else { "OFF" },
+        brightness: status.percent.round() as u64,
+        color_mode: "brightness",
+    };
+    let payload = serde_json::to_vec(&state)?;
+    if wait {
+        client.publish(topic, QoS::AtLeastOnce, true, payload)?;
+    } else {
+        client.try_publish(topic, QoS::AtLeastOnce, true, payload)?;
+    }
+    Ok(())
+}
+
+/// The name of this machine, to tell it apart from other screens on the same broker.
+#[cfg(unix)]
+fn hostname() -> Result<String> {
+    let mut name = [0u8; 256];
+    // SAFETY: the buffer is as long as we say it is.
+    if unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to get the host name");
+    }
+    let length = name
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(name.len());
+    Ok(String::from_utf8_lossy(&name[..length]).into_owned())
+}
+
+/// The name of this machine, to tell it apart from other screens on the same broker.
+#[cfg(windows)]
+fn hostname() -> Result<String> {
+    std::env::var("COMPUTERNAME").context("Failed to get the host name")
+}
+
+/// The ID Home Assistant knows the screen of `host` by, which may only have letters, digits,
+/// underscores and dashes in it.
+fn object_id(host: &str) -> String {
+    let host: String = host
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => c,
+            _ => '_',
+        })
+        .collect();
+    format!("dimmer_{host}")
+}
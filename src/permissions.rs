@@ -0,0 +1,262 @@
+use anyhow::{bail, Context, Result};
+use dimmer::backend::Kind;
+use dimmer::Device;
+#[cfg(unix)]
+use dimmer::DimmerError;
+#[cfg(unix)]
+use std::ffi::{CStr, CString};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+const UDEV_RULES_DIR: &str = "/etc/udev/rules.d";
+
+/// The group we suggest giving write access to.
+pub const DEFAULT_GROUP: &str = "video";
+
+/// Whether the group owning a file may write to it, in its mode.
+#[cfg(unix)]
+const GROUP_WRITE: u32 = 0o020;
+
+/// Who the udev rule grants write access to the device.
+#[derive(Debug, Clone)]
+pub enum Access {
+    /// The members of a group.
+    Group(String),
+    /// The user logged in at the seat, through systemd's `uaccess` ACLs.
+    Uaccess,
+}
+
+/// The udev rule giving `access` to `device` of the `backend`.
+///
+/// Sysfs attributes don't get ACLs or groups from udev like device nodes do, so for sysfs we
+/// change the group and permissions of the brightness file when the device is added.
+pub fn udev_rule(backend: Kind, device: &Device, access: &Access) -> Result<String> {
+    let name = device.name();
+    if backend == Kind::Sysfs {
+        let Access::Group(group) = access else {
+            bail!(
+                "uaccess only applies to device nodes, use a group or the logind backend instead"
+            );
+        };
+        return Ok(sysfs_rule(device.class(), name, group));
+    }
+    #[cfg(feature = "drm")]
+    if backend == Kind::Drm {
+        return Ok(match access {
+            Access::Group(group) => format!(
+                "SUBSYSTEM==\"drm\", KERNEL==\"card[0-9]*\", GROUP=\"{group}\", MODE=\"0660\"\n"
+            ),
+            Access::Uaccess => {
+                "SUBSYSTEM==\"drm\", KERNEL==\"card[0-9]*\", TAG+=\"uaccess\"\n".to_string()
+            }
+        });
+    }
+    bail!("{name} needs no special permissions with this backend")
+}
+
+/// The udev rule giving `group` write access to the brightness of the sysfs device called `name`
+/// in `subsystem`.
+fn sysfs_rule(subsystem: &str, name: &str, group: &str) -> String {
+    let file = format!("/sys/class/{subsystem}/%k/brightness");
+    format!(
+        "ACTION==\"add\", SUBSYSTEM==\"{subsystem}\", KERNEL==\"{name}\", \
+         RUN+=\"/bin/chgrp {group} {file}\", RUN+=\"/bin/chmod g+w {file}\"\n"
+    )
+}
+
+/// How to get write access to the file `err` says we had no permission to write, if that's what
+/// it's about: which group owns the file, whether we're in it, and otherwise the udev rule giving a
+/// group we can join access, or the logind backend that needs none.
+#[cfg(unix)]
+pub fn diagnose(err: &anyhow::Error) -> Option<String> {
+    let path = err.chain().find_map(|cause| match cause.downcast_ref() {
+        Some(DimmerError::PermissionDenied(path)) => Some(path),
+        _ => None,
+    })?;
+    let metadata = std::fs::metadata(path).ok()?;
+    let owner = group_name(metadata.gid()).unwrap_or_else(|| metadata.gid().to_string());
+    let file = path.display();
+
+    if metadata.mode() & GROUP_WRITE != 0 && owner != "root" {
+        if is_member(metadata.gid()) {
+            return Some(format!(
+                "{file} is writable by the {owner} group, which you're in, but not in this \
+                 session yet. Log out and back in, or try `newgrp {owner}`."
+            ));
+        }
+        return Some(format!(
+            "{file} is writable by the {owner} group, which you're not in. Add yourself to it, \
+             and log in again:\n\n    sudo usermod -aG {owner} $USER"
+        ));
+    }
+
+    let mut hint = format!(
+        "{file} belongs to the {owner} group, which can't write to it. Give the \
+         {DEFAULT_GROUP} group write access with this udev rule"
+    );
+    let device = path.parent().and_then(Path::file_name);
+    let subsystem = path
+        .parent()
+        .and_then(Path::parent)
+        .and_then(Path::file_name);
+    match device.zip(subsystem) {
+        Some((device, subsystem)) => {
+            let name = device.to_string_lossy();
+            let rule = sysfs_rule(&subsystem.to_string_lossy(), &name, DEFAULT_GROUP);
+            hint += &format!(
+                " in {}, which `dimmer setup-permissions --install` installs for you:\n\n    {rule}",
+                rule_path(&name).display()
+            );
+        }
+        None => hint += ", as `dimmer setup-permissions` prints.\n",
+    }
+    if !group_id(DEFAULT_GROUP).is_some_and(is_member) {
+        hint += &format!(
+            "\nThen add yourself to the {DEFAULT_GROUP} group, and log in again:\n\n    \
+             sudo usermod -aG {DEFAULT_GROUP} $USER\n"
+        );
+    }
+    hint += "\nOr go through systemd-logind, which needs no write access, with `--backend logind` \
+             (when built with the `logind` feature).";
+    Some(hint)
+}
+
+/// Windows has no groups or udev to give access with.
+#[cfg(windows)]
+pub fn diagnose(_err: &anyhow::Error) -> Option<String> {
+    None
+}
+
+/// The name of the group with id `gid`.
+#[cfg(unix)]
+fn group_name(gid: libc::gid_t) -> Option<String> {
+    // SAFETY: group is plain old data, for which all zeroes is valid.
+    let mut group: libc::group = unsafe { std::mem::zeroed() };
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    let mut result = std::ptr::null_mut();
+    // SAFETY: all pointers point at valid values, and buffer is valid for writes of its length.
+    let failed = unsafe {
+        libc::getgrgid_r(
+            gid,
+            &mut group,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+    if failed != 0 || result.is_null() {
+        return None;
+    }
+    // SAFETY: getgrgid_r found the group, so its name points at a nul-terminated string in buffer.
+    let name = unsafe { CStr::from_ptr(group.gr_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// The id of the group called `name`.
+#[cfg(unix)]
+fn group_id(name: &str) -> Option<libc::gid_t> {
+    let name = CString::new(name).ok()?;
+    // SAFETY: group is plain old data, for which all zeroes is valid.
+    let mut group: libc::group = unsafe { std::mem::zeroed() };
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    let mut result = std::ptr::null_mut();
+    // SAFETY: all pointers point at valid values, and buffer is valid for writes of its length.
+    let failed = unsafe {
+        libc::getgrnam_r(
+            name.as_ptr(),
+            &mut group,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+    (failed == 0 && !result.is_null()).then_some(group.gr_gid)
+}
+
+/// Whether this process runs as a member of the group with id `gid`.
+#[cfg(unix)]
+fn is_member(gid: libc::gid_t) -> bool {
+    // SAFETY: getegid can't fail.
+    if unsafe { libc::getegid() } == gid {
+        return true;
+    }
+    // SAFETY: a size of 0 only asks for the number of groups.
+    let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+    let mut groups = vec![0; count.max(0) as usize];
+    // SAFETY: groups is valid for writes of the number of groups given.
+    let count = unsafe { libc::getgroups(count, groups.as_mut_ptr()) };
+    groups.truncate(count.max(0) as usize);
+    groups.contains(&gid)
+}
+
+/// Where the rule for the device called `name` is installed.
+pub fn rule_path(name: &str) -> PathBuf {
+    Path::new(UDEV_RULES_DIR).join(format!("90-dimmer-{name}.rules"))
+}
+
+/// Install `rule` at `path` and have udev apply it, using sudo unless we're root already.
+pub fn install(rule: &str, path: &Path) -> Result<()> {
+    let mut tee = elevated("tee")
+        .arg(path)
+        .stdout(Stdio::null())
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("Failed to run tee")?;
+    tee.stdin
+        .take()
+        .context("Failed to open tee's input")?
+        .write_all(rule.as_bytes())?;
+    if !tee.wait()?.success() {
+        bail!("Failed to write {}", path.display());
+    }
+
+    for args in [
+        &["control", "--reload"][..],
+        &["trigger", "--action=add", "--subsystem-match=backlight"],
+        &["trigger", "--action=add", "--subsystem-match=leds"],
+        &["trigger", "--action=add", "--subsystem-match=drm"],
+    ] {
+        if !elevated("udevadm").args(args).status()?.success() {
+            bail!("Failed to run udevadm {}", args.join(" "));
+        }
+    }
+    Ok(())
+}
+
+/// Whether we can write the file at `path` already.
+#[cfg(unix)]
+pub fn is_writable(path: &Path) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_encoded_bytes()) else {
+        return false;
+    };
+    // SAFETY: path is a valid, nul-terminated string.
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
+
+/// Whether we can write the file at `path` already.
+#[cfg(windows)]
+pub fn is_writable(path: &Path) -> bool {
+    std::fs::OpenOptions::new().write(true).open(path).is_ok()
+}
+
+/// A command running `program` as root.
+#[cfg(unix)]
+fn elevated(program: &str) -> Command {
+    // SAFETY: geteuid can't fail.
+    if unsafe { libc::geteuid() } == 0 {
+        Command::new(program)
+    } else {
+        let mut command = Command::new("sudo");
+        command.arg(program);
+        command
+    }
+}
+
+/// A command running `program`, which has to be elevated already on Windows.
+#[cfg(windows)]
+fn elevated(program: &str) -> Command {
+    Command::new(program)
+}
@@ -0,0 +1,105 @@
+use dimmer::Brightness;
+use serde::Serialize;
+
+use crate::{Repeat, Segment, TransitionOpt};
+
+/// What animating a device would write when, as `dimmer plan` and `--dry-run` print it.
+#[derive(Debug, Serialize)]
+pub struct Plan {
+    pub device: String,
+    pub current: Brightness,
+    pub target: Brightness,
+    pub max: Brightness,
+    /// How many times the legs are run through, or none for until interrupted.
+    pub repeat: Option<u64>,
+    pub legs: Vec<Leg>,
+}
+
+/// The transition through a single segment of an animation.
+#[derive(Debug, Serialize)]
+pub struct Leg {
+    pub from: Brightness,
+    pub to: Brightness,
+    pub frames: u64,
+    pub duration_ms: f64,
+    /// The frames that change the brightness, as the rest aren't written.
+    pub writes: Vec<Write>,
+}
+
+/// A brightness written some time into a leg.
+#[derive(Debug, Serialize)]
+pub struct Write {
+    pub at_ms: f64,
+    pub brightness: Brightness,
+}
+
+impl Plan {
+    /// The plan for animating the device called `device` from `current` through `segments`,
+    /// `repeat` times over, with the `transition` options.
+    pub fn new(
+        device: &str,
+        current: Brightness,
+        max: Brightness,
+        segments: &[Segment],
+        repeat: Repeat,
+        transition: &TransitionOpt,
+    ) -> Plan {
+        let mut from = current;
+        let mut legs = Vec::new();
+        for segment in segments {
+            let fade = transition.segment(from, segment, max);
+            legs.push(Leg {
+                from,
+                to: segment.target,
+                frames: fade.total_frames(),
+                duration_ms: fade.effective_duration().as_secs_f64() * 1000.0,
+                writes: fade
+                    .schedule()
+                    .into_iter()
+                    .map(|(at, brightness)| Write {
+                        at_ms: at.as_secs_f64() * 1000.0,
+                        brightness,
+                    })
+                    .collect(),
+            });
+            from = segment.target;
+        }
+        Plan {
+            device: device.to_string(),
+            current,
+            target: from,
+            max,
+            repeat: match repeat {
+                Repeat::Times(times) => Some(times),
+                Repeat::Forever => None,
+            },
+            legs,
+        }
+    }
+
+    /// Print the plan for people to read.
+    pub fn print(&self) {
+        println!(
+            "{}: {} -> {} of {}",
+            self.device, self.current, self.target, self.max
+        );
+        for leg in &self.legs {
+            println!(
+                "  {} -> {}: {} frames over {}ms, {} writes",
+                leg.from,
+                leg.to,
+                leg.frames,
+                leg.duration_ms,
+                leg.writes.len()
+            );
+            for write in &leg.writes {
+                println!("    {:>10.1}ms {}", write.at_ms, write.brightness);
+            }
+        }
+        match self.repeat {
+            Some(1) => {}
+            Some(times) => println!("  repeated {times} times"),
+            None => println!("  repeated until interrupted"),
+        }
+    }
+}
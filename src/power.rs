@@ -0,0 +1,62 @@
+use std::path::Path;
+use std::time::Duration;
+
+use crate::uevent::Monitor;
+
+const SYS_POWER_SUPPLY_PREFIX: &str = "/sys/class/power_supply";
+
+/// How often to check the power source when we can't receive uevents, or miss one.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Whether we're running on mains power, or `None` if there's no mains power supply to tell, as on
+/// most desktops.
+pub fn on_ac() -> Option<bool> {
+    let supplies = std::fs::read_dir(SYS_POWER_SUPPLY_PREFIX).ok()?;
+    let mut mains = supplies
+        .flatten()
+        .filter(|supply| read(&supply.path().join("type")).as_deref() == Some("Mains"))
+        .map(|supply| read(&supply.path().join("online")).as_deref() == Some("1"))
+        .peekable();
+    mains.peek()?;
+    Some(mains.any(|online| online))
+}
+
+fn read(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|contents| contents.trim().to_string())
+}
+
+/// Call `changed` with whether we're on mains power whenever that changes, from a thread of its
+/// own. We check on every `power_supply` uevent, and every once in a while in case we miss one.
+pub fn watch<F>(mut changed: F)
+where
+    F: FnMut(bool) + Send + 'static,
+{
+    std::thread::spawn(move || {
+        let monitor = Monitor::open()
+            .inspect_err(|err| {
+                tracing::warn!("Failed to receive uevents, polling instead: {err:#}")
+            })
+            .ok();
+        let mut last = on_ac();
+        loop {
+            match &monitor {
+                Some(monitor) => match monitor.next(POLL_INTERVAL) {
+                    Ok(Some(uevent)) if uevent.subsystem != "power_supply" => continue,
+                    Ok(_) => {}
+                    Err(err) => {
+                        tracing::error!("{err:#}");
+                        std::thread::sleep(POLL_INTERVAL);
+                    }
+                },
+                None => std::thread::sleep(POLL_INTERVAL),
+            }
+            let current = on_ac();
+            if let Some(on_ac) = current.filter(|_| current != last) {
+                changed(on_ac);
+            }
+            last = current;
+        }
+    });
+}
@@ -0,0 +1,214 @@
+//! Python bindings, for automation scripts and Home Assistant components to run transitions
+//! without spawning `dimmer`:
+//!
+//! ```python
+//! import dimmer
+//!
+//! device = dimmer.Device("intel_backlight")
+//! dimmer.Transition("30%", duration=2.0, easing="ease-in-out").run(device)
+//! ```
+use pyo3::exceptions::{PyLookupError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::backend::Class;
+use crate::{Brightness, DimmerError, Easing};
+
+/// A screen or keyboard backlight.
+#[pyclass(name = "Device", module = "dimmer")]
+struct Device(Mutex<crate::Device>);
+
+#[pymethods]
+impl Device {
+    /// The backlight called `name`, or at the absolute path `name`, or the first backlight if
+    /// no name is given.
+    #[new]
+    #[pyo3(signature = (name=None))]
+    fn new(name: Option<&str>) -> PyResult<Device> {
+        let device = match name {
+            Some(name) if std::path::Path::new(name).is_absolute() => crate::Device::from_dir(name),
+            Some(name) => devices()?
+                .into_iter()
+                .find(|device| device.name() == name)
+                .ok_or_else(|| error(DimmerError::DeviceNotFound(name.to_string()).into()))?,
+            None => crate::Device::first().map_err(error)?,
+        };
+        Ok(Device(Mutex::new(device)))
+    }
+
+    /// All screen and keyboard backlights.
+    #[staticmethod]
+    fn list() -> PyResult<Vec<Device>> {
+        Ok(devices()?
+            .into_iter()
+            .map(|device| Device(Mutex::new(device)))
+            .collect())
+    }
+
+    #[getter]
+    fn name(&self) -> String {
+        self.0.lock().unwrap().name().to_string()
+    }
+
+    /// The current brightness, which sets the brightness right away when assigned to.
+    #[getter]
+    fn brightness(&self) -> PyResult<u64> {
+        Ok(self.0.lock().unwrap().current().map_err(error)?.0)
+    }
+
+    #[setter]
+    fn set_brightness(&self, brightness: u64) -> PyResult<()> {
+        self.0
+            .lock()
+            .unwrap()
+            .set(Brightness(brightness))
+            .map_err(error)
+    }
+
+    /// The maximum brightness.
+    #[getter]
+    fn max(&self) -> PyResult<u64> {
+        Ok(self.0.lock().unwrap().max().map_err(error)?.0)
+    }
+
+    /// The current brightness as a percentage of the maximum.
+    #[getter]
+    fn percent(&self) -> PyResult<f64> {
+        Ok(self.0.lock().unwrap().status().map_err(error)?.percent)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Device({:?})", self.name())
+    }
+}
+
+/// The target of a transition: a raw brightness, or anything `dimmer set` takes, like "30%".
+#[derive(Debug, Clone, FromPyObject)]
+enum Target {
+    Raw(u64),
+    Parsed(String),
+}
+
+/// A smooth transition to a target brightness, which can run on any device.
+#[pyclass(name = "Transition", module = "dimmer")]
+#[derive(Debug, Clone)]
+struct Transition {
+    target: Target,
+    duration: Duration,
+    start: Option<u64>,
+    easing: Easing,
+    perceptual: bool,
+    dither: bool,
+    framerate: Option<u64>,
+}
+
+#[pymethods]
+impl Transition {
+    /// A transition to `target` taking `duration` seconds, from the brightness the device is at
+    /// unless a `start` is given. Without a `framerate`, the brightness is updated once for every
+    /// level the transition crosses.
+    #[new]
+    #[pyo3(signature = (
+        target,
+        duration=5.0,
+        start=None,
+        easing="linear",
+        perceptual=false,
+        dither=false,
+        framerate=None,
+    ))]
+    fn new(
+        target: Target,
+        duration: f64,
+        start: Option<u64>,
+        easing: &str,
+        perceptual: bool,
+        dither: bool,
+        framerate: Option<u64>,
+    ) -> PyResult<Transition> {
+        let duration = Duration::try_from_secs_f64(duration)
+            .map_err(|err| PyValueError::new_err(format!("Invalid duration: {err}")))?;
+        let easing = easing
+            .parse()
+            .map_err(|err: DimmerError| PyValueError::new_err(err.to_string()))?;
+        Ok(Transition {
+            target,
+            duration,
+            start,
+            easing,
+            perceptual,
+            dither,
+            framerate,
+        })
+    }
+
+    /// Run the transition on `device`, returning once it's done. Other Python threads keep
+    /// running in the meantime.
+    fn run(&self, py: Python<'_>, device: &Device) -> PyResult<()> {
+        py.detach(|| {
+            let mut device = device.0.lock().unwrap();
+            let current = device.current()?;
+            let maximum = device.max()?;
+            let target = match &self.target {
+                Target::Raw(target) => Brightness(*target),
+                Target::Parsed(target) => {
+                    Brightness::parse_with_percentage(target, current, maximum)?
+                }
+            };
+            let mut transition =
+                crate::Transition::new(self.start.map_or(current, Brightness), target)
+                    .duration(self.duration)
+                    .easing(self.easing);
+            transition = match self.framerate {
+                Some(framerate) => transition.framerate(framerate),
+                None => transition.auto_framerate(),
+            };
+            if self.perceptual {
+                transition = transition.perceptual(maximum);
+            }
+            if self.dither {
+                transition = transition.dither();
+            }
+            transition.run(&mut device)
+        })
+        .map_err(error)
+    }
+
+    fn __repr__(&self) -> String {
+        let target = match &self.target {
+            Target::Raw(target) => target.to_string(),
+            Target::Parsed(target) => format!("{target:?}"),
+        };
+        format!(
+            "Transition({target}, duration={})",
+            self.duration.as_secs_f64()
+        )
+    }
+}
+
+/// All screen and keyboard backlights.
+fn devices() -> PyResult<Vec<crate::Device>> {
+    let mut devices = crate::Device::discover(Class::Backlight).map_err(error)?;
+    devices.extend(crate::Device::discover(Class::Keyboard).map_err(error)?);
+    Ok(devices)
+}
+
+/// `err` as a Python exception.
+fn error(err: anyhow::Error) -> PyErr {
+    let message = format!("{err:#}");
+    match err.downcast_ref::<DimmerError>() {
+        Some(DimmerError::DeviceNotFound(_)) => PyLookupError::new_err(message),
+        Some(DimmerError::InvalidPercentage | DimmerError::InvalidBrightness(_)) => {
+            PyValueError::new_err(message)
+        }
+        _ => PyRuntimeError::new_err(message),
+    }
+}
+
+#[pymodule]
+fn dimmer(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Device>()?;
+    module.add_class::<Transition>()?;
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use glob::Pattern;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Workarounds for the hardware of a device, applied to every transition of it. Quirks set in
+/// the configuration override the built-in ones field by field.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Quirks {
+    /// The lowest brightness the panel stays lit at, as for `--min`, for panels that switch off
+    /// below it. Only used when no minimum is given otherwise.
+    pub min: Option<String>,
+    /// Whether to fade perceptually, as for `--perceptual`, for devices whose raw scale is far
+    /// from how bright they look.
+    pub perceptual: Option<bool>,
+    /// The most writes per second the device takes, for firmware that ignores writes coming in
+    /// faster than that.
+    pub max_framerate: Option<u64>,
+}
+
+impl Quirks {
+    /// These quirks, with those set in `other` taking their place.
+    fn overridden_by(self, other: &Quirks) -> Quirks {
+        Quirks {
+            min: other.min.clone().or(self.min),
+            perceptual: other.perceptual.or(self.perceptual),
+            max_framerate: other.max_framerate.or(self.max_framerate),
+        }
+    }
+}
+
+/// The quirks we know of, by a pattern matching the names of the devices that have them.
+fn built_in() -> Vec<(&'static str, Quirks)> {
+    vec![
+        // amdgpu passes the brightness on to the panel as is, which is far from linear, and
+        // plenty of its panels switch off entirely at 0.
+        (
+            "amdgpu_bl*",
+            Quirks {
+                min: Some("1".to_string()),
+                perceptual: Some(true),
+                ..Quirks::default()
+            },
+        ),
+        (
+            "radeon_bl*",
+            Quirks {
+                perceptual: Some(true),
+                ..Quirks::default()
+            },
+        ),
+        // The ScreenPad of ASUS laptops switches off at 0, rather than going dark.
+        (
+            "asus_screenpad",
+            Quirks {
+                min: Some("1".to_string()),
+                ..Quirks::default()
+            },
+        ),
+        // Plenty of ACPI firmware drops writes that come in while it's still busy with the last.
+        (
+            "acpi_video*",
+            Quirks {
+                max_framerate: Some(20),
+                ..Quirks::default()
+            },
+        ),
+        // DDC/CI asks hosts to wait 50ms after setting a value before sending the next command.
+        (
+            "ddcci*",
+            Quirks {
+                max_framerate: Some(20),
+                ..Quirks::default()
+            },
+        ),
+    ]
+}
+
+/// The quirks of the device called `name`: those of the built-in table matching it, overridden
+/// by those `configured` for patterns matching it.
+pub fn of(name: &str, configured: &BTreeMap<String, Quirks>) -> Quirks {
+    let built_in = built_in();
+    let known = built_in
+        .iter()
+        .map(|(pattern, quirks)| (*pattern, quirks))
+        .chain(
+            configured
+                .iter()
+                .map(|(pattern, quirks)| (pattern.as_str(), quirks)),
+        );
+    known
+        .filter(|(pattern, _)| Pattern::new(pattern).is_ok_and(|pattern| pattern.matches(name)))
+        .fold(Quirks::default(), |quirks, (_, other)| {
+            quirks.overridden_by(other)
+        })
+}
@@ -0,0 +1,160 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How often we check the time.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// A time of day in the local time zone, with minute precision, as in "21:30".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TimeOfDay {
+    /// Minutes since midnight.
+    minutes: u32,
+}
+
+impl TimeOfDay {
+    pub const MIDNIGHT: TimeOfDay = TimeOfDay { minutes: 0 };
+
+    /// The time of day it is now.
+    pub fn now() -> Result<TimeOfDay> {
+        // SAFETY: a null pointer asks time for the current time only.
+        TimeOfDay::at(unsafe { libc::time(std::ptr::null_mut()) })
+    }
+
+    /// The time of day it is at `timestamp`, in seconds since the epoch.
+    pub fn at(timestamp: libc::time_t) -> Result<TimeOfDay> {
+        // SAFETY: tm is plain old data, for which all zeroes is valid.
+        let mut local: libc::tm = unsafe { std::mem::zeroed() };
+        // SAFETY: both pointers point at valid values of the right type.
+        #[cfg(unix)]
+        if unsafe { libc::localtime_r(&timestamp, &mut local) }.is_null() {
+            return Err(std::io::Error::last_os_error()).context("Failed to get the local time");
+        }
+        // SAFETY: as above, with the arguments the other way around.
+        #[cfg(windows)]
+        if let error @ 1.. = unsafe { libc::localtime_s(&mut local, &timestamp) } {
+            return Err(std::io::Error::from_raw_os_error(error))
+                .context("Failed to get the local time");
+        }
+        Ok(TimeOfDay {
+            minutes: local.tm_hour as u32 * 60 + local.tm_min as u32,
+        })
+    }
+
+    /// How long it's been since `earlier`, which is taken to be a day before if it's later.
+    pub fn since(self, earlier: TimeOfDay) -> Duration {
+        let minutes = (self.minutes + MINUTES_PER_DAY - earlier.minutes) % MINUTES_PER_DAY;
+        Duration::from_secs(u64::from(minutes) * 60)
+    }
+
+    /// The time of day `duration` before this one, wrapping around midnight.
+    pub fn before(self, duration: Duration) -> TimeOfDay {
+        let minutes = (duration.as_secs() / 60 % u64::from(MINUTES_PER_DAY)) as u32;
+        TimeOfDay {
+            minutes: (self.minutes + MINUTES_PER_DAY - minutes) % MINUTES_PER_DAY,
+        }
+    }
+}
+
+impl std::fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}:{:02}", self.minutes / 60, self.minutes % 60)
+    }
+}
+
+impl FromStr for TimeOfDay {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let Some((hours, minutes)) = s.split_once(':') else {
+            bail!("Failed to parse time of day {s:?}, expected e.g. \"21:30\"");
+        };
+        let (Ok(hours), Ok(minutes)) = (hours.parse::<u32>(), minutes.parse::<u32>()) else {
+            bail!("Failed to parse time of day {s:?}, expected e.g. \"21:30\"");
+        };
+        if hours >= 24 || minutes >= 60 {
+            bail!("Time of day {s:?} is out of range");
+        }
+        Ok(TimeOfDay {
+            minutes: hours * 60 + minutes,
+        })
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// The entry of `levels` in effect at `now`: the one at the latest time before it, or the last one
+/// of the day before if there's none today yet.
+pub fn current<T>(levels: &BTreeMap<TimeOfDay, T>, now: TimeOfDay) -> Option<(TimeOfDay, &T)> {
+    levels
+        .range(..=now)
+        .next_back()
+        .or_else(|| levels.iter().next_back())
+        .map(|(time, level)| (*time, level))
+}
+
+/// Call `tick` with the time of day right away, and every once in a while after that, from a
+/// thread of its own.
+pub fn watch<F>(mut tick: F)
+where
+    F: FnMut(TimeOfDay) + Send + 'static,
+{
+    std::thread::spawn(move || loop {
+        match TimeOfDay::now() {
+            Ok(now) => tick(now),
+            Err(err) => tracing::error!("{err:#}"),
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hours: u32, minutes: u32) -> TimeOfDay {
+        TimeOfDay {
+            minutes: hours * 60 + minutes,
+        }
+    }
+
+    #[test]
+    fn parses_time_of_day() {
+        assert_eq!("00:00".parse::<TimeOfDay>().unwrap(), at(0, 0));
+        assert_eq!("21:30".parse::<TimeOfDay>().unwrap(), at(21, 30));
+        assert_eq!("7:05".parse::<TimeOfDay>().unwrap(), at(7, 5));
+        assert_eq!("23:59".parse::<TimeOfDay>().unwrap(), at(23, 59));
+    }
+
+    #[test]
+    fn rejects_invalid_time_of_day() {
+        for input in [
+            "", "21", "21:", ":30", "21:30:00", "21.30", "ab:cd", "-1:30", "21:-5", "24:00",
+            "12:60", " 21:30",
+        ] {
+            assert!(input.parse::<TimeOfDay>().is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn follows_the_latest_level() {
+        let levels = BTreeMap::from([(at(8, 0), "70%"), (at(21, 0), "30%")]);
+        assert_eq!(current(&levels, at(12, 0)), Some((at(8, 0), &"70%")));
+        assert_eq!(current(&levels, at(21, 0)), Some((at(21, 0), &"30%")));
+        // Before the first level of the day, the last one of the day before still applies.
+        assert_eq!(current(&levels, at(3, 0)), Some((at(21, 0), &"30%")));
+        assert_eq!(current(&BTreeMap::<TimeOfDay, &str>::new(), at(3, 0)), None);
+        assert_eq!(at(3, 0).since(at(21, 0)), Duration::from_secs(6 * 60 * 60));
+    }
+}
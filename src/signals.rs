@@ -0,0 +1,170 @@
+use dimmer::Control;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// Steers the running transitions from the signals we catch.
+pub static CONTROL: Control = Control::new();
+
+/// The signal that interrupted the transitions, if any.
+static INTERRUPTED_BY: AtomicI32 = AtomicI32::new(0);
+
+/// The signal sent to stop us from transitioning, leaving the brightness where it is, e.g. by a
+/// new instance taking over.
+#[cfg(target_os = "linux")]
+fn cancel_signal() -> libc::c_int {
+    libc::SIGRTMIN()
+}
+
+/// The signal sent to stop us from transitioning, jumping back to where we started.
+#[cfg(target_os = "linux")]
+fn cancel_and_restore_signal() -> libc::c_int {
+    libc::SIGRTMIN() + 1
+}
+
+/// Without realtime signals, we borrow the signals for exceeding resource limits instead, as we
+/// set none that would send them.
+#[cfg(all(unix, not(target_os = "linux")))]
+fn cancel_signal() -> libc::c_int {
+    libc::SIGXCPU
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn cancel_and_restore_signal() -> libc::c_int {
+    libc::SIGXFSZ
+}
+
+/// Ask the instance with `pid` to stop transitioning, jumping back to where it started if
+/// `restore` is set.
+#[cfg(unix)]
+pub fn cancel(pid: u32, restore: bool) {
+    let signal = if restore {
+        cancel_and_restore_signal()
+    } else {
+        cancel_signal()
+    };
+    // SAFETY: kill has no memory safety requirements. The instance may have exited already, which
+    // is fine.
+    unsafe {
+        libc::kill(pid as libc::pid_t, signal);
+    }
+}
+
+/// Windows has no signals to send another instance, so it finishes its transition first.
+#[cfg(windows)]
+pub fn cancel(pid: u32, _restore: bool) {
+    tracing::info!(
+        pid,
+        "Waiting for the other instance to finish its transition"
+    );
+}
+
+/// Call `reload` from a thread of its own whenever we receive SIGHUP. This has to be called
+/// before spawning any other threads, as they'd otherwise still get SIGHUP delivered.
+#[cfg(unix)]
+pub fn on_hangup<F>(reload: F)
+where
+    F: Fn() + Send + 'static,
+{
+    // SAFETY: set is initialised by sigemptyset before use, and only read after.
+    let set = unsafe {
+        let mut set = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGHUP);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+        set
+    };
+    std::thread::spawn(move || loop {
+        let mut signal = 0;
+        // SAFETY: set is a valid signal set and signal a valid place to store the signal.
+        if unsafe { libc::sigwait(&set, &mut signal) } == 0 {
+            reload();
+        }
+    });
+}
+
+/// Windows has no SIGHUP, so the configuration is only read at startup.
+#[cfg(windows)]
+pub fn on_hangup<F>(_reload: F)
+where
+    F: Fn() + Send + 'static,
+{
+}
+
+extern "C" fn handle(signal: libc::c_int) {
+    match signal {
+        #[cfg(unix)]
+        libc::SIGUSR1 => CONTROL.toggle_pause(),
+        #[cfg(unix)]
+        libc::SIGUSR2 => CONTROL.toggle_reverse(),
+        _ => {
+            INTERRUPTED_BY.store(signal, Ordering::Relaxed);
+            CONTROL.interrupt();
+        }
+    }
+}
+
+/// Catches the signals that steer transitions until dropped: SIGINT and SIGTERM interrupt them,
+/// SIGUSR1 pauses and resumes them and SIGUSR2 reverses them. Other instances cancel them with
+/// SIGRTMIN, or with SIGRTMIN+1 to restore the brightness they started from (SIGXCPU and SIGXFSZ
+/// where there are no realtime signals). Windows only has SIGINT and SIGTERM.
+pub struct Signals;
+
+impl Signals {
+    pub fn catch() -> Signals {
+        set_handler(handle as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        Signals
+    }
+
+    /// The signal we were interrupted by, if any.
+    pub fn interrupted_by(&self) -> Option<i32> {
+        Some(INTERRUPTED_BY.load(Ordering::Relaxed)).filter(|signal| *signal != 0)
+    }
+
+    /// Whether another instance cancelled our transitions.
+    #[cfg(unix)]
+    pub fn cancelled(&self) -> bool {
+        self.interrupted_by() == Some(cancel_signal()) || self.restore_requested()
+    }
+
+    #[cfg(windows)]
+    pub fn cancelled(&self) -> bool {
+        false
+    }
+
+    /// Whether another instance cancelled our transitions, asking to restore the brightness they
+    /// started from.
+    #[cfg(unix)]
+    pub fn restore_requested(&self) -> bool {
+        self.interrupted_by() == Some(cancel_and_restore_signal())
+    }
+
+    #[cfg(windows)]
+    pub fn restore_requested(&self) -> bool {
+        false
+    }
+}
+
+impl Drop for Signals {
+    fn drop(&mut self) {
+        set_handler(libc::SIG_DFL);
+    }
+}
+
+fn set_handler(handler: libc::sighandler_t) {
+    #[cfg(unix)]
+    let signals = [
+        libc::SIGINT,
+        libc::SIGTERM,
+        libc::SIGUSR1,
+        libc::SIGUSR2,
+        cancel_signal(),
+        cancel_and_restore_signal(),
+    ];
+    #[cfg(windows)]
+    let signals = [libc::SIGINT, libc::SIGTERM];
+    for signal in signals {
+        // SAFETY: the handler only flips atomics, which is async-signal-safe.
+        unsafe {
+            libc::signal(signal, handler);
+        }
+    }
+}
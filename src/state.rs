@@ -0,0 +1,363 @@
+use anyhow::{bail, Context, Result};
+use dimmer::{Brightness, Device};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The version of the state file format we write.
+const VERSION: u32 = 1;
+
+/// Where the state file is kept in `$XDG_STATE_HOME/dimmer`.
+const FILE_NAME: &str = "state.toml";
+
+/// Where the state file used to be kept in `$XDG_CONFIG_HOME/dimmer`, before we realised it's
+/// not configuration.
+#[cfg(unix)]
+const LEGACY_FILE_NAME: &str = "stored_brightness";
+
+/// The brightness we saved for each device, to restore later.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct State {
+    version: u32,
+    #[serde(default)]
+    devices: BTreeMap<String, Saved>,
+    /// The brightness saved in named slots, next to the default one above.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    slots: BTreeMap<String, BTreeMap<String, Saved>>,
+    /// The brightness from a state file of before we kept track of devices, which held nothing
+    /// but a single number.
+    #[serde(skip)]
+    unnamed: Option<Brightness>,
+    /// Whether the last `dimmer toggle` saved the brightness, so the next one restores it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    toggled: bool,
+}
+
+/// The brightness saved for a device.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Saved {
+    pub brightness: Brightness,
+    pub max: Brightness,
+    pub percent: f64,
+    /// When the brightness was saved, in seconds since the Unix epoch.
+    pub saved_at: u64,
+}
+
+impl State {
+    /// Read the state file at `path`. A missing state file is an empty one.
+    pub fn load(path: &Path) -> Result<State> {
+        let state = match std::fs::read_to_string(path) {
+            Ok(state) => state,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(State::default()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+        if let Ok(brightness) = state.trim().parse() {
+            return Ok(State {
+                unnamed: Some(brightness),
+                ..State::default()
+            });
+        }
+
+        let state: State = toml::from_str(&state)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        if state.version > VERSION {
+            bail!(
+                "{} is of a newer version ({}) than we understand ({VERSION})",
+                path.display(),
+                state.version
+            );
+        }
+        Ok(state)
+    }
+
+    /// Update the state file at `path` with `update`. We hold a lock while we do, so concurrent
+    /// updates can't undo each other.
+    pub fn update<F>(path: &Path, update: F) -> Result<()>
+    where
+        F: FnOnce(&mut State) -> Result<()>,
+    {
+        let lock_path = sibling(path, ".lock");
+        let lock = File::create(&lock_path)
+            .with_context(|| format!("Failed to open {}", lock_path.display()))?;
+        lock.lock()
+            .with_context(|| format!("Failed to lock {}", lock_path.display()))?;
+
+        let mut state = State::load(path)?;
+        update(&mut state)?;
+        state.store(path)
+    }
+
+    /// Write the state file to `path`. We write to a temporary file first and move that into
+    /// place, so a crash halfway through can't leave a truncated state file behind.
+    fn store(&self, path: &Path) -> Result<()> {
+        let state = State {
+            version: VERSION,
+            devices: self.devices.clone(),
+            slots: self.slots.clone(),
+            unnamed: None,
+            toggled: self.toggled,
+        };
+        let state = toml::to_string(&state)?;
+
+        let temporary = sibling(path, &format!(".{}.tmp", std::process::id()));
+        let mut file = File::create(&temporary)
+            .with_context(|| format!("Failed to create {}", temporary.display()))?;
+        file.write_all(state.as_bytes())
+            .and_then(|_| file.sync_all())
+            .with_context(|| format!("Failed to write {}", temporary.display()))?;
+        std::fs::rename(&temporary, path)
+            .with_context(|| format!("Failed to move {} into place", temporary.display()))
+    }
+
+    /// Save the current brightness of `device`, in the named `slot` if given.
+    pub fn save(&mut self, device: &Device, slot: Option<&str>) -> Result<()> {
+        let brightness = device.current()?;
+        let max = device.max()?;
+        let saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or_default();
+        let devices = match slot {
+            Some(slot) => self.slots.entry(slot.to_string()).or_default(),
+            None => &mut self.devices,
+        };
+        devices.insert(
+            device.name().to_string(),
+            Saved {
+                brightness,
+                max,
+                percent: brightness.percentage_of(max),
+                saved_at,
+            },
+        );
+        Ok(())
+    }
+
+    /// Whether the last `dimmer toggle` saved the brightness rather than restoring it.
+    pub fn is_toggled(&self) -> bool {
+        self.toggled
+    }
+
+    pub fn set_toggled(&mut self, toggled: bool) {
+        self.toggled = toggled;
+    }
+
+    /// The brightness saved for the device called `name` in the named `slot` if given, for a
+    /// device with brightness `max`. If the maximum changed since, e.g. because it's a different
+    /// device by the same name, we restore the same percentage instead.
+    ///
+    /// Returns `None` if the brightness was saved longer than `max_age` ago, as restoring it
+    /// probably makes little sense anymore.
+    pub fn restore(
+        &self,
+        name: &str,
+        max: Brightness,
+        slot: Option<&str>,
+        max_age: Option<Duration>,
+    ) -> Result<Option<Brightness>> {
+        match self.saved(name, slot) {
+            (Some(saved), _) if max_age.is_some_and(|max_age| saved.age() > max_age) => Ok(None),
+            (Some(saved), _) if saved.max == max => Ok(Some(saved.brightness)),
+            (Some(saved), _) => Ok(Some(Brightness::from_percentage(saved.percent, max))),
+            // We don't know how old an unnamed brightness is, so we'll have to trust it.
+            (None, Some(brightness)) => Ok(Some(brightness)),
+            (None, None) => match slot {
+                Some(slot) => bail!("No brightness was saved for {name} in slot {slot}"),
+                None => bail!("No brightness was saved for {name}"),
+            },
+        }
+    }
+
+    /// Whether a brightness was saved for the device called `name` in the named `slot` if given,
+    /// for `restore` to restore.
+    pub fn is_saved(&self, name: &str, slot: Option<&str>) -> bool {
+        !matches!(self.saved(name, slot), (None, None))
+    }
+
+    /// The brightness saved for the device called `name` in the named `slot` if given, and the
+    /// unnamed brightness from an old state file to fall back to.
+    fn saved(&self, name: &str, slot: Option<&str>) -> (Option<&Saved>, Option<Brightness>) {
+        match slot {
+            Some(slot) => {
+                let saved = self.slots.get(slot).and_then(|devices| devices.get(name));
+                (saved, None)
+            }
+            None => (self.devices.get(name), self.unnamed),
+        }
+    }
+}
+
+impl Saved {
+    /// How long ago the brightness was saved.
+    fn age(&self) -> Duration {
+        let saved_at = UNIX_EPOCH + Duration::from_secs(self.saved_at);
+        SystemTime::now()
+            .duration_since(saved_at)
+            .unwrap_or_default()
+    }
+}
+
+/// Where the state file is kept by default, in `$XDG_STATE_HOME/dimmer`. A state file left at
+/// its old location in the config directory is moved here first.
+#[cfg(unix)]
+pub fn default_path() -> Result<PathBuf> {
+    let dirs = xdg::BaseDirectories::with_prefix("dimmer")
+        .context("Failed to setup XDG base directories")?;
+    let path = dirs
+        .place_state_file(FILE_NAME)
+        .context("Failed to create the XDG state directory")?;
+    if let Some(legacy) = dirs.find_config_file(LEGACY_FILE_NAME) {
+        if !path.exists() {
+            migrate(&legacy, &path)?;
+        }
+    }
+    Ok(path)
+}
+
+/// Where the state file is kept by default, in `%LOCALAPPDATA%\dimmer`.
+#[cfg(windows)]
+pub fn default_path() -> Result<PathBuf> {
+    let local_app_data =
+        std::env::var_os("LOCALAPPDATA").context("Failed to find %LOCALAPPDATA%")?;
+    let dir = PathBuf::from(local_app_data).join("dimmer");
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    Ok(dir.join(FILE_NAME))
+}
+
+/// Move the state file at `from` to `to`, copying it if it's on another file system.
+#[cfg(unix)]
+fn migrate(from: &Path, to: &Path) -> Result<()> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    std::fs::copy(from, to)
+        .and_then(|_| std::fs::remove_file(from))
+        .with_context(|| format!("Failed to move {} to {}", from.display(), to.display()))
+}
+
+/// The path of `path` with `suffix` appended to its file name.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A state file holding `contents` in the temporary directory, unique to the test `name`.
+    fn state_file(name: &str, contents: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("dimmer-test-{}-{name}.toml", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn loads_a_bare_brightness() {
+        let path = state_file("bare", "300\n");
+        let state = State::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let restored = state
+            .restore("intel", Brightness(1000), None, None)
+            .unwrap();
+        assert_eq!(restored, Some(Brightness(300)));
+        assert!(state
+            .restore("intel", Brightness(1000), Some("movie"), None)
+            .is_err());
+        assert!(state.is_saved("acpi", None));
+        assert!(!state.is_saved("acpi", Some("movie")));
+    }
+
+    #[test]
+    fn loads_saved_devices() {
+        let path = state_file(
+            "devices",
+            "version = 1\n\
+             [devices.intel]\n\
+             brightness = 300\n\
+             max = 1000\n\
+             percent = 30.0\n\
+             saved-at = 0\n\
+             [slots.movie.intel]\n\
+             brightness = 100\n\
+             max = 1000\n\
+             percent = 10.0\n\
+             saved-at = 0\n",
+        );
+        let state = State::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let restore = |max, slot, max_age| state.restore("intel", Brightness(max), slot, max_age);
+        assert_eq!(restore(1000, None, None).unwrap(), Some(Brightness(300)));
+        // The maximum changed, so we go by the percentage.
+        assert_eq!(restore(2000, None, None).unwrap(), Some(Brightness(600)));
+        assert_eq!(
+            restore(1000, Some("movie"), None).unwrap(),
+            Some(Brightness(100))
+        );
+        assert_eq!(
+            restore(1000, None, Some(Duration::from_secs(60))).unwrap(),
+            None
+        );
+        assert!(restore(1000, Some("reading"), None).is_err());
+        assert!(state.restore("acpi", Brightness(1000), None, None).is_err());
+        assert!(state.is_saved("intel", None));
+        assert!(state.is_saved("intel", Some("movie")));
+        assert!(!state.is_saved("intel", Some("reading")));
+        assert!(!state.is_saved("acpi", None));
+    }
+
+    #[test]
+    fn loads_a_missing_state_file_as_empty() {
+        let path =
+            std::env::temp_dir().join(format!("dimmer-test-{}-missing.toml", std::process::id()));
+        let state = State::load(&path).unwrap();
+        assert!(state
+            .restore("intel", Brightness(1000), None, None)
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_state_files() {
+        for (name, contents) in [
+            ("newer", "version = 2\n"),
+            ("unversioned", "[devices.intel]\nbrightness = 300\n"),
+            (
+                "incomplete",
+                "version = 1\n[devices.intel]\nbrightness = 300\n",
+            ),
+            ("negative", "-300\n"),
+            ("garbage", "brightness: 300\n"),
+        ] {
+            let path = state_file(name, contents);
+            let state = State::load(&path);
+            std::fs::remove_file(&path).unwrap();
+            assert!(state.is_err(), "{name}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn migrates_the_legacy_state_file() {
+        let legacy = state_file("legacy", "300\n");
+        let path = sibling(&legacy, ".migrated");
+        migrate(&legacy, &path).unwrap();
+        assert!(!legacy.exists());
+        let state = State::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            state
+                .restore("intel", Brightness(1000), None, None)
+                .unwrap(),
+            Some(Brightness(300))
+        );
+    }
+}
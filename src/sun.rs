@@ -0,0 +1,73 @@
+use anyhow::Result;
+
+use crate::schedule::TimeOfDay;
+
+/// The Julian date of the Unix epoch.
+const UNIX_EPOCH: f64 = 2_440_587.5;
+
+/// The Julian date of noon on January 1st, 2000.
+const J2000: f64 = 2_451_545.0;
+
+const SECONDS_PER_DAY: f64 = 24.0 * 60.0 * 60.0;
+
+/// The tilt of the Earth's axis, in degrees.
+const OBLIQUITY: f64 = 23.4397;
+
+/// How far below the horizon the center of the sun is at sunrise and sunset, in degrees, for
+/// refraction and the size of the sun's disc.
+const HORIZON: f64 = -0.833;
+
+/// Whether and when the sun rises and sets today.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Daylight {
+    Cycle {
+        rise: TimeOfDay,
+        set: TimeOfDay,
+    },
+    /// The sun stays up all day, as in summer near the poles.
+    Day,
+    /// The sun stays down all day, as in winter near the poles.
+    Night,
+}
+
+/// When the sun rises and sets today at `latitude` and `longitude`, in degrees north and east,
+/// by the sunrise equation.
+pub fn daylight(latitude: f64, longitude: f64) -> Result<Daylight> {
+    // SAFETY: a null pointer asks time for the current time only.
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    let days = (now as f64 / SECONDS_PER_DAY + UNIX_EPOCH - J2000 + 0.0008).ceil();
+
+    let noon = days - longitude / 360.0;
+    let anomaly = (357.5291 + 0.985_600_28 * noon).rem_euclid(360.0);
+    let center = 1.9148 * sin(anomaly) + 0.02 * sin(2.0 * anomaly) + 0.0003 * sin(3.0 * anomaly);
+    let ecliptic_longitude = (anomaly + center + 180.0 + 102.9372).rem_euclid(360.0);
+    let transit = J2000 + noon + 0.0053 * sin(anomaly) - 0.0069 * sin(2.0 * ecliptic_longitude);
+    let declination = (sin(ecliptic_longitude) * sin(OBLIQUITY)).asin();
+
+    let hour_angle =
+        (sin(HORIZON) - sin(latitude) * declination.sin()) / (cos(latitude) * declination.cos());
+    if hour_angle > 1.0 {
+        return Ok(Daylight::Night);
+    }
+    if hour_angle < -1.0 {
+        return Ok(Daylight::Day);
+    }
+    let half_day = hour_angle.acos().to_degrees() / 360.0;
+    Ok(Daylight::Cycle {
+        rise: time_of_day(transit - half_day)?,
+        set: time_of_day(transit + half_day)?,
+    })
+}
+
+/// The local time of day at the Julian date `date`.
+fn time_of_day(date: f64) -> Result<TimeOfDay> {
+    TimeOfDay::at(((date - UNIX_EPOCH) * SECONDS_PER_DAY).round() as libc::time_t)
+}
+
+fn sin(degrees: f64) -> f64 {
+    degrees.to_radians().sin()
+}
+
+fn cos(degrees: f64) -> f64 {
+    degrees.to_radians().cos()
+}
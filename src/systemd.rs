@@ -0,0 +1,57 @@
+#[cfg(unix)]
+use std::os::fd::FromRawFd;
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+#[cfg(unix)]
+use std::os::unix::net::{SocketAddr, UnixDatagram, UnixListener};
+
+/// The first file descriptor systemd passes with socket activation, see sd_listen_fds(3).
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// The listening socket systemd passed us when socket activated, if any.
+#[cfg(unix)]
+pub fn listener() -> Option<UnixListener> {
+    let pid = std::env::var("LISTEN_PID").ok()?.parse::<u32>().ok()?;
+    let fds = std::env::var("LISTEN_FDS").ok()?.parse::<i32>().ok()?;
+    if pid != std::process::id() || fds < 1 {
+        return None;
+    }
+    // Don't pass the sockets on to anything we start.
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+    // SAFETY: systemd passed us this file descriptor to own, and we only take it once, as we've
+    // just removed the variables telling us about it.
+    Some(unsafe { UnixListener::from_raw_fd(LISTEN_FDS_START) })
+}
+
+/// There's no systemd to activate us on Windows.
+#[cfg(windows)]
+pub fn listener() -> Option<uds_windows::UnixListener> {
+    None
+}
+
+/// Tell systemd we're ready to take requests, if it's waiting to hear from us, see
+/// sd_notify(3).
+#[cfg(unix)]
+pub fn notify_ready() {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let address = match path.as_encoded_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => SocketAddr::from_abstract_name(name),
+        _ => SocketAddr::from_pathname(&path),
+    };
+    let result = address.and_then(|address| {
+        let socket = UnixDatagram::unbound()?;
+        socket.send_to_addr(b"READY=1", &address)
+    });
+    if let Err(err) = result {
+        tracing::warn!("Failed to notify systemd: {err}");
+    }
+}
+
+#[cfg(windows)]
+pub fn notify_ready() {}
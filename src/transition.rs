@@ -0,0 +1,484 @@
+use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::{Brightness, Curve, Device, Easing};
+
+/// How many frames a transition is made up of.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Frames {
+    /// A number of frames per second.
+    Rate(u64),
+    /// A fixed number of frames, however long the transition takes. This suits devices with only
+    /// a handful of brightness levels.
+    Steps(u64),
+    /// As many frames as there are brightness levels between the start and the target, so every
+    /// frame moves the brightness by a single level, at no more than 120 frames per second.
+    Auto,
+}
+
+/// The highest framerate `Frames::Auto` picks, for devices with a very fine resolution.
+const AUTO_MAX_FRAMERATE: u64 = 120;
+
+/// How long it may take at most to notice a transition was paused, reversed or interrupted.
+const INTERRUPT_LATENCY: Duration = Duration::from_millis(50);
+
+/// How many frames ahead we look for a dithered frame that changes the brightness, before we
+/// look again from there.
+const DITHER_LOOKAHEAD: u64 = 1024;
+
+/// Stepping through multiples of this, the fractional part covers 0 to 1 about as evenly as
+/// possible for any number of steps, which makes for good dithering thresholds.
+const GOLDEN_RATIO_CONJUGATE: f64 = 0.618_033_988_749_895;
+
+/// How a transition run with `Transition::run_with` ended.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Outcome {
+    /// The transition reached its target.
+    Finished,
+    /// The transition was reversed, and made it back to where it started.
+    Reversed,
+    /// The transition was interrupted before reaching either end.
+    Interrupted,
+}
+
+/// A frame written by `Transition::run_reporting`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frame {
+    /// The brightness written.
+    pub brightness: Brightness,
+    /// How long after it was due the frame was written, including the time the write took.
+    pub late: Duration,
+}
+
+/// Steers a running transition from another thread, or from a signal handler, as all it does is
+/// flip atomics.
+#[derive(Debug, Default)]
+pub struct Control {
+    interrupted: AtomicBool,
+    paused: AtomicBool,
+    reversed: AtomicBool,
+}
+
+impl Control {
+    pub const fn new() -> Control {
+        Control {
+            interrupted: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            reversed: AtomicBool::new(false),
+        }
+    }
+
+    /// Stop the transition where it is.
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed)
+    }
+
+    /// Pause the transition, or resume it if it's paused.
+    pub fn toggle_pause(&self) {
+        self.paused.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Head back to where the transition started, or on to its target again if it was already
+    /// reversed.
+    pub fn toggle_reverse(&self) {
+        self.reversed.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    pub fn is_reversed(&self) -> bool {
+        self.reversed.load(Ordering::Relaxed)
+    }
+}
+
+/// A smooth transition from one brightness to another.
+#[derive(Debug, Clone)]
+pub struct Transition {
+    from: Brightness,
+    to: Brightness,
+    duration: Duration,
+    frames: Frames,
+    easing: Easing,
+    perceptual: Option<(Brightness, Curve)>,
+    full_scale: Option<Brightness>,
+    dither: bool,
+    max_framerate: Option<u64>,
+}
+
+impl Transition {
+    pub fn new(from: Brightness, to: Brightness) -> Transition {
+        Transition {
+            from,
+            to,
+            duration: Duration::from_secs(5),
+            frames: Frames::Rate(60),
+            easing: Easing::default(),
+            perceptual: None,
+            full_scale: None,
+            dither: false,
+            max_framerate: None,
+        }
+    }
+
+    /// How long the transition should take.
+    pub fn duration(mut self, duration: Duration) -> Transition {
+        self.duration = duration;
+        self
+    }
+
+    /// Treat the duration as the time a transition across the full range from 0 to `max` would
+    /// take, so transitions over a shorter distance take proportionally less time.
+    pub fn full_scale(mut self, max: Brightness) -> Transition {
+        self.full_scale = Some(max);
+        self
+    }
+
+    /// How long the transition actually takes, taking `full_scale` into account.
+    pub fn effective_duration(&self) -> Duration {
+        match self.full_scale {
+            Some(max) if max.0 > 0 => {
+                let distance = self.from.0.abs_diff(self.to.0).min(max.0);
+                self.duration.mul_f64(distance as f64 / max.0 as f64)
+            }
+            _ => self.duration,
+        }
+    }
+
+    /// How many times per second the brightness will be updated.
+    pub fn framerate(mut self, framerate: u64) -> Transition {
+        self.frames = Frames::Rate(framerate);
+        self
+    }
+
+    /// How many times the brightness will be updated over the whole transition, instead of a
+    /// number of times per second.
+    pub fn steps(mut self, steps: u64) -> Transition {
+        self.frames = Frames::Steps(steps);
+        self
+    }
+
+    /// Derive the number of frames from the number of brightness levels the transition crosses,
+    /// instead of a fixed framerate.
+    pub fn auto_framerate(mut self) -> Transition {
+        self.frames = Frames::Auto;
+        self
+    }
+
+    /// Never update the brightness more than `framerate` times per second, whatever the frames
+    /// say, for firmware that ignores writes coming in faster than that.
+    pub fn max_framerate(mut self, framerate: u64) -> Transition {
+        self.max_framerate = Some(framerate);
+        self
+    }
+
+    pub fn easing(mut self, easing: Easing) -> Transition {
+        self.easing = easing;
+        self
+    }
+
+    /// Interpolate in CIE L* lightness instead of raw brightness units, with `max` as the
+    /// brightest white. Our eyes are far more sensitive to changes in the dark, so a fade that's
+    /// linear in raw units seems to crawl at first and then fall off a cliff near the bottom.
+    pub fn perceptual(mut self, max: Brightness) -> Transition {
+        self.perceptual = Some((max, Curve::Lightness));
+        self
+    }
+
+    /// Interpolate in perceived brightness as `curve` tells it, with `max` as the full brightness,
+    /// for devices whose levels don't look the way CIE L* lightness predicts.
+    pub fn perceptual_curve(mut self, max: Brightness, curve: Curve) -> Transition {
+        self.perceptual = Some((max, curve));
+        self
+    }
+
+    /// Alternate between the two brightness levels around the ideal brightness of every frame, so
+    /// that on average the brightness follows the curve even between levels. Without this, a fade
+    /// on a panel with only a handful of levels looks like a staircase.
+    ///
+    /// Dithering only helps when frames come in faster than the levels change, so the automatic
+    /// framerate always runs at its maximum when dithering.
+    pub fn dither(mut self) -> Transition {
+        self.dither = true;
+        self
+    }
+
+    /// The brightness at `progress` (0.0 to 1.0) through the transition.
+    ///
+    /// We interpolate in floating point and round to the nearest brightness for every frame,
+    /// rather than adding up integer steps, so transitions over a distance shorter than their
+    /// number of frames still move and the last frame always lands exactly on the target.
+    pub fn value_at(&self, progress: f64) -> Brightness {
+        if progress <= 0.0 {
+            return self.from;
+        }
+        if progress >= 1.0 {
+            return self.to;
+        }
+        Brightness(self.exact_at(progress).round() as u64)
+    }
+
+    /// The brightness at `progress` (0.0 to 1.0) through the transition, before rounding it to a
+    /// level the device supports.
+    fn exact_at(&self, progress: f64) -> f64 {
+        let progress = progress.clamp(0.0, 1.0);
+
+        let progress = self.easing.apply(progress);
+        let value = match &self.perceptual {
+            Some((max, curve)) if max.0 > 0 => {
+                let max = max.0 as f64;
+                let from = curve.perceived(self.from.0 as f64 / max);
+                let to = curve.perceived(self.to.0 as f64 / max);
+                curve.raw(from + (to - from) * progress) * max
+            }
+            _ => {
+                let from = self.from.0 as f64;
+                let to = self.to.0 as f64;
+                from + (to - from) * progress
+            }
+        };
+        // Easing curves like cubic-bezier() may overshoot, which could take us beyond what the
+        // device supports.
+        let low = self.from.0.min(self.to.0) as f64;
+        let high = self.from.0.max(self.to.0) as f64;
+        value.clamp(low, high)
+    }
+
+    /// The number of brightness updates in the transition. Even the shortest transition takes a
+    /// single frame, which jumps straight to the target.
+    pub fn total_frames(&self) -> u64 {
+        let frames = match self.frames {
+            Frames::Rate(framerate) => {
+                self.effective_duration().as_millis() * framerate as u128 / 1000
+            }
+            Frames::Steps(steps) => steps as u128,
+            Frames::Auto => {
+                let levels = self.from.0.abs_diff(self.to.0) as u128;
+                let limit =
+                    self.effective_duration().as_millis() * AUTO_MAX_FRAMERATE as u128 / 1000;
+                levels.min(limit)
+            }
+        };
+        let frames = match self.max_framerate {
+            Some(framerate) => {
+                frames.min(self.effective_duration().as_millis() * framerate as u128 / 1000)
+            }
+            None => frames,
+        };
+        // Frames less than a nanosecond apart couldn't be told apart.
+        let frames = frames.min(self.effective_duration().as_nanos());
+        frames.max(1) as u64
+    }
+
+    /// The time between two frames.
+    pub fn frame_period(&self) -> Duration {
+        let nanos = self.effective_duration().as_nanos() / self.total_frames() as u128;
+        Duration::from_nanos(nanos as u64)
+    }
+
+    /// Run the transition on `device`, blocking until it's done.
+    pub fn run(&self, device: &mut Device) -> Result<()> {
+        self.run_with(device, &Control::new())?;
+        Ok(())
+    }
+
+    /// Run the transition on `device` like `run`, while `control` may pause, reverse or interrupt
+    /// it.
+    ///
+    /// Frame `i` is shown from `i` frame periods into the transition, rather than sleeping a fixed
+    /// time between frames, so the time it takes to write a frame doesn't add up over the
+    /// transition. When a write takes longer than a frame period, we skip ahead to the frame that
+    /// is due instead of falling further behind.
+    ///
+    /// Frames that wouldn't change the brightness are skipped altogether, so coarse devices aren't
+    /// woken up to write the same value many times per second.
+    ///
+    /// Time stands still while the transition is paused, and runs backwards while it's reversed,
+    /// so a reversed transition takes as long to get back to where it started as it took to get
+    /// where it was.
+    pub fn run_with(&self, device: &mut Device, control: &Control) -> Result<Outcome> {
+        self.run_reporting(device, control, |_frame| {})
+    }
+
+    /// Run the transition on `device` like `run_with`, calling `report` with every frame written,
+    /// e.g. to show the progress.
+    pub fn run_reporting<F>(
+        &self,
+        device: &mut Device,
+        control: &Control,
+        mut report: F,
+    ) -> Result<Outcome>
+    where
+        F: FnMut(Frame),
+    {
+        let total_frames = self.total_frames();
+        let period = self.frame_period().max(Duration::from_nanos(1));
+        let duration = frames(period, total_frames);
+        let _span = tracing::debug_span!(
+            "transition",
+            device = device.name(),
+            from = self.from.0,
+            to = self.to.0,
+            frames = total_frames,
+        )
+        .entered();
+        let mut position = Duration::ZERO;
+        let mut tick = Instant::now();
+        let mut last = self.from;
+        loop {
+            if control.is_interrupted() {
+                tracing::debug!(at = ?position, "Interrupted");
+                return Ok(Outcome::Interrupted);
+            }
+            let now = Instant::now();
+            let elapsed = now - tick;
+            tick = now;
+            let paused = control.is_paused();
+            let reversed = control.is_reversed();
+            if !paused && reversed {
+                position = position.saturating_sub(elapsed);
+            } else if !paused {
+                position = (position + elapsed).min(duration);
+            }
+
+            let frame = ((position.as_nanos() / period.as_nanos()) as u64).min(total_frames);
+            let value = self.frame_value(frame, total_frames);
+            if value != last {
+                tracing::debug_span!("frame", frame, brightness = value.0).in_scope(|| {
+                    device.set(value)?;
+                    tracing::debug!(at = ?position, took = ?tick.elapsed(), "Wrote frame");
+                    anyhow::Ok(())
+                })?;
+                // Reversed, a frame is due when we get to its end rather than its start.
+                let due = match reversed {
+                    false => frames(period, frame),
+                    true => frames(period, frame + 1).min(duration),
+                };
+                report(Frame {
+                    brightness: value,
+                    late: position.abs_diff(due) + tick.elapsed(),
+                });
+                last = value;
+            }
+
+            let wait = match (paused, reversed) {
+                (true, _) => INTERRUPT_LATENCY,
+                (false, false) if frame >= total_frames => {
+                    tracing::debug!("Finished");
+                    return Ok(Outcome::Finished);
+                }
+                (false, true) if position.is_zero() => {
+                    tracing::debug!("Reversed back to the start");
+                    return Ok(Outcome::Reversed);
+                }
+                (false, false) => {
+                    let next = self
+                        .next_change(frame + 1, last, total_frames)
+                        .unwrap_or(total_frames);
+                    frames(period, next).saturating_sub(position)
+                }
+                // Go just past the start of the frame, into the previous one.
+                (false, true) => {
+                    position.saturating_sub(frames(period, frame)) + Duration::from_nanos(1)
+                }
+            };
+            // Writing the frame may have taken a while, which counts towards the wait.
+            std::thread::sleep(wait.saturating_sub(tick.elapsed()).min(INTERRUPT_LATENCY));
+        }
+    }
+
+    /// When each frame that changes the brightness is due, and what it writes, as `run` would
+    /// write them if nothing held it up.
+    pub fn schedule(&self) -> Vec<(Duration, Brightness)> {
+        let total_frames = self.total_frames();
+        let period = self.frame_period();
+        let mut last = self.from;
+        let mut schedule = Vec::new();
+        let mut frame = 0;
+        while let Some(next) = self.next_change(frame, last, total_frames) {
+            let value = self.frame_value(next, total_frames);
+            if value != last {
+                schedule.push((frames(period, next), value));
+                last = value;
+            }
+            frame = next + 1;
+        }
+        schedule
+    }
+
+    /// The first frame from `frame` on with a brightness different from `last`, if any.
+    ///
+    /// Without dithering, the brightness moves towards the target frame after frame, so rather
+    /// than trying every frame, we leap ahead further and further until the brightness changed
+    /// and then narrow down on the frame it did. That takes a few dozen tries even for billions of
+    /// frames. Easing curves that overshoot may leave a level and come back to it between two
+    /// leaps, which we then don't notice, but such a brief visit isn't worth a write anyway.
+    ///
+    /// Dithered frames jump between levels, so we try them one by one, but only so far ahead. If
+    /// none of those change the brightness, we return the frame after them to look from again.
+    fn next_change(&self, frame: u64, last: Brightness, total_frames: u64) -> Option<u64> {
+        if frame > total_frames {
+            return None;
+        }
+        let changes = |frame: u64| self.frame_value(frame, total_frames) != last;
+        if self.dither {
+            let end = frame.saturating_add(DITHER_LOOKAHEAD).min(total_frames);
+            return (frame..=end)
+                .find(|frame| changes(*frame))
+                .or((end < total_frames).then_some(end + 1));
+        }
+        if changes(frame) {
+            return Some(frame);
+        }
+        // The brightness is the same at `unchanged` and has changed at `changed`.
+        let (mut unchanged, mut leap) = (frame, 1u64);
+        let mut changed = loop {
+            let next = unchanged.saturating_add(leap).min(total_frames);
+            if changes(next) {
+                break next;
+            }
+            if next == total_frames {
+                return None;
+            }
+            unchanged = next;
+            leap = leap.saturating_mul(2);
+        };
+        while changed - unchanged > 1 {
+            let middle = unchanged + (changed - unchanged) / 2;
+            if changes(middle) {
+                changed = middle;
+            } else {
+                unchanged = middle;
+            }
+        }
+        Some(changed)
+    }
+
+    /// The brightness written in `frame` out of `total_frames`.
+    ///
+    /// When dithering, we round up or down depending on how far the ideal brightness is past the
+    /// level below it, compared to a threshold that jumps around between 0 and 1 from frame to
+    /// frame. Over a few frames the levels then average out to the ideal brightness. The
+    /// thresholds depend on the frame alone, so skipping frames doesn't throw the dithering off.
+    fn frame_value(&self, frame: u64, total_frames: u64) -> Brightness {
+        let progress = frame as f64 / total_frames as f64;
+        if !self.dither || frame >= total_frames {
+            return self.value_at(progress);
+        }
+        let threshold = (frame as f64 * GOLDEN_RATIO_CONJUGATE).fract();
+        Brightness((self.exact_at(progress) + threshold).floor() as u64)
+    }
+}
+
+/// How long `count` frames of `period` take. There may be more frames than fit in the `u32` a
+/// `Duration` is multiplied by.
+fn frames(period: Duration, count: u64) -> Duration {
+    let nanos = period.as_nanos() * count as u128;
+    Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+}
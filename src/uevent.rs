@@ -0,0 +1,139 @@
+#[cfg(not(target_os = "linux"))]
+use anyhow::bail;
+#[cfg(target_os = "linux")]
+use anyhow::Context;
+use anyhow::Result;
+#[cfg(target_os = "linux")]
+use std::collections::HashMap;
+#[cfg(target_os = "linux")]
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::Duration;
+
+/// The netlink multicast group the kernel sends its uevents to.
+#[cfg(target_os = "linux")]
+const KERNEL_GROUP: u32 = 1;
+
+/// A uevent the kernel sent when one of its devices was added, removed or changed.
+#[derive(Debug)]
+pub struct Uevent {
+    /// What happened to the device, as in "add" or "remove".
+    pub action: String,
+    pub subsystem: String,
+    /// The device's name in its class, as in "intel_backlight".
+    pub name: String,
+}
+
+/// Receives the kernel's uevents.
+pub struct Monitor {
+    #[cfg(target_os = "linux")]
+    socket: OwnedFd,
+}
+
+/// Only Linux sends uevents, so elsewhere we never get to receive any.
+#[cfg(not(target_os = "linux"))]
+impl Monitor {
+    pub fn open() -> Result<Monitor> {
+        bail!("Only Linux sends uevents")
+    }
+
+    pub fn next(&self, _timeout: Duration) -> Result<Option<Uevent>> {
+        Ok(None)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Monitor {
+    pub fn open() -> Result<Monitor> {
+        // SAFETY: socket has no memory safety requirements.
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+                libc::NETLINK_KOBJECT_UEVENT,
+            )
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to open uevent socket");
+        }
+        // SAFETY: fd is a socket we just opened and nothing else owns.
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // SAFETY: sockaddr_nl is plain old data, for which all zeroes is valid.
+        let mut address: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        address.nl_groups = KERNEL_GROUP;
+        // SAFETY: address is a valid sockaddr_nl of the size given.
+        let bound = unsafe {
+            libc::bind(
+                socket.as_raw_fd(),
+                &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if bound < 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to bind uevent socket");
+        }
+        Ok(Monitor { socket })
+    }
+
+    /// Wait up to `timeout` for the next uevent.
+    pub fn next(&self, timeout: Duration) -> Result<Option<Uevent>> {
+        let mut poll = libc::pollfd {
+            fd: self.socket.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+        // SAFETY: poll points at a single valid pollfd.
+        match unsafe { libc::poll(&mut poll, 1, timeout) } {
+            0 => return Ok(None),
+            ready if ready < 0 => {
+                let err = std::io::Error::last_os_error();
+                if err.kind() == std::io::ErrorKind::Interrupted {
+                    return Ok(None);
+                }
+                return Err(err).context("Failed to wait for uevents");
+            }
+            _ => {}
+        }
+
+        let mut buffer = [0u8; 8192];
+        // SAFETY: buffer is valid for writes of its length.
+        let received = unsafe {
+            libc::recv(
+                self.socket.as_raw_fd(),
+                buffer.as_mut_ptr().cast(),
+                buffer.len(),
+                0,
+            )
+        };
+        if received < 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to receive uevent");
+        }
+        Ok(parse(&buffer[..received as usize]))
+    }
+}
+
+/// Parse a uevent, which is a header of "action@devpath" followed by "KEY=value" properties, all
+/// nul-terminated. Messages from udev rather than the kernel have a different header, and are
+/// skipped.
+#[cfg(target_os = "linux")]
+fn parse(message: &[u8]) -> Option<Uevent> {
+    let mut fields = message
+        .split(|byte| *byte == 0)
+        .map(|field| String::from_utf8_lossy(field).into_owned());
+    let header = fields.next()?;
+    let (action, devpath) = header.split_once('@')?;
+    let name = devpath.rsplit('/').next()?;
+    let properties: HashMap<_, _> = fields
+        .filter_map(|field| {
+            let (key, value) = field.split_once('=')?;
+            Some((key.to_string(), value.to_string()))
+        })
+        .collect();
+    Some(Uevent {
+        action: action.to_string(),
+        subsystem: properties.get("SUBSYSTEM")?.clone(),
+        name: name.to_string(),
+    })
+}
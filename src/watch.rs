@@ -0,0 +1,124 @@
+use anyhow::Result;
+use dimmer::{Device, Status};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How long to wait before reading a device again that we failed to read.
+const RETRY_DELAY: Duration = Duration::from_millis(10);
+
+/// Call `report` with the status of each of `devices`, and again whenever its brightness changes,
+/// until `report` fails.
+///
+/// Writes to the brightness files are picked up right away through inotify, but the kernel
+/// doesn't report every change that way (e.g. by the firmware on brightness key presses), so we
+/// poll every `interval` as well. Without inotify, we only poll.
+pub fn watch<F>(devices: &[Device], interval: Duration, mut report: F) -> Result<()>
+where
+    F: FnMut(&Status) -> Result<()>,
+{
+    let paths: Vec<PathBuf> = devices.iter().flat_map(Device::watched_paths).collect();
+    let mut changes = Changes::watch(&paths)?;
+
+    let mut last: Vec<Option<Status>> = vec![None; devices.len()];
+    loop {
+        for (device, last) in devices.iter().zip(&mut last) {
+            // We may be woken up halfway through a write, so give it a moment to finish before
+            // giving up on the device.
+            let status = match device.status() {
+                Ok(status) => status,
+                Err(_) => {
+                    std::thread::sleep(RETRY_DELAY);
+                    device.status()?
+                }
+            };
+            let changed = last
+                .as_ref()
+                .is_none_or(|last| last.current != status.current || last.max != status.max);
+            if changed {
+                report(&status)?;
+                *last = Some(status);
+            }
+        }
+        changes.wait(interval)?;
+    }
+}
+
+/// Wakes us up when files change, through inotify.
+#[cfg(target_os = "linux")]
+struct Changes {
+    /// A non-blocking inotify instance.
+    inotify: std::fs::File,
+}
+
+#[cfg(target_os = "linux")]
+impl Changes {
+    /// Watch the files at `paths` for writes.
+    fn watch(paths: &[PathBuf]) -> Result<Changes> {
+        use anyhow::Context;
+        use std::ffi::CString;
+        use std::os::fd::{AsRawFd, FromRawFd};
+
+        // SAFETY: inotify_init1 has no preconditions.
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to set up inotify");
+        }
+        // SAFETY: fd is a descriptor we just opened and nothing else owns.
+        let inotify = unsafe { std::fs::File::from_raw_fd(fd) };
+        for path in paths {
+            let Ok(path) = CString::new(path.as_os_str().as_encoded_bytes()) else {
+                continue;
+            };
+            // SAFETY: the descriptor is ours and path is a valid, nul-terminated string. Files we
+            // can't watch are still polled, so failing to watch one is no reason to bail.
+            unsafe {
+                libc::inotify_add_watch(
+                    inotify.as_raw_fd(),
+                    path.as_ptr(),
+                    libc::IN_MODIFY | libc::IN_CLOSE_WRITE,
+                );
+            }
+        }
+        Ok(Changes { inotify })
+    }
+
+    /// Wait until a file changes or `timeout` passes, and discard the events.
+    fn wait(&mut self, timeout: Duration) -> Result<()> {
+        use anyhow::Context;
+        use std::io::Read;
+        use std::os::fd::AsRawFd;
+
+        let mut poll = libc::pollfd {
+            fd: self.inotify.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+        // SAFETY: poll points at a single valid pollfd.
+        if unsafe { libc::poll(&mut poll, 1, timeout) } < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::Interrupted {
+                return Err(err).context("Failed to wait for inotify");
+            }
+        }
+        let mut events = [0; 4096];
+        while matches!(self.inotify.read(&mut events), Ok(read) if read > 0) {}
+        Ok(())
+    }
+}
+
+/// Without inotify, we never hear about changes, and only wake up to poll.
+#[cfg(not(target_os = "linux"))]
+struct Changes;
+
+#[cfg(not(target_os = "linux"))]
+impl Changes {
+    fn watch(_paths: &[PathBuf]) -> Result<Changes> {
+        Ok(Changes)
+    }
+
+    fn wait(&mut self, timeout: Duration) -> Result<()> {
+        std::thread::sleep(timeout);
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+use anyhow::{bail, Context, Result};
+use std::os::fd::AsRawFd;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use wayland_client::protocol::{wl_registry, wl_seat};
+use wayland_client::{Connection, Dispatch, EventQueue, QueueHandle};
+use wayland_protocols::ext::idle_notify::v1::client::{
+    ext_idle_notification_v1::{self, ExtIdleNotificationV1},
+    ext_idle_notifier_v1::{self, ExtIdleNotifierV1},
+};
+
+use crate::daemon::Daemon;
+
+/// How often we check whether the configured timeout changed.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct State {
+    notifier: Option<ExtIdleNotifierV1>,
+    seat: Option<wl_seat::WlSeat>,
+    /// Whether the compositor told us the session went idle or active again since we last looked.
+    idle: Option<bool>,
+}
+
+/// Have the compositor tell the daemon when the session goes idle for the configured timeout and
+/// when it's active again, from a thread of its own.
+pub fn watch(daemon: Arc<Mutex<Daemon>>) -> Result<()> {
+    let conn = Connection::connect_to_env().context("Failed to connect to Wayland compositor")?;
+    let mut queue = conn.new_event_queue();
+    conn.display().get_registry(&queue.handle(), ());
+    let mut state = State::default();
+    queue
+        .roundtrip(&mut state)
+        .context("Failed to communicate with Wayland compositor")?;
+    let (Some(notifier), Some(seat)) = (state.notifier.clone(), state.seat.clone()) else {
+        bail!("The compositor doesn't support ext-idle-notify-v1");
+    };
+
+    std::thread::spawn(move || {
+        if let Err(err) = run(queue, state, &notifier, &seat, &daemon) {
+            tracing::warn!("Stopped watching for idleness: {err:#}");
+        }
+    });
+    Ok(())
+}
+
+fn run(
+    mut queue: EventQueue<State>,
+    mut state: State,
+    notifier: &ExtIdleNotifierV1,
+    seat: &wl_seat::WlSeat,
+    daemon: &Mutex<Daemon>,
+) -> Result<()> {
+    let mut registered: Option<(Duration, ExtIdleNotificationV1)> = None;
+    loop {
+        // Register the timeout again when it's changed by reloading the configuration.
+        let timeout = daemon.lock().unwrap().idle_timeout();
+        if registered.as_ref().map(|(timeout, _)| *timeout) != timeout {
+            if let Some((_, notification)) = registered.take() {
+                notification.destroy();
+            }
+            registered = timeout.map(|timeout| {
+                let milliseconds = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
+                let notification =
+                    notifier.get_idle_notification(milliseconds, seat, &queue.handle(), ());
+                (timeout, notification)
+            });
+        }
+
+        queue.flush().context("Failed to flush Wayland requests")?;
+        if let Some(guard) = queue.prepare_read() {
+            if readable(guard.connection_fd().as_raw_fd(), POLL_INTERVAL) {
+                guard.read().context("Failed to read Wayland events")?;
+            }
+        }
+        queue
+            .dispatch_pending(&mut state)
+            .context("Failed to dispatch Wayland events")?;
+
+        if let Some(idle) = state.idle.take() {
+            if let Err(err) = daemon.lock().unwrap().set_idle(idle) {
+                tracing::error!("Failed to handle idleness: {err:#}");
+            }
+        }
+    }
+}
+
+/// Whether `fd` becomes readable within `timeout`.
+fn readable(fd: i32, timeout: Duration) -> bool {
+    let mut poll = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let timeout = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+    // SAFETY: poll points at a single valid pollfd.
+    unsafe { libc::poll(&mut poll, 1, timeout) > 0 }
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for State {
+    fn event(
+        state: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global {
+            name, interface, ..
+        } = event
+        {
+            match &interface[..] {
+                "wl_seat" if state.seat.is_none() => {
+                    state.seat = Some(registry.bind(name, 1, qh, ()));
+                }
+                "ext_idle_notifier_v1" => {
+                    state.notifier = Some(registry.bind(name, 1, qh, ()));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_seat::WlSeat,
+        _: wl_seat::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ExtIdleNotifierV1, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &ExtIdleNotifierV1,
+        _: ext_idle_notifier_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ExtIdleNotificationV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &ExtIdleNotificationV1,
+        event: ext_idle_notification_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_idle_notification_v1::Event::Idled => state.idle = Some(true),
+            ext_idle_notification_v1::Event::Resumed => state.idle = Some(false),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use anyhow::{Context, Result};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use x11rb::connection::{Connection as _, RequestConnection as _};
+use x11rb::protocol::screensaver::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::Window;
+use x11rb::rust_connection::RustConnection;
+
+use crate::daemon::Daemon;
+
+/// How often we ask the X server how long the session has been idle.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Tell the daemon how long the X session has been idle, every second, from a thread of its own.
+/// The X server keeps track of that through the MIT-SCREEN-SAVER extension.
+pub fn watch(daemon: Arc<Mutex<Daemon>>) -> Result<()> {
+    let (conn, screen) = x11rb::connect(None).context("Failed to connect to X server")?;
+    conn.extension_information(screensaver::X11_EXTENSION_NAME)?
+        .context("The X server doesn't support the MIT-SCREEN-SAVER extension")?;
+    let root = conn.setup().roots[screen].root;
+    std::thread::spawn(move || loop {
+        std::thread::sleep(POLL_INTERVAL);
+        let result =
+            idle_for(&conn, root).and_then(|idle_for| daemon.lock().unwrap().idle(Some(idle_for)));
+        if let Err(err) = result {
+            tracing::error!("Failed to handle idleness: {err:#}");
+        }
+    });
+    Ok(())
+}
+
+/// How long it's been since the last input.
+fn idle_for(conn: &RustConnection, root: Window) -> Result<Duration> {
+    let info = conn
+        .screensaver_query_info(root)?
+        .reply()
+        .context("Failed to query the screen saver")?;
+    Ok(Duration::from_millis(info.ms_since_user_input.into()))
+}
@@ -0,0 +1,371 @@
+use anyhow::Result;
+use dimmer::{Backend, Brightness, Control, Curve, Device, Easing, Outcome, Transition};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A backend that records when each brightness was written, optionally taking a while to do so.
+#[derive(Debug, Clone, Default)]
+struct Recorder {
+    writes: Arc<Mutex<Vec<(Instant, Brightness)>>>,
+    latency: Duration,
+    /// How many more writes fail because the device is busy.
+    busy: Arc<AtomicUsize>,
+}
+
+impl Backend for Recorder {
+    fn class(&self) -> &str {
+        "recorder"
+    }
+
+    fn current(&self) -> Result<Brightness> {
+        Ok(Brightness(0))
+    }
+
+    fn max(&self) -> Result<Brightness> {
+        Ok(Brightness(1000))
+    }
+
+    fn set(&mut self, brightness: Brightness) -> Result<()> {
+        std::thread::sleep(self.latency);
+        let busy = self
+            .busy
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |busy| {
+                busy.checked_sub(1)
+            });
+        if busy.is_ok() {
+            return Err(std::io::Error::from_raw_os_error(libc::EBUSY).into());
+        }
+        self.writes
+            .lock()
+            .unwrap()
+            .push((Instant::now(), brightness));
+        Ok(())
+    }
+}
+
+/// Run a transition from 0 to 1000, returning when it started and what was written when.
+fn run(transition: Transition, recorder: Recorder) -> (Instant, Vec<(Instant, Brightness)>) {
+    let writes = recorder.writes.clone();
+    let mut device = Device::new("recorder", recorder);
+    let start = Instant::now();
+    transition.run(&mut device).unwrap();
+    let writes = writes.lock().unwrap().clone();
+    (start, writes)
+}
+
+/// Like `run`, calling `steer` with the transition's control from another thread.
+fn run_with<F>(
+    transition: Transition,
+    recorder: Recorder,
+    steer: F,
+) -> (Instant, Outcome, Vec<(Instant, Brightness)>)
+where
+    F: FnOnce(&Control) + Send,
+{
+    let writes = recorder.writes.clone();
+    let mut device = Device::new("recorder", recorder);
+    let control = Control::new();
+    let start = Instant::now();
+    let outcome = std::thread::scope(|scope| {
+        scope.spawn(|| steer(&control));
+        transition.run_with(&mut device, &control).unwrap()
+    });
+    let writes = writes.lock().unwrap().clone();
+    (start, outcome, writes)
+}
+
+fn assert_close(actual: Duration, expected: Duration) {
+    let tolerance = Duration::from_millis(40);
+    assert!(
+        actual + tolerance >= expected && actual <= expected + tolerance,
+        "expected {expected:?}, got {actual:?}"
+    );
+}
+
+#[test]
+fn framerate_sets_the_frame_period() {
+    for framerate in [10, 20, 60] {
+        let transition = Transition::new(Brightness(0), Brightness(1000))
+            .duration(Duration::from_millis(500))
+            .framerate(framerate);
+        let (start, writes) = run(transition, Recorder::default());
+
+        assert_eq!(writes.len() as u64, framerate / 2);
+        let period = Duration::from_secs(1) / framerate as u32;
+        for (frame, (at, _brightness)) in writes.iter().enumerate() {
+            assert_close(*at - start, period * (frame as u32 + 1));
+        }
+    }
+}
+
+#[test]
+fn framerate_does_not_change_the_duration() {
+    for framerate in [5, 30, 120] {
+        let transition = Transition::new(Brightness(0), Brightness(1000))
+            .duration(Duration::from_millis(400))
+            .framerate(framerate);
+        let (start, writes) = run(transition, Recorder::default());
+
+        let (end, last) = writes.last().unwrap();
+        assert_close(*end - start, Duration::from_millis(400));
+        assert_eq!(*last, Brightness(1000));
+    }
+}
+
+#[test]
+fn slow_writes_skip_frames_instead_of_stretching_the_duration() {
+    let transition = Transition::new(Brightness(0), Brightness(1000))
+        .duration(Duration::from_millis(500))
+        .framerate(100);
+    let latency = Duration::from_millis(25);
+    let recorder = Recorder {
+        latency,
+        ..Recorder::default()
+    };
+    let (start, writes) = run(transition, recorder);
+
+    // The last write may only start once the one before it is done.
+    let (end, last) = writes.last().unwrap();
+    assert!(writes.len() < 50);
+    assert_close(*end - start - latency, Duration::from_millis(500));
+    assert_eq!(*last, Brightness(1000));
+}
+
+#[test]
+fn frames_that_do_not_change_the_brightness_are_not_written() {
+    let transition = Transition::new(Brightness(0), Brightness(5))
+        .duration(Duration::from_millis(500))
+        .framerate(60);
+    let (_start, writes) = run(transition, Recorder::default());
+
+    let values: Vec<_> = writes
+        .iter()
+        .map(|(_at, brightness)| brightness.0)
+        .collect();
+    assert_eq!(values, vec![1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn busy_writes_are_tried_again() {
+    let transition = Transition::new(Brightness(0), Brightness(5))
+        .duration(Duration::from_millis(500))
+        .framerate(60);
+    let recorder = Recorder {
+        busy: Arc::new(AtomicUsize::new(3)),
+        ..Recorder::default()
+    };
+    let (_start, writes) = run(transition, recorder);
+
+    let values: Vec<_> = writes
+        .iter()
+        .map(|(_at, brightness)| brightness.0)
+        .collect();
+    assert_eq!(values, vec![1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn devices_without_retries_write_busy_devices_once() {
+    let recorder = Recorder {
+        busy: Arc::new(AtomicUsize::new(2)),
+        ..Recorder::default()
+    };
+    let busy = recorder.busy.clone();
+    let mut device = Device::new("recorder", recorder).without_retries();
+
+    assert!(device.set(Brightness(5)).is_err());
+    assert_eq!(busy.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn more_steps_than_fit_in_a_u32_do_not_overflow() {
+    let transition = Transition::new(Brightness(0), Brightness(1000))
+        .duration(Duration::from_secs(10))
+        .steps(1 << 32);
+    assert_eq!(transition.frame_period(), Duration::from_nanos(2));
+
+    let transition = transition.duration(Duration::from_secs(1));
+    assert_eq!(transition.total_frames(), 1_000_000_000);
+    assert_eq!(transition.frame_period(), Duration::from_nanos(1));
+}
+
+#[test]
+fn auto_framerate_writes_every_level_once() {
+    let transition = Transition::new(Brightness(0), Brightness(5))
+        .duration(Duration::from_millis(500))
+        .auto_framerate();
+    let (start, writes) = run(transition, Recorder::default());
+
+    let values: Vec<_> = writes
+        .iter()
+        .map(|(_at, brightness)| brightness.0)
+        .collect();
+    assert_eq!(values, vec![1, 2, 3, 4, 5]);
+    for (frame, (at, _brightness)) in writes.iter().enumerate() {
+        assert_close(*at - start, Duration::from_millis(100) * (frame as u32 + 1));
+    }
+}
+
+#[test]
+fn auto_framerate_is_limited_for_fine_devices() {
+    let transition = Transition::new(Brightness(0), Brightness(1000))
+        .duration(Duration::from_millis(500))
+        .auto_framerate();
+    assert_eq!(transition.total_frames(), 60);
+    let schedule = transition.schedule();
+    assert_eq!(schedule.len(), 60);
+    assert!(schedule
+        .iter()
+        .all(|(at, _brightness)| *at <= Duration::from_millis(500)));
+    let (_start, writes) = run(transition, Recorder::default());
+
+    // Late frames are skipped rather than written, so a busy machine may write fewer of them.
+    assert!(
+        writes.len() <= 60,
+        "expected at most 60 writes, got {}",
+        writes.len()
+    );
+    assert_eq!(
+        writes.last().map(|(_at, last)| *last),
+        Some(Brightness(1000))
+    );
+}
+
+#[test]
+fn max_framerate_limits_any_frames() {
+    let transition = Transition::new(Brightness(0), Brightness(1000))
+        .duration(Duration::from_millis(500))
+        .framerate(60)
+        .max_framerate(10);
+    assert_eq!(transition.total_frames(), 5);
+    assert_eq!(transition.frame_period(), Duration::from_millis(100));
+
+    let transition = transition.steps(2);
+    assert_eq!(transition.total_frames(), 2);
+}
+
+#[test]
+fn dithering_alternates_between_levels() {
+    let transition = Transition::new(Brightness(0), Brightness(1))
+        .duration(Duration::from_millis(500))
+        .framerate(100)
+        .dither();
+    let (_start, writes) = run(transition, Recorder::default());
+
+    let values: Vec<_> = writes
+        .iter()
+        .map(|(_at, brightness)| brightness.0)
+        .collect();
+    assert!(
+        values.len() > 10,
+        "expected the brightness to flicker, got {values:?}"
+    );
+    assert!(values.iter().all(|value| *value <= 1));
+    assert_eq!(values.last(), Some(&1));
+}
+
+#[test]
+fn pausing_holds_the_transition() {
+    let transition = Transition::new(Brightness(0), Brightness(1000))
+        .duration(Duration::from_millis(400))
+        .framerate(100);
+    let recorder = Recorder::default();
+    let written = recorder.writes.clone();
+    let count = || written.lock().unwrap().len();
+    let mut held = (false, 0, 0);
+    let (start, outcome, writes) = run_with(transition, recorder, |control| {
+        std::thread::sleep(Duration::from_millis(100));
+        control.toggle_pause();
+        // A frame that was being written as we paused may still land.
+        std::thread::sleep(Duration::from_millis(100));
+        let before = count();
+        std::thread::sleep(Duration::from_millis(200));
+        held = (control.is_paused(), before, count());
+        control.toggle_pause();
+    });
+
+    let (paused, before, after) = held;
+    assert!(paused);
+    assert_eq!(before, after, "wrote frames while paused");
+    assert!(before < writes.len());
+    assert_eq!(outcome, Outcome::Finished);
+    // Time stands still for the 300ms we were paused, but for the moments it takes to notice
+    // pausing and resuming.
+    let (end, last) = writes.last().unwrap();
+    assert!(*end - start >= Duration::from_millis(600));
+    assert_eq!(*last, Brightness(1000));
+}
+
+#[test]
+fn reversing_heads_back_to_the_start() {
+    let transition = Transition::new(Brightness(0), Brightness(1000))
+        .duration(Duration::from_millis(400))
+        .framerate(100);
+    let (start, outcome, writes) = run_with(transition, Recorder::default(), |control| {
+        std::thread::sleep(Duration::from_millis(200));
+        control.toggle_reverse();
+    });
+
+    assert_eq!(outcome, Outcome::Reversed);
+    let (end, last) = writes.last().unwrap();
+    assert_close(*end - start, Duration::from_millis(400));
+    assert_eq!(*last, Brightness(0));
+    let highest = writes.iter().map(|(_at, brightness)| brightness.0).max();
+    assert!(highest.is_some_and(|highest| (450..=550).contains(&highest)));
+}
+
+#[test]
+fn mapped_devices_write_along_their_curve() {
+    let recorder = Recorder::default();
+    let writes = recorder.writes.clone();
+    let mut device = Device::new("recorder", recorder).mapped(Curve::Exponent(2.0));
+    Transition::new(Brightness(0), Brightness(500))
+        .duration(Duration::from_millis(100))
+        .steps(2)
+        .run(&mut device)
+        .unwrap();
+
+    let written: Vec<_> = writes
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(_at, brightness)| brightness.0)
+        .collect();
+    assert_eq!(written, [63, 250]);
+}
+
+#[test]
+fn schedules_only_the_frames_that_change_the_brightness() {
+    for easing in [Easing::Linear, Easing::EaseInOut, Easing::Smoothstep] {
+        let transition = Transition::new(Brightness(0), Brightness(100))
+            .duration(Duration::from_secs(1))
+            .framerate(1000)
+            .easing(easing);
+        let total_frames = transition.total_frames();
+        let mut last = Brightness(0);
+        let mut expected = Vec::new();
+        for frame in 0..=total_frames {
+            let value = transition.value_at(frame as f64 / total_frames as f64);
+            if value != last {
+                expected.push((transition.frame_period() * frame as u32, value));
+                last = value;
+            }
+        }
+        assert_eq!(transition.schedule(), expected, "{easing:?}");
+    }
+}
+
+#[test]
+fn huge_step_counts_schedule_quickly() {
+    let transition = Transition::new(Brightness(0), Brightness(1000))
+        .duration(Duration::from_secs(10))
+        .steps(1 << 32);
+    let started = Instant::now();
+    let schedule = transition.schedule();
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(schedule.len(), 1000);
+    assert_eq!(
+        schedule.last().map(|(_at, last)| *last),
+        Some(Brightness(1000))
+    );
+}